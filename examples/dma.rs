@@ -15,7 +15,7 @@ fn main() -> Result<()> {
     let v2: Vec<i32> = dma.read(v.len())?;
     println!("read");
 
-    dma.stop();
+    dma.stop()?;
     println!("stop");
 
     println!("{:?}", v2);
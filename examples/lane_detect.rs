@@ -2,7 +2,7 @@ use imageproc::drawing;
 use image::Pixel;
 
 use xipdriver_rs::umv_lane_detector::UmvLaneDetector;
-use xipdriver_rs::v_frmbuf::{VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
 use std::{thread, time};
 use std::time::Instant;
 use anyhow::Result;
@@ -22,12 +22,12 @@ fn main() -> Result<()> {
     // v_frmbuf_read config
     vfb_r.frame_width = frame_width;
     vfb_r.frame_height = frame_height;
-    vfb_r.set_format("RGB8")?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
 
     // v_frmbuf_write config
     vfb_w.frame_width = frame_width;
     vfb_w.frame_height = frame_height;
-    vfb_w.set_format("RGB8")?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
 
     ld.configure_all()?;
 
@@ -46,7 +46,7 @@ fn main() -> Result<()> {
 
         println!("write frame: {}", i);
         let start = Instant::now();
-        vfb_r.write_frame(frame.as_ptr())?;
+        vfb_r.write_frame(&frame)?;
         let end = start.elapsed();
         println!("PS->PL Write time:{:03}ms", end.as_secs_f64() * 1000.0);
 
@@ -54,12 +54,12 @@ fn main() -> Result<()> {
 
         let start = Instant::now();
         ld.start()?;
-        let points = ld.read_data();
+        let points = ld.read_data()?;
         let end = start.elapsed();
         println!("Lane detect Read time:{:03}ms", end.as_secs_f64() * 1000.0);
 
         let start = Instant::now();
-        let mut rgb_frame = vfb_w.read_frame_as_image()?;
+        let mut rgb_frame = vfb_w.read_frame_as_image()?.into_rgb8();
         let end = start.elapsed();
         println!("PL->PS Read time:{:03}ms", end.as_secs_f64() * 1000.0);
 
@@ -69,10 +69,10 @@ fn main() -> Result<()> {
             drawing::draw_filled_circle_mut(&mut rgb_frame, (p.x as i32, p.y as i32), 3, *image::Rgb::from_slice(&color));
         }
         rgb_frame.save(format!("out{}.bmp", i))?;
-        println!("");
+        println!();
     }
 
-    vfb_r.stop();
+    vfb_r.stop()?;
     vfb_w.stop();
 
     Ok(())
@@ -4,7 +4,7 @@ use anyhow::Result;
 use std::{thread, time};
 use std::time::Instant;
 
-use xipdriver_rs::v_frmbuf::{VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
 use xipdriver_rs::v_proc_ss::VideoProcSubsystemCsc;
 use xipdriver_rs::umv_lane_detector::UmvLaneDetector;
 
@@ -27,7 +27,7 @@ fn main() -> Result<()> {
     // v_frmbuf_read config
     vfb_r0.frame_width = frame_width;
     vfb_r0.frame_height = frame_height;
-    vfb_r0.set_format("YUYV")?;
+    vfb_r0.set_format(ColorFormat::Yuyv8)?;
 
     // // v_proc_ss config
     vpss_csc.frame_width = frame_width;
@@ -37,13 +37,13 @@ fn main() -> Result<()> {
     // v_frmbuf_write config
     vfb_w0.frame_width = frame_width;
     vfb_w0.frame_height = frame_height;
-    vfb_w0.set_format("RGB8")?;
+    vfb_w0.set_format(ColorFormat::Rgb8)?;
 
     // v_frmbuf_read config
     vfb_r1.frame_width = frame_width;
     vfb_r1.frame_height = frame_height;
-    vfb_r1.set_format("RGB8")?;
-    vfb_r1.tie(&vfb_w0);
+    vfb_r1.set_format(ColorFormat::Rgb8)?;
+    vfb_r1.tie(&vfb_w0)?;
 
     ld.video_mode = 0;
     ld.configure_all()?;
@@ -51,7 +51,7 @@ fn main() -> Result<()> {
     // v_frmbuf_write config
     vfb_w1.frame_width = frame_width;
     vfb_w1.frame_height = frame_height;
-    vfb_w1.set_format("RGB8")?;
+    vfb_w1.set_format(ColorFormat::Rgb8)?;
 
     // start IP
     vfb_r0.start()?;
@@ -71,7 +71,7 @@ fn main() -> Result<()> {
     for i in 0..frames {
         println!("YUYV -> RGB: {}", i);
         let total_start = Instant::now();
-        vfb_r0.write_frame(frame.as_ptr())?;
+        vfb_r0.write_frame(&frame)?;
         let end = total_start.elapsed();
         println!("  PS->PL Write time:{:.02}ms", end.as_secs_f64() * 1000.0);
 
@@ -83,7 +83,7 @@ fn main() -> Result<()> {
 
         // println!("RGB -> Lane Detection: {}", i);
         // let start = Instant::now();
-        // vfb_r1.write_frame(rgb_frame.as_ptr())?;
+        // vfb_r1.write_frame(&rgb_frame)?;
         // let end = start.elapsed();
         // println!("  PS->PL Write time:{:.02}ms", end.as_secs_f64() * 1000.0);
 
@@ -91,14 +91,14 @@ fn main() -> Result<()> {
 
         let start = Instant::now();
         ld.start()?;
-        let points = ld.read_data();
+        let points = ld.read_data()?;
         let end = start.elapsed();
         let p_end = total_start.elapsed();
         println!("  Lane detect Read time:{:.02}ms", end.as_secs_f64() * 1000.0);
         println!("Total processing time:{:.02}ms, {:.02}FPS", p_end.as_secs_f64() * 1000.0, 1./p_end.as_secs_f64());
 
         let start = Instant::now();
-        let mut lane_image = vfb_w1.read_frame_as_image()?;
+        let mut lane_image = vfb_w1.read_frame_as_image()?.into_rgb8();
         let end = start.elapsed();
         let total_end = total_start.elapsed();
         println!("  PL->PS Read time:{:.02}ms", end.as_secs_f64() * 1000.0);
@@ -110,13 +110,13 @@ fn main() -> Result<()> {
             drawing::draw_filled_circle_mut(&mut lane_image, (p.x as i32, p.y as i32), 3, *image::Rgb::from_slice(&color));
         }
         lane_image.save(format!("lane{}.jpg", i))?;
-        println!("");
+        println!();
     }
-    vfb_r0.stop();
+    vfb_r0.stop()?;
     vfb_w0.stop();
     vpss_csc.stop();
-    ld.stop();
-    vfb_r1.stop();
+    ld.stop()?;
+    vfb_r1.stop()?;
     vfb_w1.stop();
     Ok(())
 }
@@ -127,18 +127,18 @@ fn rgb2yuyv(rgb: &[u8]) -> Vec<u8> {
     let length = rgb.len() / 3;
     let mut yuyv = vec![0; length * 2];
     for i in 0..length {
-        let r = rgb[3 * i + 0] as f64;
+        let r = rgb[3 * i] as f64;
         let g = rgb[3 * i + 1] as f64;
         let b = rgb[3 * i + 2] as f64;
         let y = 0.257 * r + 0.504 * g + 0.098 * b + 16.0;
         let u = -0.148 * r - 0.291 * g + 0.439 * b + 128.0;
         let v = 0.439 * r - 0.368 * g - 0.071 * b + 128.0;
         if i % 2 == 0 {
-            yuyv[2 * i + 0] = y.max(0.0).min(255.0) as u8;
-            yuyv[2 * i + 1] = u.max(0.0).min(255.0) as u8;
+            yuyv[2 * i] = y.clamp(0.0, 255.0) as u8;
+            yuyv[2 * i + 1] = u.clamp(0.0, 255.0) as u8;
         } else {
-            yuyv[2 * i + 0] = y.max(0.0).min(255.0) as u8;
-            yuyv[2 * i + 1] = v.max(0.0).min(255.0) as u8;
+            yuyv[2 * i] = y.clamp(0.0, 255.0) as u8;
+            yuyv[2 * i + 1] = v.clamp(0.0, 255.0) as u8;
         }
     }
     yuyv
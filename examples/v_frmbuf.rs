@@ -1,6 +1,6 @@
 use std::time::Instant;
 use std::{thread, time};
-use xipdriver_rs::v_frmbuf::{VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
 use anyhow::Result;
 
 fn main() -> Result<()> {
@@ -17,12 +17,12 @@ fn main() -> Result<()> {
     // v_frmbuf_read config
     vfb_r.frame_width = frame_width;
     vfb_r.frame_height = frame_height;
-    vfb_r.set_format("RGB8")?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
 
     // v_frmbuf_write config
     vfb_w.frame_width = frame_width;
     vfb_w.frame_height = frame_height;
-    vfb_w.set_format("RGB8")?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
 
     // start IP
     vfb_r.start()?;
@@ -40,7 +40,7 @@ fn main() -> Result<()> {
         // Write to v_frmbuf_read
 
         let start = Instant::now();
-        vfb_r.write_frame(frame.as_ptr())?;
+        vfb_r.write_frame(&frame)?;
         let end = start.elapsed();
         println!("PS->PL Write time:{:03}ms", end.as_secs_f64() * 1000.0);
         elapsed1 += end.as_secs_f64();
@@ -77,7 +77,7 @@ fn main() -> Result<()> {
 
     println!("Done!");
 
-    vfb_r.stop();
+    vfb_r.stop()?;
     vfb_w.stop();
     Ok(())
 }
@@ -1,4 +1,4 @@
-use xipdriver_rs::v_frmbuf::VideoFrameBufWrite;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufWrite};
 use xipdriver_rs::vdma::AxiVdmaMM2S;
 use std::{thread, time};
 use anyhow::Result;
@@ -22,7 +22,7 @@ fn main() -> Result<()> {
     // v_frmbuf_write config
     vfb_w.frame_width = frame_width;
     vfb_w.frame_height = frame_height;
-    vfb_w.set_format("RGB8")?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
 
     // start IP
     vfb_w.start()?;
@@ -32,9 +32,9 @@ fn main() -> Result<()> {
 
     for i in 0..10 {
         let frame: Vec<u8> = vec![0xFF / 9 * (9 - i); (frame_width * frame_height * 3) as usize];
-        vdma.write_frame(frame.as_ptr())?;
+        vdma.write_frame(&frame)?;
         let start = time::Instant::now();
-        let rgb_frame = vfb_w.read_frame_as_image()?;
+        let rgb_frame = vfb_w.read_frame_as_image()?.into_rgb8();
         let end = start.elapsed();
         println!("{}秒経過しました。", end.as_secs_f32());
         rgb_frame.save(format!("out{}.bmp", i))?;
@@ -45,7 +45,7 @@ fn main() -> Result<()> {
         thread::sleep(time::Duration::from_millis(10));
     }
 
-    vdma.reset();
+    vdma.reset()?;
     vfb_w.stop();
 
     Ok(())
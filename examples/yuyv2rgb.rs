@@ -1,4 +1,4 @@
-use xipdriver_rs::v_frmbuf::{VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
 use xipdriver_rs::v_proc_ss::VideoProcSubsystemCsc;
 use std::time::Instant;
 use anyhow::Result;
@@ -18,7 +18,7 @@ fn main() -> Result<()> {
     // v_frmbuf_read config
     vfb_r.frame_width = frame_width;
     vfb_r.frame_height = frame_height;
-    vfb_r.set_format("YUYV")?;
+    vfb_r.set_format(ColorFormat::Yuyv8)?;
 
     // v_proc_ss config
     vpss_csc.frame_width = frame_width;
@@ -28,7 +28,7 @@ fn main() -> Result<()> {
     // v_frmbuf_write config
     vfb_w.frame_width = frame_width;
     vfb_w.frame_height = frame_height;
-    vfb_w.set_format("RGB8")?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
 
     // start IP
     vpss_csc.start()?;
@@ -47,13 +47,13 @@ fn main() -> Result<()> {
 
         // Write to v_frmbuf_read
         let start = Instant::now();
-        vfb_r.write_frame(frame_yuyv.as_ptr())?;
+        vfb_r.write_frame(&frame_yuyv)?;
         let end = start.elapsed();
         println!("Write: {} msec", end.as_secs_f32() * 1000.);
 
         // Read from v_frmbuf_write
         let start = Instant::now();
-        let rgb_frame = vfb_w.read_frame_as_image()?;
+        let rgb_frame = vfb_w.read_frame_as_image()?.into_rgb8();
         let end = start.elapsed();
         println!("Read: {} msec", end.as_secs_f32() * 1000.);
 
@@ -70,14 +70,14 @@ fn main() -> Result<()> {
     }
 
     println!("Save frames");
-    for i in 0..read_frames.len() {
+    for (i, read_frame) in read_frames.iter().enumerate() {
         println!("Writing out{}.bmp...", i);
-        read_frames[i].save(format!("out{}.bmp", i))?;
+        read_frame.save(format!("out{}.bmp", i))?;
     }
 
     println!("Done!");
 
-    vfb_r.stop();
+    vfb_r.stop()?;
     vpss_csc.stop();
     vfb_w.stop();
 
@@ -90,18 +90,18 @@ fn rgb2yuyv(rgb: &[u8]) -> Vec<u8> {
     let length = rgb.len() / 3;
     let mut yuyv = vec![0; length * 2];
     for i in 0..length {
-        let r = rgb[3 * i + 0] as f64;
+        let r = rgb[3 * i] as f64;
         let g = rgb[3 * i + 1] as f64;
         let b = rgb[3 * i + 2] as f64;
         let y = 0.257 * r + 0.504 * g + 0.098 * b + 16.0;
         let u = -0.148 * r - 0.291 * g + 0.439 * b + 128.0;
         let v = 0.439 * r - 0.368 * g - 0.071 * b + 128.0;
         if i % 2 == 0 {
-            yuyv[2 * i + 0] = y.max(0.0).min(255.0) as u8;
-            yuyv[2 * i + 1] = u.max(0.0).min(255.0) as u8;
+            yuyv[2 * i] = y.clamp(0.0, 255.0) as u8;
+            yuyv[2 * i + 1] = u.clamp(0.0, 255.0) as u8;
         } else {
-            yuyv[2 * i + 0] = y.max(0.0).min(255.0) as u8;
-            yuyv[2 * i + 1] = v.max(0.0).min(255.0) as u8;
+            yuyv[2 * i] = y.clamp(0.0, 255.0) as u8;
+            yuyv[2 * i + 1] = v.clamp(0.0, 255.0) as u8;
         }
     }
     yuyv
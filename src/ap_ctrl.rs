@@ -0,0 +1,162 @@
+//! Block-level control (`ap_ctrl_hs`) of Vitis / Vivado HLS cores.
+//!
+//! Every HLS core with an `s_axilite` control port starts with the same four
+//! registers:
+//!
+//! | offset | register | bits |
+//! |--------|----------|------|
+//! | 0x00 | control | 0: ap_start, 1: ap_done (clear on read), 2: ap_idle, 3: ap_ready, 7: auto_restart |
+//! | 0x04 | GIE | 0: global interrupt enable |
+//! | 0x08 | IER | 0: ap_done, 1: ap_ready |
+//! | 0x0C | ISR | 0: ap_done, 1: ap_ready (toggle on write) |
+
+use std::time::Duration;
+
+use crate::backend::RegIo;
+use crate::error::Result;
+use crate::timeout::{wait_until, IrqDeadline};
+
+pub const AP_CTRL: usize = 0x00;
+pub const AP_GIE: usize = 0x04;
+pub const AP_IER: usize = 0x08;
+pub const AP_ISR: usize = 0x0C;
+
+pub const AP_START: u32 = 0x01;
+pub const AP_DONE: u32 = 0x02;
+pub const AP_IDLE: u32 = 0x04;
+pub const AP_READY: u32 = 0x08;
+pub const AP_AUTO_RESTART: u32 = 0x80;
+
+/// Interrupt sources in IER / ISR.
+pub const AP_INT_DONE: u32 = 0x01;
+pub const AP_INT_READY: u32 = 0x02;
+
+/// `ap_ctrl_hs` handshake, implemented by every HLS driver on top of its
+/// register window.
+pub trait ApCtrl {
+    fn ap_regs(&self) -> &dyn RegIo;
+    fn ap_regs_mut(&mut self) -> &mut dyn RegIo;
+
+    /// Raw control register. Reading it clears ap_done.
+    fn ap_ctrl(&self) -> u32 {
+        unsafe { self.ap_regs().read_mem32(AP_CTRL) }
+    }
+
+    /// ap_start is set: a start was requested and not yet accepted, or the
+    /// core is free running with auto-restart.
+    fn is_running(&self) -> bool {
+        self.ap_ctrl() & AP_START == AP_START
+    }
+    fn is_done(&self) -> bool {
+        self.ap_ctrl() & AP_DONE == AP_DONE
+    }
+    fn is_idle(&self) -> bool {
+        self.ap_ctrl() & AP_IDLE == AP_IDLE
+    }
+    fn is_ready(&self) -> bool {
+        self.ap_ctrl() & AP_READY == AP_READY
+    }
+    fn get_auto_restart_enable(&self) -> bool {
+        self.ap_ctrl() & AP_AUTO_RESTART == AP_AUTO_RESTART
+    }
+
+    /// Writing 0 to ap_start has no effect, so this never aborts a frame;
+    /// disabling auto-restart lets the core go idle after the current one.
+    fn set_auto_restart_enable(&self, en: bool) {
+        let reg = if en { AP_AUTO_RESTART } else { 0 };
+        unsafe {
+            self.ap_regs().write_mem32(AP_CTRL, reg);
+        }
+    }
+
+    /// Sets ap_start, keeping the auto-restart setting.
+    fn ap_start(&self) {
+        let auto_restart = self.ap_ctrl() & AP_AUTO_RESTART;
+        unsafe {
+            self.ap_regs().write_mem32(AP_CTRL, auto_restart | AP_START);
+        }
+    }
+
+    /// Sets ap_start together with auto-restart.
+    fn ap_start_auto_restart(&self) {
+        unsafe {
+            self.ap_regs()
+                .write_mem32(AP_CTRL, AP_AUTO_RESTART | AP_START);
+        }
+    }
+
+    /// Starts the core once and polls until it reports ap_done or returns
+    /// to idle. Fails with `TimeoutError` otherwise.
+    fn start_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.ap_start();
+        wait_until(
+            "ap_ctrl: start_and_wait",
+            timeout,
+            || {
+                let ctrl = self.ap_ctrl();
+                Ok(ctrl & AP_DONE != 0 || ctrl & (AP_IDLE | AP_START) == AP_IDLE)
+            },
+            || self.ap_ctrl(),
+        )
+    }
+
+    /// Polls ap_idle, e.g. after disabling auto-restart.
+    fn wait_idle(&self, timeout: Duration) -> Result<()> {
+        wait_until(
+            "ap_ctrl: wait_idle",
+            timeout,
+            || Ok(self.is_idle()),
+            || self.ap_ctrl(),
+        )
+    }
+
+    /// Enables the UIO interrupt and the core's interrupt sources in `mask`
+    /// (`AP_INT_DONE` / `AP_INT_READY`).
+    fn enable_interrupt(&mut self, mask: u32) -> Result<()> {
+        self.ap_regs_mut().set_irq_enable(true)?;
+        unsafe {
+            self.ap_regs().write_mem32(AP_IER, mask);
+            self.ap_regs().write_mem32(AP_GIE, 0x01);
+        }
+        Ok(())
+    }
+
+    fn disable_interrupt(&mut self) {
+        unsafe {
+            self.ap_regs().write_mem32(AP_GIE, 0x00);
+            self.ap_regs().write_mem32(AP_IER, 0x00);
+        }
+    }
+
+    fn interrupt_status(&self) -> u32 {
+        unsafe { self.ap_regs().read_mem32(AP_ISR) }
+    }
+
+    /// Acknowledges the pending sources in `mask`.
+    fn clear_interrupt(&self, mask: u32) {
+        // ISR bits toggle on write, so only write the ones that are set.
+        let pending = self.interrupt_status() & mask;
+        if pending != 0 {
+            unsafe {
+                self.ap_regs().write_mem32(AP_ISR, pending);
+            }
+        }
+    }
+
+    /// Blocks on the ap_done interrupt unless the core is already idle. Fails
+    /// with `Timeout`, reporting the control register, after `timeout`.
+    fn wait_done_interrupt(&mut self, timeout: Duration) -> Result<()> {
+        self.enable_interrupt(AP_INT_DONE)?;
+        let result = if self.is_idle() {
+            Ok(())
+        } else {
+            IrqDeadline::new("ap_ctrl: wait_done_interrupt", timeout)
+                .wait_irq(self.ap_regs_mut(), AP_CTRL)
+        };
+        self.clear_interrupt(AP_INT_DONE);
+        unsafe {
+            self.ap_regs().write_mem32(AP_GIE, 0x00);
+        }
+        result
+    }
+}
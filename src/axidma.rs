@@ -1,29 +1,205 @@
-use anyhow::{bail, ensure, Context, Result};
+use std::collections::VecDeque;
+use std::time::Duration;
 
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
-use crate::json_as_vec;
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::dma_buffer::{split_phys_addr, DmaBuffer};
+use crate::error::{bail, ensure, Result, XipError};
+use crate::hwinfo::IpDescriptor;
+use crate::timeout::{wait_until, IrqDeadline, DEFAULT_TIMEOUT};
 
 const DMACR: usize = 0x00;
 const DMASR: usize = 0x04;
+/// Address registers are followed by their MSB word when `C_ADDR_WIDTH`
+/// is above 32.
+const CURDESC: usize = 0x08;
+const TAILDESC: usize = 0x10;
 const SA: usize = 0x18;
 const LENGTH: usize = 0x28;
 const S2MM_OFFSET: usize = 0x30;
 
+const DMACR_RS: u32 = 1 << 0;
+const DMACR_RESET: u32 = 1 << 2;
+
+/// Interrupt enables in DMACR and the matching write-1-to-clear status bits
+/// in DMASR.
+pub const DMA_IRQ_IOC: u32 = 1 << 12;
+pub const DMA_IRQ_DELAY: u32 = 1 << 13;
+pub const DMA_IRQ_ERR: u32 = 1 << 14;
+pub const DMA_IRQ_ALL: u32 = DMA_IRQ_IOC | DMA_IRQ_DELAY | DMA_IRQ_ERR;
+
+/// Scatter-gather buffer descriptor, `BD_SIZE` apart in the ring. Both
+/// addresses are followed by their MSB word.
+const BD_NXTDESC: usize = 0x00;
+const BD_BUFFER_ADDRESS: usize = 0x08;
+const BD_CONTROL: usize = 0x18;
+const BD_STATUS: usize = 0x1C;
+const BD_SIZE: usize = 0x40;
+
+const BD_CONTROL_SOF: u32 = 1 << 27;
+const BD_CONTROL_EOF: u32 = 1 << 26;
+const BD_STATUS_CMPLT: u32 = 1 << 31;
+const BD_STATUS_DEC_ERR: u32 = 1 << 30;
+const BD_STATUS_SLV_ERR: u32 = 1 << 29;
+const BD_STATUS_INT_ERR: u32 = 1 << 28;
+const BD_STATUS_SOF: u32 = 1 << 27;
+const BD_STATUS_EOF: u32 = 1 << 26;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DmaChannelMode {
     MM2S,
     S2MM,
 }
 
+/// Decoded DMASR of one channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DmaStatus {
+    pub raw: u32,
+    pub halted: bool,
+    pub idle: bool,
+    /// The scatter-gather engine is included (`C_INCLUDE_SG`).
+    pub sg_included: bool,
+    pub internal_error: bool,
+    pub slave_error: bool,
+    pub decode_error: bool,
+    pub sg_internal_error: bool,
+    pub sg_slave_error: bool,
+    pub sg_decode_error: bool,
+    pub ioc_irq: bool,
+    pub delay_irq: bool,
+    pub error_irq: bool,
+    /// Descriptors left until the next IOC interrupt, and the current delay
+    /// count (scatter-gather mode).
+    pub irq_threshold: u8,
+    pub irq_delay: u8,
+}
+
+impl DmaStatus {
+    pub fn from_reg(raw: u32) -> Self {
+        let bit = |n: u32| raw & (1 << n) != 0;
+        DmaStatus {
+            raw,
+            halted: bit(0),
+            idle: bit(1),
+            sg_included: bit(3),
+            internal_error: bit(4),
+            slave_error: bit(5),
+            decode_error: bit(6),
+            sg_internal_error: bit(8),
+            sg_slave_error: bit(9),
+            sg_decode_error: bit(10),
+            ioc_irq: bit(12),
+            delay_irq: bit(13),
+            error_irq: bit(14),
+            irq_threshold: (raw >> 16) as u8,
+            irq_delay: (raw >> 24) as u8,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error().is_some()
+    }
+
+    /// The first error flag as an `XipError`. The DMA halts on any of them
+    /// and only a reset clears them.
+    pub fn error(&self) -> Option<XipError> {
+        let status = self.raw;
+        if self.internal_error {
+            Some(XipError::DmaInternal { status })
+        } else if self.slave_error {
+            Some(XipError::DmaSlave { status })
+        } else if self.decode_error {
+            Some(XipError::DmaDecode { status })
+        } else if self.sg_internal_error {
+            Some(XipError::DmaSgInternal { status })
+        } else if self.sg_slave_error {
+            Some(XipError::DmaSgSlave { status })
+        } else if self.sg_decode_error {
+            Some(XipError::DmaSgDecode { status })
+        } else {
+            None
+        }
+    }
+}
+
+/// One physically contiguous piece of a scatter-gather transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaSegment {
+    pub phys_addr: usize,
+    pub len: usize,
+}
+
+/// Status word of one buffer descriptor, as written back by the DMA.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BdStatus {
+    pub complete: bool,
+    /// Bytes actually transferred; on S2MM it can be less than the segment
+    /// when the stream packet ended early.
+    pub transferred: usize,
+    /// Start / end of packet (S2MM only).
+    pub sof: bool,
+    pub eof: bool,
+    pub internal_error: bool,
+    pub slave_error: bool,
+    pub decode_error: bool,
+}
+
+impl BdStatus {
+    fn from_reg(reg: u32, len_mask: u32) -> Self {
+        BdStatus {
+            complete: reg & BD_STATUS_CMPLT != 0,
+            transferred: (reg & len_mask) as usize,
+            sof: reg & BD_STATUS_SOF != 0,
+            eof: reg & BD_STATUS_EOF != 0,
+            internal_error: reg & BD_STATUS_INT_ERR != 0,
+            slave_error: reg & BD_STATUS_SLV_ERR != 0,
+            decode_error: reg & BD_STATUS_DEC_ERR != 0,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.internal_error || self.slave_error || self.decode_error
+    }
+}
+
+/// Descriptors of one submitted scatter-gather transfer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SgTransfer {
+    first: usize,
+    count: usize,
+}
+
+impl SgTransfer {
+    pub fn len(&self) -> usize {
+        self.count
+    }
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// Descriptor ring at the start of the channel's udmabuf. Descriptors are
+/// handed out in ring order and reclaimed in the same order.
+struct SgRing {
+    descriptors: usize,
+    /// Oldest descriptor not yet reclaimed, and how many are in flight.
+    head: usize,
+    pending: usize,
+}
+
 pub struct AxiDmaChannel {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: UdmabufAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: DmaBuffer,
     first_transfer: bool,
     mode: DmaChannelMode,
     offset: usize,
+    timeout: Duration,
+    has_sg: bool,
+    sg_len_mask: u32,
+    addr_width: u32,
+    sg: Option<SgRing>,
+    /// DMACR bits other than RS: interrupt enables, threshold and delay.
+    irq_ctrl: u32,
+    auto_reset: bool,
 }
 
 impl AxiDmaChannel {
@@ -32,29 +208,41 @@ impl AxiDmaChannel {
         mode: DmaChannelMode,
         udmabuf_name: &str,
     ) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        // let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
+        Self::with_backend(hw_info, mode, udmabuf_name, &DeviceBackend)
+    }
+
+    pub fn with_backend(
+        hw_info: &serde_json::Value,
+        mode: DmaChannelMode,
+        udmabuf_name: &str,
+        backend: &dyn Backend,
+    ) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, mode, udmabuf_name, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor, mode: DmaChannelMode, udmabuf_name: &str) -> Result<Self> {
+        Self::from_desc_with_backend(desc, mode, udmabuf_name, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(
+        desc: &IpDescriptor,
+        mode: DmaChannelMode,
+        udmabuf_name: &str,
+        backend: &dyn Backend,
+    ) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axi_dma", "AxiDmaChannel::new()")?;
+        let uio = backend.open_uio(desc.uio()?)?;
+        let udmabuf = backend.open_udmabuf(udmabuf_name, desc.udmabuf_cached)?;
+        let udmabuf = DmaBuffer::from_io(udmabuf);
+        let has_sg = desc.param_bool_or("C_INCLUDE_SG", false)?;
+        let sg_length_width = desc.param_u32_or("C_SG_LENGTH_WIDTH", 14)?;
+        let addr_width = desc.addr_width("C_ADDR_WIDTH")?;
         ensure!(
-            vendor == "xilinx.com" && library == "ip" && name == "axi_dma",
-            "AxiDmaChannel::new(): This IP is not supported. ({})",
-            name
+            (8..=26).contains(&sg_length_width),
+            HwDescription,
+            "AxiDmaChannel::new(): C_SG_LENGTH_WIDTH must be 8 to 26, found {}",
+            sg_length_width
         );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => uio_acc,
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
-        let udmabuf = match UdmabufAccessor::new(udmabuf_name, false) {
-            Ok(udmabuf_acc) => udmabuf_acc,
-            Err(e) => {
-                bail!("UdmabufAccessor: {}", e)
-            }
-        };
 
         Ok(AxiDmaChannel {
             uio_acc: uio,
@@ -66,40 +254,149 @@ impl AxiDmaChannel {
             } else {
                 S2MM_OFFSET
             },
+            timeout: DEFAULT_TIMEOUT,
+            has_sg,
+            sg_len_mask: (1 << sg_length_width) - 1,
+            addr_width,
+            sg: None,
+            irq_ctrl: 0,
+            auto_reset: false,
         })
     }
 
     pub fn is_running(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(self.offset + DMASR) & 1 == 0 }
+        !self.status().halted
     }
 
     pub fn is_idle(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(self.offset + DMASR) & 2 == 2 }
+        self.status().idle
     }
 
     pub fn is_error(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(self.offset + DMASR) & 0x70 != 0 }
+        self.status().is_error()
+    }
+
+    pub fn status(&self) -> DmaStatus {
+        DmaStatus::from_reg(self.read_status())
+    }
+
+    /// A DMA error also halts the channel; report the error rather than that.
+    fn ensure_running(&self) -> Result<()> {
+        let status = self.status();
+        if let Some(e) = status.error() {
+            return Err(e);
+        }
+        ensure!(!status.halted, InvalidState, "DMA channel not started");
+        Ok(())
+    }
+
+    fn write_buf_addr(&self) -> Result<()> {
+        self.write_addr(SA, self.udmabuf_acc.phys_addr())
+    }
+
+    /// Writes the LSB word, then the MSB word, which is what starts a
+    /// descriptor fetch on TAILDESC in 64-bit mode.
+    fn write_addr(&self, reg: usize, addr: usize) -> Result<()> {
+        let (lsb, msb) = split_phys_addr(addr, self.addr_width)?;
+        unsafe {
+            self.uio_acc.write_mem32(self.offset + reg, lsb);
+            if self.addr_width > 32 {
+                self.uio_acc.write_mem32(self.offset + reg + 4, msb);
+            }
+        }
+        Ok(())
     }
 
-    fn write_buf_addr(&self) {
-        let mem_phys_addr = self.udmabuf_acc.phys_addr() as u32;
+    fn write_bd_addr(&self, offset: usize, addr: usize) -> Result<()> {
+        let (lsb, msb) = split_phys_addr(addr, self.addr_width)?;
         unsafe {
-            self.uio_acc.write_mem32(self.offset + SA, mem_phys_addr);
+            self.udmabuf_acc.write_mem32(offset, lsb);
+            self.udmabuf_acc.write_mem32(offset + 4, msb);
         }
+        Ok(())
+    }
+
+    fn read_status(&self) -> u32 {
+        unsafe { self.uio_acc.read_mem32(self.offset + DMASR) }
+    }
+
+    /// Timeout of `stop` and `wait`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
     }
 
-    pub fn stop(&self) {
+    pub fn poll_halted(&self) -> bool {
+        !self.is_running()
+    }
+
+    pub fn stop(&self) -> Result<()> {
         unsafe {
             self.uio_acc.write_mem32(self.offset + DMACR, 0);
         }
-        while self.is_running() {}
+        wait_until(
+            "AxiDmaChannel::stop",
+            self.timeout,
+            || Ok(self.poll_halted()),
+            || self.read_status(),
+        )
     }
 
     pub fn start(&mut self) {
         unsafe {
-            self.uio_acc.write_mem32(self.offset + DMACR, 0x0001);
+            self.uio_acc
+                .write_mem32(self.offset + DMACR, self.irq_ctrl | DMACR_RS);
+        }
+        self.first_transfer = true;
+    }
+
+    /// Soft-resets the DMA, which clears the error flags and halts it.
+    ///
+    /// The reset covers both channels of the IP. This channel's interrupt
+    /// settings and descriptor ring are set up again, so `start` is all it
+    /// needs; transfers in flight are lost. The other channel has to be
+    /// reset (or set up) and started again too.
+    pub fn reset(&mut self) -> Result<()> {
+        unsafe {
+            self.uio_acc.write_mem32(self.offset + DMACR, DMACR_RESET);
+        }
+        wait_until(
+            "AxiDmaChannel::reset",
+            self.timeout,
+            || Ok(unsafe { self.uio_acc.read_mem32(self.offset + DMACR) } & DMACR_RESET == 0),
+            || self.read_status(),
+        )?;
+        self.set_irq_ctrl(self.irq_ctrl)?;
+        if let Some(descriptors) = self.sg.as_ref().map(|ring| ring.descriptors) {
+            self.setup_sg(descriptors)?;
         }
         self.first_transfer = true;
+        Ok(())
+    }
+
+    /// Resets and restarts the channel whenever `wait`, `read`, `submit` or
+    /// `wait_transfer` run into a DMA error. The error is still returned.
+    pub fn set_auto_reset(&mut self, enable: bool) {
+        self.auto_reset = enable;
+    }
+
+    fn recover<T>(&mut self, result: Result<T>) -> Result<T> {
+        let dma_error = matches!(
+            result,
+            Err(XipError::DmaInternal { .. }
+                | XipError::DmaSlave { .. }
+                | XipError::DmaDecode { .. }
+                | XipError::DmaSgInternal { .. }
+                | XipError::DmaSgSlave { .. }
+                | XipError::DmaSgDecode { .. })
+        );
+        if dma_error && self.auto_reset {
+            self.reset()?;
+            self.start();
+        }
+        result
     }
     pub fn write_len(&self, len: u32) {
         unsafe {
@@ -109,99 +406,715 @@ impl AxiDmaChannel {
     pub fn read_len(&self) -> u32 {
         unsafe { self.uio_acc.read_mem32(self.offset + LENGTH) }
     }
-    pub fn write<V>(&mut self, data: &[V]) -> Result<()> {
+    /// The channel's udmabuf, to fill or read in place. In scatter-gather
+    /// mode it starts with the descriptor ring.
+    pub fn buffer(&self) -> &DmaBuffer {
+        &self.udmabuf_acc
+    }
+    pub fn buffer_mut(&mut self) -> &mut DmaBuffer {
+        &mut self.udmabuf_acc
+    }
+
+    fn ensure_ready(&self, mode: DmaChannelMode, size: usize) -> Result<()> {
         ensure!(
-            self.mode == DmaChannelMode::MM2S,
-            "Channel mode is not MM2S"
+            self.mode == mode,
+            InvalidState,
+            "Channel mode is not {:?}",
+            mode
         );
-        ensure!(self.is_running(), "DMA channel not started");
+        self.ensure_running()?;
         ensure!(
             self.is_idle() || self.first_transfer,
+            InvalidState,
             "DMA channel not idle"
         );
-        let size = core::mem::size_of::<V>();
-        ensure!(
-            data.len() * size <= self.udmabuf_acc.phys_addr(),
-            "Array size too large ({}/{})",
-            data.len() * size,
-            self.udmabuf_acc.phys_addr()
-        );
+        if size > self.udmabuf_acc.size() {
+            return Err(XipError::BufferTooLarge {
+                size,
+                capacity: self.udmabuf_acc.size(),
+            });
+        }
+        // LENGTH is `C_SG_LENGTH_WIDTH` bits wide.
+        if size > self.max_segment_len() {
+            return Err(XipError::BufferTooLarge {
+                size,
+                capacity: self.max_segment_len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends the first `len` bytes of `buffer()` without copying them.
+    pub fn write_in_place(&mut self, len: usize) -> Result<()> {
+        self.ensure_ready(DmaChannelMode::MM2S, len)?;
+        self.udmabuf_acc.sync_for_device(0, len)?;
+        self.write_buf_addr()?;
+        self.write_len(len as u32);
+        self.first_transfer = false;
+        Ok(())
+    }
+    pub fn write<V>(&mut self, data: &[V]) -> Result<()> {
+        let size = core::mem::size_of_val(data);
+        self.ensure_ready(DmaChannelMode::MM2S, size)?;
         unsafe {
             self.udmabuf_acc
                 .copy_from(data.as_ptr(), 0, data.len());
         }
-        self.write_buf_addr();
-        self.write_len((data.len() * size) as u32);
+        self.write_in_place(size)
+    }
+    /// # Safety
+    /// `data` must be valid for reads of `size` elements of `V`.
+    pub unsafe fn write_with_size<V>(&mut self, data: *const V, size: usize) -> Result<()> {
+        let bytes = core::mem::size_of::<V>() * size;
+        self.ensure_ready(DmaChannelMode::MM2S, bytes)?;
+        self.udmabuf_acc.copy_from(data, 0, size);
+        self.write_in_place(bytes)
+    }
+    /// Receives up to `len` bytes into the start of `buffer()` and returns
+    /// how many arrived.
+    pub fn read_in_place(&mut self, len: usize) -> Result<usize> {
+        self.ensure_ready(DmaChannelMode::S2MM, len)?;
+        self.udmabuf_acc.sync_for_device(0, len)?;
+        self.write_buf_addr()?;
+        self.write_len(len as u32);
+        self.wait()?;
+        self.udmabuf_acc.sync_for_cpu(0, len)?;
         self.first_transfer = false;
+        Ok(self.read_len() as usize)
+    }
+    pub fn read<V>(&mut self, len: usize) -> Result<Vec<V>> {
+        self.read_in_place(core::mem::size_of::<V>() * len)?;
+
+        let mut buf = Vec::with_capacity(len);
+        unsafe {
+            self.udmabuf_acc.copy_to(0x00, buf.as_mut_ptr(), len);
+            buf.set_len(len);
+        }
+        Ok(buf)
+    }
+    /// Whether the current transfer has finished; fails on a DMA error.
+    pub fn poll(&self) -> Result<bool> {
+        let status = self.status();
+        match status.error() {
+            Some(e) => Err(e),
+            None => Ok(status.idle),
+        }
+    }
+    /// Waits for the transfer to finish, on the interrupt if enabled.
+    pub fn wait(&mut self) -> Result<()> {
+        let result = if self.interrupt_enabled() {
+            self.ensure_running()?;
+            self.wait_interrupt_until("AxiDmaChannel::wait", |ch| ch.poll())
+        } else {
+            self.wait_timeout(self.timeout)
+        };
+        self.recover(result)
+    }
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<()> {
+        self.ensure_running()?;
+        wait_until(
+            "AxiDmaChannel::wait",
+            timeout,
+            || self.poll(),
+            || self.read_status(),
+        )
+    }
+
+    /// Makes `wait`, `wait_transfer` and the blocking transfers sleep on the
+    /// UIO interrupt instead of polling DMASR. The interrupt fires after
+    /// `threshold` completed descriptors, or `delay` (in units of 125 stream
+    /// clocks, 0 = off) after the last one; both only apply in
+    /// scatter-gather mode. Errors always interrupt.
+    ///
+    /// Interrupt waits still give up after `timeout`. When both channels of an
+    /// `AxiDma` share one UIO interrupt, a wait woken by the other channel
+    /// just re-checks its own status.
+    pub fn enable_interrupt(&mut self, threshold: u8, delay: u8) -> Result<()> {
+        ensure!(
+            threshold > 0,
+            InvalidArgument,
+            "the IRQ threshold must be 1 to 255"
+        );
+        ensure!(
+            self.has_sg || (threshold == 1 && delay == 0),
+            InvalidArgument,
+            "interrupt coalescing needs the scatter-gather engine"
+        );
+        let mut irq_ctrl = DMA_IRQ_IOC | DMA_IRQ_ERR | (threshold as u32) << 16;
+        if delay > 0 {
+            irq_ctrl |= DMA_IRQ_DELAY | (delay as u32) << 24;
+        }
+        self.set_irq_ctrl(irq_ctrl)?;
+        self.uio_acc.set_irq_enable(true)
+    }
+
+    pub fn disable_interrupt(&mut self) -> Result<()> {
+        self.set_irq_ctrl(0)?;
+        self.uio_acc.set_irq_enable(false)
+    }
+
+    pub fn interrupt_enabled(&self) -> bool {
+        self.irq_ctrl != 0
+    }
+
+    /// Keeps RS and writes the interrupt bits of DMACR.
+    fn set_irq_ctrl(&mut self, irq_ctrl: u32) -> Result<()> {
+        self.irq_ctrl = irq_ctrl;
+        let rs = unsafe { self.uio_acc.read_mem32(self.offset + DMACR) } & 1;
+        unsafe {
+            self.uio_acc
+                .write_mem32(self.offset + DMACR, self.irq_ctrl | rs);
+        }
+        self.clear_interrupt(DMA_IRQ_ALL);
         Ok(())
     }
-    pub unsafe fn write_with_size<V>(&mut self, data: *const V, size: usize) -> Result<()> {
+
+    /// Pending interrupt sources (`DMA_IRQ_*`).
+    pub fn interrupt_status(&self) -> u32 {
+        self.read_status() & DMA_IRQ_ALL
+    }
+
+    /// Acknowledges the pending sources in `mask`.
+    pub fn clear_interrupt(&self, mask: u32) {
+        let pending = self.interrupt_status() & mask;
+        if pending != 0 {
+            unsafe {
+                self.uio_acc.write_mem32(self.offset + DMASR, pending);
+            }
+        }
+    }
+
+    /// Acknowledges DMASR and re-enables the UIO interrupt, which the kernel
+    /// masks after every one.
+    fn rearm_interrupt(&mut self) -> Result<()> {
+        self.clear_interrupt(DMA_IRQ_ALL);
+        self.uio_acc.set_irq_enable(true)
+    }
+
+    /// Sleeps on the interrupt until `done` holds, up to the channel timeout.
+    fn wait_interrupt_until(
+        &mut self,
+        operation: &'static str,
+        mut done: impl FnMut(&Self) -> Result<bool>,
+    ) -> Result<()> {
+        let deadline = IrqDeadline::new(operation, self.timeout);
+        loop {
+            // Acknowledge before checking, so that a completion in between
+            // still wakes up the wait below.
+            self.rearm_interrupt()?;
+            if done(self)? {
+                return Ok(());
+            }
+            deadline.wait_irq(self.uio_acc.as_mut(), self.offset + DMASR)?;
+        }
+    }
+
+    /// Whether the IP was built with the scatter-gather engine
+    /// (`C_INCLUDE_SG`).
+    pub fn has_sg(&self) -> bool {
+        self.has_sg
+    }
+
+    /// Largest segment one descriptor can carry (`C_SG_LENGTH_WIDTH`).
+    pub fn max_segment_len(&self) -> usize {
+        self.sg_len_mask as usize
+    }
+
+    /// Builds a ring of `descriptors` buffer descriptors at the start of the
+    /// udmabuf and points CURDESC at it. The channel must be halted; call
+    /// `start` afterwards. The rest of the udmabuf is the data area used by
+    /// `write_sg` / `read_sg`.
+    pub fn setup_sg(&mut self, descriptors: usize) -> Result<()> {
         ensure!(
-            self.mode == DmaChannelMode::MM2S,
-            "Channel mode is not MM2S"
+            self.has_sg,
+            Unsupported,
+            "The scatter-gather engine is not included in this IP."
         );
-        ensure!(self.is_running(), "DMA channel not started");
         ensure!(
-            self.is_idle() || self.first_transfer,
-            "DMA channel not idle"
+            descriptors > 0,
+            InvalidArgument,
+            "the descriptor ring cannot be empty"
         );
-        let size_of_v = core::mem::size_of::<V>();
         ensure!(
-            size_of_v * size <= self.udmabuf_acc.phys_addr(),
-            "Array size too large ({}/{})",
-            size_of_v * size,
-            self.udmabuf_acc.phys_addr()
+            !self.is_running(),
+            InvalidState,
+            "stop the channel before setting up the descriptor ring"
         );
-        self.udmabuf_acc.copy_from(data, 0, size);
-        self.write_buf_addr();
-        self.write_len((size * size_of_v) as u32);
-        self.first_transfer = false;
+        let ring_size = descriptors * BD_SIZE;
+        if ring_size > self.udmabuf_acc.size() {
+            return Err(XipError::BufferTooLarge {
+                size: ring_size,
+                capacity: self.udmabuf_acc.size(),
+            });
+        }
+        for i in 0..descriptors {
+            let next = self.bd_phys_addr((i + 1) % descriptors);
+            unsafe {
+                for word in (0..BD_SIZE).step_by(4) {
+                    self.udmabuf_acc.write_mem32(i * BD_SIZE + word, 0);
+                }
+            }
+            self.write_bd_addr(i * BD_SIZE + BD_NXTDESC, next)?;
+        }
+        self.udmabuf_acc.sync_for_device(0, ring_size)?;
+        self.write_addr(CURDESC, self.bd_phys_addr(0))?;
+        self.sg = Some(SgRing {
+            descriptors,
+            head: 0,
+            pending: 0,
+        });
         Ok(())
     }
-    pub fn read<V>(&mut self, len: usize) -> Result<Vec<V>> {
+
+    fn bd_phys_addr(&self, index: usize) -> usize {
+        self.udmabuf_acc.phys_addr() + index * BD_SIZE
+    }
+
+    fn ring(&self) -> Result<&SgRing> {
+        self.sg.as_ref().ok_or_else(|| {
+            XipError::InvalidState("the descriptor ring is not set up (setup_sg)".to_string())
+        })
+    }
+
+    /// Offset of the data area behind the descriptor ring.
+    pub fn sg_data_offset(&self) -> Result<usize> {
+        Ok(self.ring()?.descriptors * BD_SIZE)
+    }
+
+    /// Queues one transfer made of `segments` and moves TAILDESC past it.
+    /// On MM2S the segments form one stream packet.
+    pub fn submit(&mut self, segments: &[DmaSegment]) -> Result<SgTransfer> {
+        let running = self.ensure_running();
+        self.recover(running)?;
+        let ring = self.ring()?;
         ensure!(
-            self.mode == DmaChannelMode::S2MM,
-            "Channel mode is not S2MM"
+            !segments.is_empty(),
+            InvalidArgument,
+            "no segments to transfer"
         );
-        ensure!(self.is_running(), "DMA channel not started");
         ensure!(
-            self.is_idle() || self.first_transfer,
-            "DMA channel not idle"
+            segments.len() <= ring.descriptors - ring.pending,
+            InvalidState,
+            "descriptor ring is full ({} of {} in flight, {} more needed)",
+            ring.pending,
+            ring.descriptors,
+            segments.len()
+        );
+        for segment in segments {
+            ensure!(
+                segment.len > 0 && segment.len <= self.max_segment_len(),
+                InvalidArgument,
+                "segment length must be 1 to {}, found {}",
+                self.max_segment_len(),
+                segment.len
+            );
+            split_phys_addr(segment.phys_addr + segment.len - 1, self.addr_width)?;
+        }
+        let first = (ring.head + ring.pending) % ring.descriptors;
+        let descriptors = ring.descriptors;
+        let mut index = first;
+        for (i, segment) in segments.iter().enumerate() {
+            let mut control = segment.len as u32;
+            if self.mode == DmaChannelMode::MM2S {
+                if i == 0 {
+                    control |= BD_CONTROL_SOF;
+                }
+                if i == segments.len() - 1 {
+                    control |= BD_CONTROL_EOF;
+                }
+            }
+            let bd = index * BD_SIZE;
+            self.write_bd_addr(bd + BD_BUFFER_ADDRESS, segment.phys_addr)?;
+            unsafe {
+                self.udmabuf_acc.write_mem32(bd + BD_STATUS, 0);
+                self.udmabuf_acc.write_mem32(bd + BD_CONTROL, control);
+            }
+            self.udmabuf_acc.sync_for_device(bd, BD_SIZE)?;
+            self.sync_segment_for_device(segment)?;
+            index = (index + 1) % descriptors;
+        }
+        let last = (first + segments.len() - 1) % descriptors;
+        self.write_addr(TAILDESC, self.bd_phys_addr(last))?;
+        if let Some(ring) = &mut self.sg {
+            ring.pending += segments.len();
+        }
+        self.first_transfer = false;
+        Ok(SgTransfer {
+            first,
+            count: segments.len(),
+        })
+    }
+
+    /// Segments in this channel's udmabuf are synced with the descriptors;
+    /// the caller syncs any other buffer.
+    fn sync_segment_for_device(&self, segment: &DmaSegment) -> Result<()> {
+        match segment.phys_addr.checked_sub(self.udmabuf_acc.phys_addr()) {
+            Some(offset) if offset + segment.len <= self.udmabuf_acc.size() => {
+                self.udmabuf_acc.sync_for_device(offset, segment.len)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Status of every descriptor of `transfer`, in submission order.
+    pub fn transfer_status(&self, transfer: &SgTransfer) -> Result<Vec<BdStatus>> {
+        let ring = self.ring()?;
+        let mut status = Vec::with_capacity(transfer.count);
+        for i in 0..transfer.count {
+            let bd = (transfer.first + i) % ring.descriptors * BD_SIZE;
+            self.udmabuf_acc.sync_for_cpu(bd, BD_SIZE)?;
+            let reg = unsafe { self.udmabuf_acc.read_mem32(bd + BD_STATUS) };
+            status.push(BdStatus::from_reg(reg, self.sg_len_mask));
+        }
+        Ok(status)
+    }
+
+    /// Whether every descriptor of `transfer` has completed; fails on a DMA
+    /// error.
+    pub fn poll_transfer(&self, transfer: &SgTransfer) -> Result<bool> {
+        self.poll()?;
+        Ok(self
+            .transfer_status(transfer)?
+            .iter()
+            .all(|status| status.complete))
+    }
+
+    /// Waits for `transfer` and returns its descriptors to the ring.
+    /// Transfers have to be waited for in submission order.
+    pub fn wait_transfer(&mut self, transfer: &SgTransfer) -> Result<Vec<BdStatus>> {
+        let ring = self.ring()?;
+        ensure!(
+            ring.pending >= transfer.count && transfer.first == ring.head,
+            InvalidState,
+            "transfers must be waited for in submission order"
         );
-        self.write_buf_addr();
-        let size = core::mem::size_of::<V>();
-        let bytes = size * len;
-        self.write_len(bytes as u32);
+        let result = if self.interrupt_enabled() {
+            self.wait_interrupt_until("AxiDmaChannel::wait_transfer", |ch| {
+                ch.poll_transfer(transfer)
+            })
+        } else {
+            wait_until(
+                "AxiDmaChannel::wait_transfer",
+                self.timeout,
+                || self.poll_transfer(transfer),
+                || self.read_status(),
+            )
+        };
+        self.recover(result)?;
+        let status = self.transfer_status(transfer)?;
+        if let Some(ring) = &mut self.sg {
+            ring.head = (ring.head + transfer.count) % ring.descriptors;
+            ring.pending -= transfer.count;
+        }
+        Ok(status)
+    }
 
-        let mut buf = Vec::with_capacity(len);
+    /// Splits `len` bytes of the data area into descriptor-sized segments.
+    fn data_segments(&self, len: usize) -> Result<Vec<DmaSegment>> {
+        let data_offset = self.sg_data_offset()?;
+        let capacity = self.udmabuf_acc.size() - data_offset;
+        if len > capacity {
+            return Err(XipError::BufferTooLarge {
+                size: len,
+                capacity,
+            });
+        }
+        // Keep every segment but the last a multiple of 64 bytes so that
+        // all of them start aligned.
+        let max_len = self.max_segment_len() & !0x3F;
+        let base = self.udmabuf_acc.phys_addr() + data_offset;
+        Ok((0..len)
+            .step_by(max_len)
+            .map(|start| DmaSegment {
+                phys_addr: base + start,
+                len: max_len.min(len - start),
+            })
+            .collect())
+    }
 
-        self.wait()?;
+    /// Sends `data` through the data area as one packet, in as many
+    /// descriptors as needed, and waits for it.
+    pub fn write_sg<V>(&mut self, data: &[V]) -> Result<Vec<BdStatus>> {
+        ensure!(
+            self.mode == DmaChannelMode::MM2S,
+            InvalidState,
+            "Channel mode is not MM2S"
+        );
+        let segments = self.data_segments(core::mem::size_of_val(data))?;
+        unsafe {
+            self.udmabuf_acc
+                .copy_from(data.as_ptr(), self.sg_data_offset()?, data.len());
+        }
+        let transfer = self.submit(&segments)?;
+        self.wait_transfer(&transfer)
+    }
 
+    /// Starts continuous capture into `buffers` buffers of `buffer_len` bytes
+    /// each, one descriptor per buffer. See `S2mmStream`.
+    pub fn stream(&mut self, buffers: usize, buffer_len: usize) -> Result<S2mmStream<'_>> {
+        S2mmStream::new(self, buffers, buffer_len)
+    }
+
+    /// Receives `len` elements of `V` through the data area.
+    pub fn read_sg<V>(&mut self, len: usize) -> Result<Vec<V>> {
+        ensure!(
+            self.mode == DmaChannelMode::S2MM,
+            InvalidState,
+            "Channel mode is not S2MM"
+        );
+        let size = len * core::mem::size_of::<V>();
+        let segments = self.data_segments(size)?;
+        let transfer = self.submit(&segments)?;
+        self.wait_transfer(&transfer)?;
+        self.udmabuf_acc.sync_for_cpu(self.sg_data_offset()?, size)?;
+        let mut buf = Vec::with_capacity(len);
         unsafe {
-            self.udmabuf_acc.copy_to(0x00, buf.as_mut_ptr(), len);
+            self.udmabuf_acc
+                .copy_to(self.sg_data_offset()?, buf.as_mut_ptr(), len);
             buf.set_len(len);
         }
-
-        self.first_transfer = false;
         Ok(buf)
     }
-    pub fn wait(&self) -> Result<()> {
-        ensure!(self.is_running(), "DMA channel not started");
-        loop {
-            if self.is_error() {
-                let error = unsafe { self.uio_acc.read_mem32(DMASR) };
-                ensure!(error & 0x10 == 0, "DMA Internal Error (transfer length 0?)");
-                ensure!(
-                    error & 0x20 == 0,
-                    "DMA Slave Error (cannot access memory map interface)"
-                );
-                ensure!(error & 0x20 == 0, "DMA Decode Error (invalid address)");
+}
+
+/// A filled buffer of an `S2mmStream`. Hand it back with
+/// `S2mmStream::release` so that its descriptor is armed again.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StreamBuffer {
+    index: usize,
+    status: BdStatus,
+}
+
+impl StreamBuffer {
+    /// Slot of the buffer in the ring.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    /// Received bytes.
+    pub fn len(&self) -> usize {
+        self.status.transferred
+    }
+    pub fn is_empty(&self) -> bool {
+        self.status.transferred == 0
+    }
+    /// Start / end of packet flags; a packet longer than one buffer spans
+    /// several.
+    pub fn status(&self) -> &BdStatus {
+        &self.status
+    }
+}
+
+/// Continuous S2MM capture into a ring of buffers.
+///
+/// Every buffer not held by the application stays queued on the DMA, so the
+/// stream keeps being received without re-arming transfers by hand. When
+/// all buffers are filled before the application returns any, the DMA stalls
+/// and the stream data behind it is lost; `overruns` counts these events.
+///
+/// A DMA error ends the stream: drop it, `reset` the channel and open a new
+/// one.
+///
+/// ```no_run
+/// # fn run(dma: &mut xipdriver_rs::axidma::AxiDma) -> xipdriver_rs::error::Result<()> {
+/// let mut stream = dma.stream(8, 4096)?;
+/// while let Some(buf) = stream.next() {
+///     let buf = buf?;
+///     let data = stream.read::<u8>(&buf);
+///     stream.release(buf)?;
+/// #   drop(data);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct S2mmStream<'a> {
+    channel: &'a mut AxiDmaChannel,
+    buffer_len: usize,
+    slot_size: usize,
+    /// Transfers queued on the DMA, oldest first.
+    armed: VecDeque<SgTransfer>,
+    /// Slots the application handed back, re-armed in ring order.
+    returned: Vec<bool>,
+    next_rearm: usize,
+    stalled: bool,
+    overruns: u64,
+}
+
+impl<'a> S2mmStream<'a> {
+    fn new(channel: &'a mut AxiDmaChannel, buffers: usize, buffer_len: usize) -> Result<Self> {
+        ensure!(
+            channel.mode == DmaChannelMode::S2MM,
+            InvalidState,
+            "Channel mode is not S2MM"
+        );
+        ensure!(
+            buffer_len > 0 && buffer_len <= channel.max_segment_len(),
+            InvalidArgument,
+            "buffer length must be 1 to {}, found {}",
+            channel.max_segment_len(),
+            buffer_len
+        );
+        if channel.is_running() {
+            channel.stop()?;
+        }
+        channel.setup_sg(buffers)?;
+        let slot_size = (buffer_len + 0x3F) & !0x3F;
+        let size = channel.sg_data_offset()? + buffers * slot_size;
+        if size > channel.udmabuf_acc.size() {
+            return Err(XipError::BufferTooLarge {
+                size,
+                capacity: channel.udmabuf_acc.size(),
+            });
+        }
+        channel.start();
+        let mut stream = S2mmStream {
+            channel,
+            buffer_len,
+            slot_size,
+            armed: VecDeque::with_capacity(buffers),
+            returned: vec![true; buffers],
+            next_rearm: 0,
+            stalled: false,
+            overruns: 0,
+        };
+        stream.rearm()?;
+        Ok(stream)
+    }
+
+    pub fn buffers(&self) -> usize {
+        self.returned.len()
+    }
+
+    /// Times the ring ran full since the stream was started.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    fn slot_offset(&self, index: usize) -> usize {
+        // setup_sg() succeeded, so the ring is there.
+        self.channel.sg_data_offset().unwrap_or(0) + index * self.slot_size
+    }
+
+    /// Queues returned slots on the DMA, keeping the descriptors in ring
+    /// order.
+    fn rearm(&mut self) -> Result<()> {
+        while self.returned[self.next_rearm] {
+            let segment = DmaSegment {
+                phys_addr: self.channel.udmabuf_acc.phys_addr() + self.slot_offset(self.next_rearm),
+                len: self.buffer_len,
+            };
+            let transfer = self.channel.submit(&[segment])?;
+            debug_assert_eq!(transfer.first, self.next_rearm);
+            self.armed.push_back(transfer);
+            self.returned[self.next_rearm] = false;
+            self.next_rearm = (self.next_rearm + 1) % self.returned.len();
+            self.stalled = false;
+        }
+        Ok(())
+    }
+
+    /// The oldest filled buffer, if any. Never blocks.
+    pub fn try_next(&mut self) -> Result<Option<StreamBuffer>> {
+        let complete = match self.armed.front() {
+            Some(transfer) => self.channel.poll_transfer(transfer)?,
+            None => false,
+        };
+        // No descriptor left for the DMA: the stream is not being received.
+        let full = self.armed.iter().all(|transfer| {
+            self.channel
+                .transfer_status(transfer)
+                .map(|status| status[0].complete)
+                .unwrap_or(false)
+        });
+        if full && !self.stalled {
+            self.stalled = true;
+            self.overruns += 1;
+        }
+        if !complete {
+            return Ok(None);
+        }
+        let transfer = self.armed.pop_front().unwrap();
+        let status = self.channel.wait_transfer(&transfer)?[0];
+        self.channel
+            .udmabuf_acc
+            .sync_for_cpu(self.slot_offset(transfer.first), self.buffer_len)?;
+        Ok(Some(StreamBuffer {
+            index: transfer.first,
+            status,
+        }))
+    }
+
+    /// Waits for the next filled buffer, on the interrupt if the channel has
+    /// it enabled and up to the channel timeout otherwise.
+    pub fn next_buffer(&mut self) -> Result<StreamBuffer> {
+        if self.channel.interrupt_enabled() {
+            let deadline = IrqDeadline::new("S2mmStream::next_buffer", self.channel.timeout);
+            loop {
+                self.channel.rearm_interrupt()?;
+                if let Some(buffer) = self.try_next()? {
+                    return Ok(buffer);
+                }
+                let status_offset = self.channel.offset + DMASR;
+                deadline.wait_irq(self.channel.uio_acc.as_mut(), status_offset)?;
             }
-            if self.is_idle() {
-                break;
+        }
+        let mut buffer = None;
+        let result = wait_until(
+            "S2mmStream::next_buffer",
+            self.channel.timeout,
+            || {
+                buffer = self.try_next()?;
+                Ok(buffer.is_some())
+            },
+            || 0,
+        );
+        match result {
+            Ok(()) => Ok(buffer.unwrap()),
+            Err(XipError::Timeout(mut e)) => {
+                e.status = self.channel.read_status();
+                Err(e.into())
             }
+            Err(e) => Err(e),
         }
-        Ok(())
+    }
+
+    /// Copies the received part of `buf` out of the ring.
+    pub fn read<V>(&self, buf: &StreamBuffer) -> Vec<V> {
+        let len = buf.len() / core::mem::size_of::<V>();
+        let mut data = Vec::with_capacity(len);
+        unsafe {
+            self.channel
+                .udmabuf_acc
+                .copy_to(self.slot_offset(buf.index), data.as_mut_ptr(), len);
+            data.set_len(len);
+        }
+        data
+    }
+
+    /// Gives `buf` back to the DMA.
+    pub fn release(&mut self, buf: StreamBuffer) -> Result<()> {
+        self.returned[buf.index] = true;
+        self.rearm()
+    }
+
+    /// Halts the channel. Dropping the stream does the same but ignores
+    /// errors.
+    pub fn stop(self) -> Result<()> {
+        self.channel.stop()
+    }
+}
+
+impl Iterator for S2mmStream<'_> {
+    type Item = Result<StreamBuffer>;
+
+    /// Blocks like `next_buffer`; the stream never ends on its own.
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_buffer())
+    }
+}
+
+impl Drop for S2mmStream<'_> {
+    fn drop(&mut self) {
+        let _ = self.channel.stop();
     }
 }
 
@@ -212,38 +1125,42 @@ pub struct AxiDma {
 
 impl AxiDma {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        ensure!(
-            vendor == "xilinx.com" && library == "ip" && name == "axi_dma",
-            "AxiDma::new(): This IP is not supported. ({})",
-            name
-        );
-        let udmabuf_names = json_as_vec!(hw_object["udmabuf"]);
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axi_dma", "AxiDma::new()")?;
         let mut udmabuf_i = 0;
-        let mm2s = if hw_params["C_INCLUDE_MM2S"] == 0 {
+        let mm2s = if !desc.param_bool_or("C_INCLUDE_MM2S", true)? {
             None
         } else {
-            let udmabuf_name = json_as_str!(udmabuf_names[udmabuf_i]);
+            let udmabuf_name = desc.udmabuf(udmabuf_i)?;
             udmabuf_i += 1;
-            Some(AxiDmaChannel::new(
-                hw_info,
+            Some(AxiDmaChannel::from_desc_with_backend(
+                desc,
                 DmaChannelMode::MM2S,
                 udmabuf_name,
+                backend,
             )?)
         };
 
-        let s2mm = if hw_params["C_INCLUDE_S2MM"] == 0 {
+        let s2mm = if !desc.param_bool_or("C_INCLUDE_S2MM", true)? {
             None
         } else {
-            let udmabuf_name = json_as_str!(udmabuf_names[udmabuf_i]);
-            Some(AxiDmaChannel::new(
-                hw_info,
+            let udmabuf_name = desc.udmabuf(udmabuf_i)?;
+            Some(AxiDmaChannel::from_desc_with_backend(
+                desc,
                 DmaChannelMode::S2MM,
                 udmabuf_name,
+                backend,
             )?)
         };
 
@@ -258,27 +1175,69 @@ impl AxiDma {
             ch.start();
         }
     }
-    pub fn stop(&self) {
+    pub fn stop(&self) -> Result<()> {
         if let Some(ch) = &self.mm2s {
-            ch.stop();
+            ch.stop()?;
         }
         if let Some(ch) = &self.s2mm {
-            ch.stop();
+            ch.stop()?;
         }
+        Ok(())
+    }
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        if let Some(ch) = &mut self.mm2s {
+            ch.set_timeout(timeout);
+        }
+        if let Some(ch) = &mut self.s2mm {
+            ch.set_timeout(timeout);
+        }
+    }
+    /// Soft-resets the DMA and sets every channel up again; see
+    /// `AxiDmaChannel::reset`.
+    pub fn reset(&mut self) -> Result<()> {
+        if let Some(ch) = &mut self.mm2s {
+            ch.reset()?;
+        }
+        if let Some(ch) = &mut self.s2mm {
+            ch.reset()?;
+        }
+        Ok(())
+    }
+    /// Enables interrupts on every channel; see
+    /// `AxiDmaChannel::enable_interrupt`.
+    pub fn enable_interrupt(&mut self, threshold: u8, delay: u8) -> Result<()> {
+        if let Some(ch) = &mut self.mm2s {
+            ch.enable_interrupt(threshold, delay)?;
+        }
+        if let Some(ch) = &mut self.s2mm {
+            ch.enable_interrupt(threshold, delay)?;
+        }
+        Ok(())
+    }
+    pub fn disable_interrupt(&mut self) -> Result<()> {
+        if let Some(ch) = &mut self.mm2s {
+            ch.disable_interrupt()?;
+        }
+        if let Some(ch) = &mut self.s2mm {
+            ch.disable_interrupt()?;
+        }
+        Ok(())
     }
     pub fn write<V>(&mut self, data: &[V]) -> Result<()> {
         if let Some(ch) = &mut self.mm2s {
             ch.write(data)?;
         } else {
-            bail!("The MM2S channel is not supported on this IP.");
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
         }
         Ok(())
     }
+    /// # Safety
+    /// `data` must be valid for reads of `size` elements of `V`.
     pub unsafe fn write_with_size<V>(&mut self, data: *const V, size: usize) -> Result<()> {
         if let Some(ch) = &mut self.mm2s {
             ch.write_with_size(data, size)?;
         } else {
-            bail!("The MM2S channel is not supported on this IP.");
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
         }
         Ok(())
     }
@@ -286,49 +1245,81 @@ impl AxiDma {
         if let Some(ch) = &mut self.s2mm {
             ch.read(len)
         } else {
-            bail!("The S2MM channel is not supported on this IP.");
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
+        }
+    }
+    /// Sets up a ring of `descriptors` on every channel; see
+    /// `AxiDmaChannel::setup_sg`.
+    pub fn setup_sg(&mut self, descriptors: usize) -> Result<()> {
+        if let Some(ch) = &mut self.mm2s {
+            ch.setup_sg(descriptors)?;
+        }
+        if let Some(ch) = &mut self.s2mm {
+            ch.setup_sg(descriptors)?;
+        }
+        Ok(())
+    }
+    pub fn write_sg<V>(&mut self, data: &[V]) -> Result<Vec<BdStatus>> {
+        if let Some(ch) = &mut self.mm2s {
+            ch.write_sg(data)
+        } else {
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
+        }
+    }
+    pub fn stream(&mut self, buffers: usize, buffer_len: usize) -> Result<S2mmStream<'_>> {
+        if let Some(ch) = &mut self.s2mm {
+            ch.stream(buffers, buffer_len)
+        } else {
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
+        }
+    }
+    pub fn read_sg<V>(&mut self, len: usize) -> Result<Vec<V>> {
+        if let Some(ch) = &mut self.s2mm {
+            ch.read_sg(len)
+        } else {
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
         }
     }
     pub fn is_mm2s_running(&self) -> Result<bool> {
         if let Some(ch) = &self.mm2s {
             Ok(ch.is_running())
         } else {
-            bail!("The MM2S channel is not supported on this IP.");
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
         }
     }
     pub fn is_mm2s_idle(&self) -> Result<bool> {
         if let Some(ch) = &self.mm2s {
             Ok(ch.is_idle())
         } else {
-            bail!("The MM2S channel is not supported on this IP.");
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
         }
     }
     pub fn is_mm2s_error(&self) -> Result<bool> {
         if let Some(ch) = &self.mm2s {
             Ok(ch.is_error())
         } else {
-            bail!("The MM2S channel is not supported on this IP.");
+            bail!(Unsupported, "The MM2S channel is not supported on this IP.");
         }
     }
     pub fn is_s2mm_running(&self) -> Result<bool> {
         if let Some(ch) = &self.s2mm {
             Ok(ch.is_running())
         } else {
-            bail!("The S2MM channel is not supported on this IP.");
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
         }
     }
     pub fn is_s2mm_idle(&self) -> Result<bool> {
         if let Some(ch) = &self.s2mm {
             Ok(ch.is_idle())
         } else {
-            bail!("The S2MM channel is not supported on this IP.");
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
         }
     }
     pub fn is_s2mm_error(&self) -> Result<bool> {
         if let Some(ch) = &self.s2mm {
             Ok(ch.is_error())
         } else {
-            bail!("The S2MM channel is not supported on this IP.");
+            bail!(Unsupported, "The S2MM channel is not supported on this IP.");
         }
     }
 }
@@ -1,40 +1,30 @@
 #![allow(unused)]
 
-use anyhow::{ensure, Result, Context, bail};
-
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::error::{ensure, Result};
+use crate::hwinfo::IpDescriptor;
 
 pub struct AxiGpio {
-    uio_acc: UioAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
     bitw: [u64; 2],
 }
 
 impl AxiGpio {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        // let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "axi_gpio",
-            "VideoFrameBufRead::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axi_gpio", "AxiGpio::new()")?;
+        let uio = backend.open_uio(desc.uio()?)?;
         Ok(AxiGpio {
             uio_acc: uio,
             bitw: [32, 32],
@@ -42,14 +32,14 @@ impl AxiGpio {
     }
 
     pub fn read_data(&self, channel: usize) -> Result<u32> {
-        ensure!(channel == 1 || channel == 2, "channel bust be 1 or 2");
+        ensure!(channel == 1 || channel == 2, InvalidArgument, "channel bust be 1 or 2");
         let offset = if channel == 1 { 0x00 } else { 0x08 };
         Ok(unsafe {
             self.uio_acc.read_mem32(offset)
         })
     }
     pub fn write_data(&self, channel: usize, data:u32) -> Result<()> {
-        ensure!(channel == 1 || channel == 2, "channel bust be 1 or 2");
+        ensure!(channel == 1 || channel == 2, InvalidArgument, "channel bust be 1 or 2");
         let offset = if channel == 1 { 0x00 } else { 0x08 };
         unsafe {
             self.uio_acc.write_mem32(offset, data);
@@ -57,14 +47,14 @@ impl AxiGpio {
         Ok(())
     }
     pub fn read_tri(&self, channel: usize) -> Result<u32> {
-        ensure!(channel == 1 || channel == 2, "channel bust be 1 or 2");
+        ensure!(channel == 1 || channel == 2, InvalidArgument, "channel bust be 1 or 2");
         let offset = if channel == 1 { 0x04 } else { 0x0C };
         Ok(unsafe {
             self.uio_acc.read_mem32(offset)
         })
     }
     pub fn write_tri(&self, channel: usize, data:u32) -> Result<()> {
-        ensure!(channel == 1 || channel == 2, "channel bust be 1 or 2");
+        ensure!(channel == 1 || channel == 2, InvalidArgument, "channel bust be 1 or 2");
         let offset = if channel == 1 { 0x04 } else { 0x0C };
         unsafe {
             self.uio_acc.write_mem32(offset, data);
@@ -1,36 +1,28 @@
-use anyhow::{bail, ensure, Context, Result};
-
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
-
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::error::Result;
+use crate::hwinfo::IpDescriptor;
 
 pub struct AxisSwitch {
-    uio_acc: UioAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
 }
 
 impl AxisSwitch {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "axis_switch",
-            "VideoFrameBufRead::new(): This IP is not supported. ({})",
-            name
-        );
-
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => uio_acc,
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axis_switch", "AxisSwitch::new()")?;
+
+        let uio = backend.open_uio(desc.uio()?)?;
 
         Ok(AxisSwitch {
             uio_acc: uio,
@@ -62,7 +54,7 @@ impl AxisSwitch {
         reg_value &= 0x0F;
 
         let si_index_u32 = si_index as u32;
-        ((reg_value == si_index_u32) && (!enable)) || ((reg_value & si_index_u32) != 0 && (!enable))
+        !enable && ((reg_value == si_index_u32) || (reg_value & si_index_u32) != 0)
     }
 
     pub fn is_mi_port_disabled(&self, mi_index: u8) -> bool {
@@ -0,0 +1,280 @@
+//! Register / memory access backends used by every driver.
+//!
+//! Drivers only talk to `RegIo` (the IP's AXI-Lite register window) and
+//! `BufIo` (a physically contiguous DMA buffer). `DeviceBackend` maps them
+//! onto UIO and u-dma-buf devices; `crate::sim::SimBackend` keeps them in memory.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use jelly_mem_access::*;
+
+use crate::error::{Result, XipError};
+use crate::sysfs::{Sysfs, UdmabufSync};
+
+/// AXI-Lite register window of an IP.
+pub trait RegIo: Send {
+    fn phys_addr(&self) -> usize;
+    fn size(&self) -> usize;
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn read_mem32(&self, offset: usize) -> u32;
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn write_mem32(&self, offset: usize, data: u32);
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn read_memi32(&self, offset: usize) -> i32 {
+        self.read_mem32(offset) as i32
+    }
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn write_memi32(&self, offset: usize, data: i32) {
+        self.write_mem32(offset, data as u32)
+    }
+
+    fn set_irq_enable(&mut self, enable: bool) -> Result<()>;
+    fn wait_irq(&mut self) -> Result<()>;
+    /// Like `wait_irq`, giving up after `timeout`. Returns whether an
+    /// interrupt arrived.
+    fn wait_irq_timeout(&mut self, timeout: Duration) -> Result<bool>;
+}
+
+/// Physically contiguous buffer shared with the PL.
+pub trait BufIo: Send {
+    fn phys_addr(&self) -> usize;
+    fn size(&self) -> usize;
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn read_mem32(&self, offset: usize) -> u32;
+
+    /// # Safety
+    /// `offset + 4` must be within `size()`.
+    unsafe fn write_mem32(&self, offset: usize, data: u32);
+
+    /// # Safety
+    /// `dst_ptr` must be valid for `len` bytes and `src_adr + len` within `size()`.
+    unsafe fn copy_to_bytes(&self, src_adr: usize, dst_ptr: *mut u8, len: usize);
+
+    /// # Safety
+    /// `src_ptr` must be valid for `len` bytes and `dst_adr + len` within `size()`.
+    unsafe fn copy_from_bytes(&self, src_ptr: *const u8, dst_adr: usize, len: usize);
+
+    /// Start of the CPU mapping, valid for `size()` bytes while `self` lives.
+    fn as_mut_ptr(&self) -> *mut u8;
+
+    /// Makes what the PL wrote to `offset..offset + len` visible to the CPU.
+    /// Nothing to do for uncached buffers.
+    fn sync_for_cpu(&self, _offset: usize, _len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes what the CPU wrote to `offset..offset + len` visible to the PL.
+    fn sync_for_device(&self, _offset: usize, _len: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl dyn BufIo {
+    /// Copies `count` elements of `V` out of the buffer.
+    ///
+    /// # Safety
+    /// Same as `BufIo::copy_to_bytes`.
+    pub unsafe fn copy_to<V>(&self, src_adr: usize, dst_ptr: *mut V, count: usize) {
+        self.copy_to_bytes(
+            src_adr,
+            dst_ptr as *mut u8,
+            count * core::mem::size_of::<V>(),
+        );
+    }
+
+    /// Copies `count` elements of `V` into the buffer.
+    ///
+    /// # Safety
+    /// Same as `BufIo::copy_from_bytes`.
+    pub unsafe fn copy_from<V>(&self, src_ptr: *const V, dst_adr: usize, count: usize) {
+        self.copy_from_bytes(
+            src_ptr as *const u8,
+            dst_adr,
+            count * core::mem::size_of::<V>(),
+        );
+    }
+}
+
+/// Opens register windows and DMA buffers by device name.
+pub trait Backend {
+    fn open_uio(&self, name: &str) -> Result<Box<dyn RegIo>>;
+    fn open_udmabuf(&self, name: &str, cache_enable: bool) -> Result<Box<dyn BufIo>>;
+}
+
+/// UIO / u-dma-buf devices of the running board.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceBackend;
+
+impl Backend for DeviceBackend {
+    /// `name` is matched against `/sys/class/uio/uioN/name`; a node name
+    /// such as `uio3` opens that node directly.
+    fn open_uio(&self, name: &str) -> Result<Box<dyn RegIo>> {
+        let node = if name
+            .strip_prefix("uio")
+            .is_some_and(|n| n.parse::<usize>().is_ok())
+        {
+            name.to_string()
+        } else {
+            Sysfs::new()
+                .uio_devices()?
+                .into_iter()
+                .find(|device| device.name == name)
+                .map(|device| device.node)
+                .ok_or_else(|| {
+                    XipError::Mapping(format!("UioAccessor: no UIO device named {}", name))
+                })?
+        };
+        let number = node["uio".len()..].parse::<usize>().unwrap();
+        let acc = UioAccessor::<usize>::new(number)
+            .map_err(|e| XipError::Mapping(format!("UioAccessor: {}", e)))?;
+        let path = format!("/dev/{}", node);
+        let irq = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| XipError::io("open", &path, e))?;
+        Ok(Box::new(Uio { acc, irq }))
+    }
+
+    /// A cached buffer syncs through the device's sysfs controls.
+    fn open_udmabuf(&self, name: &str, cache_enable: bool) -> Result<Box<dyn BufIo>> {
+        let udmabuf_acc = UdmabufAccessor::<usize>::new(name, cache_enable)
+            .map_err(|e| XipError::Mapping(format!("UdmabufAccessor: {}", e)))?;
+        if cache_enable {
+            Ok(Box::new(CachedUdmabuf {
+                acc: udmabuf_acc,
+                sync: Sysfs::new().udmabuf_sync(name),
+            }))
+        } else {
+            Ok(Box::new(udmabuf_acc))
+        }
+    }
+}
+
+/// A UIO node. Interrupts go through a second handle on `/dev/uioN`, which
+/// can be polled with a timeout.
+struct Uio {
+    acc: UioAccessor<usize>,
+    irq: File,
+}
+
+impl RegIo for Uio {
+    fn phys_addr(&self) -> usize {
+        MemAccess::phys_addr(&self.acc)
+    }
+    fn size(&self) -> usize {
+        MemAccess::size(&self.acc)
+    }
+    unsafe fn read_mem32(&self, offset: usize) -> u32 {
+        MemAccess::read_mem32(&self.acc, offset)
+    }
+    unsafe fn write_mem32(&self, offset: usize, data: u32) {
+        MemAccess::write_mem32(&self.acc, offset, data)
+    }
+    unsafe fn read_memi32(&self, offset: usize) -> i32 {
+        MemAccess::read_memi32(&self.acc, offset)
+    }
+    unsafe fn write_memi32(&self, offset: usize, data: i32) {
+        MemAccess::write_memi32(&self.acc, offset, data)
+    }
+    fn set_irq_enable(&mut self, enable: bool) -> Result<()> {
+        self.irq
+            .write_all(&(enable as u32).to_ne_bytes())
+            .map_err(|e| XipError::Mapping(format!("UIO interrupt control: {}", e)))
+    }
+    fn wait_irq(&mut self) -> Result<()> {
+        let mut count = [0; 4];
+        self.irq
+            .read_exact(&mut count)
+            .map_err(|e| XipError::Mapping(format!("UIO interrupt wait: {}", e)))
+    }
+    fn wait_irq_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.irq.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ms = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        match unsafe { libc::poll(&mut fd, 1, ms) } {
+            0 => Ok(false),
+            n if n < 0 => Err(XipError::Mapping(format!(
+                "UIO interrupt wait: {}",
+                std::io::Error::last_os_error()
+            ))),
+            _ => self.wait_irq().map(|()| true),
+        }
+    }
+}
+
+impl BufIo for UdmabufAccessor<usize> {
+    fn phys_addr(&self) -> usize {
+        MemAccess::phys_addr(self)
+    }
+    fn size(&self) -> usize {
+        MemAccess::size(self)
+    }
+    unsafe fn read_mem32(&self, offset: usize) -> u32 {
+        MemAccess::read_mem32(self, offset)
+    }
+    unsafe fn write_mem32(&self, offset: usize, data: u32) {
+        MemAccess::write_mem32(self, offset, data)
+    }
+    unsafe fn copy_to_bytes(&self, src_adr: usize, dst_ptr: *mut u8, len: usize) {
+        MemAccess::copy_to(self, src_adr, dst_ptr, len)
+    }
+    unsafe fn copy_from_bytes(&self, src_ptr: *const u8, dst_adr: usize, len: usize) {
+        MemAccess::copy_from(self, src_ptr, dst_adr, len)
+    }
+    fn as_mut_ptr(&self) -> *mut u8 {
+        MemAccess::addr(self) as *mut u8
+    }
+}
+
+/// A u-dma-buf mapped cached, which has to be synced around DMA.
+struct CachedUdmabuf {
+    acc: UdmabufAccessor<usize>,
+    sync: UdmabufSync,
+}
+
+impl BufIo for CachedUdmabuf {
+    fn phys_addr(&self) -> usize {
+        BufIo::phys_addr(&self.acc)
+    }
+    fn size(&self) -> usize {
+        BufIo::size(&self.acc)
+    }
+    unsafe fn read_mem32(&self, offset: usize) -> u32 {
+        BufIo::read_mem32(&self.acc, offset)
+    }
+    unsafe fn write_mem32(&self, offset: usize, data: u32) {
+        BufIo::write_mem32(&self.acc, offset, data)
+    }
+    unsafe fn copy_to_bytes(&self, src_adr: usize, dst_ptr: *mut u8, len: usize) {
+        self.acc.copy_to_bytes(src_adr, dst_ptr, len)
+    }
+    unsafe fn copy_from_bytes(&self, src_ptr: *const u8, dst_adr: usize, len: usize) {
+        self.acc.copy_from_bytes(src_ptr, dst_adr, len)
+    }
+    fn as_mut_ptr(&self) -> *mut u8 {
+        self.acc.as_mut_ptr()
+    }
+    fn sync_for_cpu(&self, offset: usize, len: usize) -> Result<()> {
+        self.sync.sync_for_cpu(offset, len)
+    }
+    fn sync_for_device(&self, offset: usize, len: usize) -> Result<()> {
+        self.sync.sync_for_device(offset, len)
+    }
+}
@@ -1,54 +1,48 @@
-use anyhow::{bail, ensure, Context, Result};
+use std::time::Duration;
 
-use crate::json_as_map;
-use crate::json_as_str;
-use crate::json_as_vec;
-
-use jelly_mem_access::*;
+use crate::ap_ctrl::ApCtrl;
+use crate::backend::{Backend, BufIo, DeviceBackend, RegIo};
+use crate::dma_buffer::split_phys_addr;
+use crate::error::{bail, Result, XipError};
+use crate::frame_pool::{FramePool, FrameSpec};
+use crate::hwinfo::IpDescriptor;
+use crate::timeout::DEFAULT_TIMEOUT;
 
 pub struct BirdEyeViewHW {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: Vec<UdmabufAccessor<usize>>,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Vec<Box<dyn BufIo>>,
     max_width: u32,
     max_height: u32,
+    addr_width: u32,
+    pool_in: Option<FramePool>,
+    pool_out: Option<FramePool>,
+    timeout: Duration,
 }
 
 impl BirdEyeViewHW {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        //let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let udmabuf_names = json_as_vec!(hw_object["udmabuf"]);
-
-        ensure!(
-            vendor == "xilinx.com"
-                && (library == "ip" || library == "hls")
-                && name == "bird_eye_view",
-            "BierdEyeViewHW::new(): This IP is nort supported. ({})",
-            name
-        );
-
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => uio_acc,
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
 
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip", "hls"], "bird_eye_view", "BirdEyeViewHW::new()")?;
+
+        let addr_width = desc.m_axi_addr_width()?;
+        let uio = backend.open_uio(desc.uio()?)?;
+
+        // Input image, remap table and output image.
+        desc.udmabuf(2)?;
         let mut udmabuf = Vec::new();
-        for name in udmabuf_names.iter() {
-            let udmabuf_name = name.as_str().context("udmabuf_name is not string")?;
-            match UdmabufAccessor::new(udmabuf_name, false) {
-                Ok(udmabuf_acc) => {
-                    udmabuf.push(udmabuf_acc);
-                }
-                Err(e) => {
-                    bail!("UdmabufAccessor: {}", e)
-                }
-            };
+        for udmabuf_name in desc.udmabuf.iter() {
+            udmabuf.push(backend.open_udmabuf(udmabuf_name, false)?);
         }
 
         Ok(BirdEyeViewHW {
@@ -56,94 +50,134 @@ impl BirdEyeViewHW {
             udmabuf_acc: udmabuf,
             max_width: 1280,
             max_height: 720,
+            addr_width,
+            pool_in: None,
+            pool_out: None,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
 
-    pub fn is_running(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 1 }
+    pub fn start_once(&mut self) -> Result<()> {
+        self.ap_start();
+        Ok(())
     }
 
-    pub fn is_done(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 2 == 2 }
-    }
-    pub fn is_idle(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 4 == 4 }
-    }
-    pub fn is_ready(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 8 == 8 }
+    pub fn set_img_in_addr(&mut self) -> Result<()> {
+        self.write_addr(0x18, self.udmabuf_acc[0].phys_addr())
     }
 
-    pub fn get_auto_restart_enable(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 0x20 == 0x20 }
+    pub fn set_img_map_addr(&mut self) -> Result<()> {
+        self.write_addr(0x24, self.udmabuf_acc[1].phys_addr())
     }
 
-    pub fn set_auto_restart_enable(&self, en: bool) {
-        let reg = if en { 0x20 } else { 0 };
-        unsafe {
-            self.uio_acc.write_mem32(0x00, reg);
-        }
+    pub fn set_img_out_addr(&mut self) -> Result<()> {
+        self.write_addr(0x30, self.udmabuf_acc[2].phys_addr())
     }
 
-    pub fn start_once(&mut self) -> Result<()> {
+    /// Pointer arguments take two words; the upper one only matters with a
+    /// wider m_axi address.
+    fn write_addr(&self, offset: usize, addr: usize) -> Result<()> {
+        let (lsb, msb) = split_phys_addr(addr, self.addr_width)?;
         unsafe {
-            self.uio_acc.write_mem32(0x00, 0x01);
+            self.uio_acc.write_mem32(offset, lsb);
+            if self.addr_width > 32 {
+                self.uio_acc.write_mem32(offset + 4, msb);
+            }
         }
         Ok(())
     }
 
-    pub fn set_img_in_addr(&mut self) -> Result<()> {
-        unsafe {
-            self.uio_acc
-                .write_mem32(0x18, self.udmabuf_acc[0].phys_addr() as u32);
+    /// Fails with `BufferTooLarge` unless `bytes` fit in udmabuf `index`.
+    fn check_fits(&self, index: usize, bytes: usize) -> Result<()> {
+        let capacity = self.udmabuf_acc[index].size();
+        if bytes > capacity {
+            return Err(XipError::BufferTooLarge {
+                size: bytes,
+                capacity,
+            });
         }
         Ok(())
     }
 
-    pub fn set_img_map_addr(&mut self) -> Result<()> {
+    pub fn write_img_in(&mut self, img_in: &[u32]) -> Result<()> {
+        self.check_fits(0, std::mem::size_of_val(img_in))?;
         unsafe {
-            self.uio_acc
-                .write_mem32(0x24, self.udmabuf_acc[1].phys_addr() as u32);
+            self.udmabuf_acc[0].copy_from(img_in.as_ptr(), 0x00, img_in.len());
         }
         Ok(())
     }
 
-    pub fn set_img_out_addr(&mut self) -> Result<()> {
+    pub fn write_img_map(&mut self, img_map: &[u32]) -> Result<()> {
+        self.check_fits(1, std::mem::size_of_val(img_map))?;
         unsafe {
-            self.uio_acc
-                .write_mem32(0x30, self.udmabuf_acc[2].phys_addr() as u32);
+            self.udmabuf_acc[1].copy_from(img_map.as_ptr(), 0x00, img_map.len());
         }
         Ok(())
     }
 
-    pub fn write_img_in(&mut self, img_in: &[u32]) -> Result<()> {
-        ensure!(
-            img_in.len() <= self.udmabuf_acc[0].size(),
-            "img_in.len() is too large\n img_in.len() : {}\n udmabuf : {}",
-            img_in.len(),
-            self.udmabuf_acc[0].size()
-        );
-        unsafe {
-            self.udmabuf_acc[0].copy_from(img_in.as_ptr(), 0x00, img_in.len());
-        }
+    /// Timeout of `run_pools`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Input and output images: one u32 per pixel, lines tightly packed.
+    pub fn frame_spec(&self) -> FrameSpec {
+        FrameSpec::packed(self.max_width, self.max_height, 4)
+    }
+
+    /// Takes `img_in` from `pool` in `run_pools`.
+    pub fn attach_input_pool(&mut self, pool: &FramePool) -> Result<()> {
+        self.pool_in = Some(pool.attach(&self.frame_spec())?);
         Ok(())
     }
 
-    pub fn write_img_map(&mut self, img_map: &[u32]) -> Result<()> {
-        ensure!(
-            img_map.len() <= self.udmabuf_acc[1].size(),
-            "img_map.len() is too large\n img_map.len() : {}\n udmabuf : {}",
-            img_map.len(),
-            self.udmabuf_acc[1].size()
-        );
-        unsafe {
-            self.udmabuf_acc[1].copy_from(img_map.as_ptr(), 0x00, img_map.len());
-        }
+    /// Publishes `img_out` to `pool` in `run_pools`.
+    pub fn attach_output_pool(&mut self, pool: &FramePool) -> Result<()> {
+        self.pool_out = Some(pool.attach(&self.frame_spec())?);
         Ok(())
     }
 
+    pub fn detach_pools(&mut self) {
+        self.pool_in = None;
+        self.pool_out = None;
+    }
+
+    /// Remaps the latest input frame into a free output buffer and publishes
+    /// it. Returns the sequence number of the output frame, or `None` if no
+    /// input frame was published yet. `img_map` stays in udmabuf 1.
+    pub fn run_pools(&mut self) -> Result<Option<u64>> {
+        let (Some(pool_in), Some(pool_out)) = (&self.pool_in, &self.pool_out) else {
+            bail!(InvalidState, "BirdEyeViewHW: input and output pools must be attached");
+        };
+        let Some(frame) = pool_in.acquire_read() else {
+            return Ok(None);
+        };
+        let result = pool_out.acquire_write().and_then(|out| {
+            let remap = pool_in
+                .phys_addr(frame.index)
+                .and_then(|addr| self.write_addr(0x18, addr))
+                .and_then(|()| pool_out.phys_addr(out))
+                .and_then(|addr| self.write_addr(0x30, addr))
+                .and_then(|()| self.start_and_wait(self.timeout));
+            match remap {
+                Ok(()) => pool_out.publish(out),
+                Err(e) => {
+                    pool_out.release_write(out)?;
+                    Err(e)
+                }
+            }
+        });
+        pool_in.release_read(frame.index)?;
+        result.map(Some)
+    }
+
     pub fn read_img_out(&mut self) -> Result<Vec<u32>> {
         let w = self.max_width as usize;
         let h = self.max_height as usize;
+        self.check_fits(2, w * h * std::mem::size_of::<u32>())?;
         let mut buf = Vec::with_capacity(w * h);
         unsafe {
             self.udmabuf_acc[2].copy_to(0x00, buf.as_mut_ptr(), w * h);
@@ -152,3 +186,12 @@ impl BirdEyeViewHW {
         Ok(buf)
     }
 }
+
+impl ApCtrl for BirdEyeViewHW {
+    fn ap_regs(&self) -> &dyn RegIo {
+        &*self.uio_acc
+    }
+    fn ap_regs_mut(&mut self) -> &mut dyn RegIo {
+        &mut *self.uio_acc
+    }
+}
@@ -0,0 +1,357 @@
+//! Opens a driver for every IP of a hardware description.
+//!
+//! ```no_run
+//! use xipdriver_rs::board::Board;
+//! use xipdriver_rs::hwinfo::HwInfo;
+//! use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead};
+//!
+//! let mut board = Board::new(&HwInfo::read("hwinfo.json")?);
+//! let vfb_r = board.get_mut::<VideoFrameBufRead>("/lane_detection/*")?;
+//! vfb_r.set_format(ColorFormat::Rgb8)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::any::{type_name, Any};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::axidma::AxiDma;
+use crate::axigpio::AxiGpio;
+use crate::axis_switch::AxisSwitch;
+use crate::backend::{Backend, DeviceBackend};
+use crate::bird_eye_view::BirdEyeViewHW;
+use crate::error::{bail, ensure, Result, XipError};
+use crate::hwinfo::{HwInfo, IpDescriptor};
+use crate::umv_lane_detector::UmvLaneDetector;
+use crate::umv_motor_controller::UmvMotorController;
+use crate::v_frmbuf::{VideoFrameBufRead, VideoFrameBufWrite};
+use crate::v_proc_ss::VideoProcSubsystemCsc;
+use crate::vdma::{AxiVdmaMM2S, AxiVdmaS2MM};
+use crate::yolo::Yolo;
+
+/// An opened driver and its type name.
+type Opened = (&'static str, Box<dyn Any + Send>);
+
+type Constructor = Box<dyn Fn(&IpDescriptor, &dyn Backend) -> Result<Opened> + Send + Sync>;
+
+struct Entry {
+    vendor: String,
+    name: String,
+    constructor: Constructor,
+}
+
+fn opened<T: Any + Send>(driver: T) -> Opened {
+    (type_name::<T>(), Box::new(driver))
+}
+
+/// The MM2S channel, or the S2MM channel of a VDMA built without MM2S.
+fn open_axi_vdma(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Opened> {
+    if desc.param_bool_or("C_INCLUDE_MM2S", true)? {
+        AxiVdmaMM2S::from_desc_with_backend(desc, backend).map(opened)
+    } else {
+        AxiVdmaS2MM::from_desc_with_backend(desc, backend).map(opened)
+    }
+}
+
+/// Which driver to open for which IP.
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// Every driver of this crate.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register("xilinx.com", "axi_dma", AxiDma::from_desc_with_backend)
+            .push("xilinx.com", "axi_vdma", Box::new(open_axi_vdma))
+            .register(
+                "xilinx.com",
+                "v_frmbuf_rd",
+                VideoFrameBufRead::from_desc_with_backend,
+            )
+            .register(
+                "xilinx.com",
+                "v_frmbuf_wr",
+                VideoFrameBufWrite::from_desc_with_backend,
+            )
+            .register(
+                "xilinx.com",
+                "v_proc_ss",
+                VideoProcSubsystemCsc::from_desc_with_backend,
+            )
+            .register(
+                "xilinx.com",
+                "axis_switch",
+                AxisSwitch::from_desc_with_backend,
+            )
+            .register("xilinx.com", "axi_gpio", AxiGpio::from_desc_with_backend)
+            .register(
+                "xilinx.com",
+                "bird_eye_view",
+                BirdEyeViewHW::from_desc_with_backend,
+            )
+            .register("xilinx.com", "yolo_*", Yolo::from_desc_with_backend)
+            .register(
+                "slab",
+                "umv_lane_detector",
+                UmvLaneDetector::from_desc_with_backend,
+            )
+            .register(
+                "slab",
+                "umv_motor_controller",
+                UmvMotorController::from_desc_with_backend,
+            );
+        registry
+    }
+
+    pub fn empty() -> Self {
+        Registry {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Opens IPs of `vendor` whose VLNV name matches the glob `name` with
+    /// `constructor`. Entries registered later take precedence.
+    pub fn register<T, F>(&mut self, vendor: &str, name: &str, constructor: F) -> &mut Self
+    where
+        T: Any + Send,
+        F: Fn(&IpDescriptor, &dyn Backend) -> Result<T> + Send + Sync + 'static,
+    {
+        self.push(
+            vendor,
+            name,
+            Box::new(move |desc, backend| constructor(desc, backend).map(opened)),
+        )
+    }
+
+    fn push(&mut self, vendor: &str, name: &str, constructor: Constructor) -> &mut Self {
+        self.entries.push(Entry {
+            vendor: vendor.to_string(),
+            name: name.to_string(),
+            constructor,
+        });
+        self
+    }
+
+    fn find(&self, desc: &IpDescriptor) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.vendor == desc.vlnv.vendor && glob_match(&e.name, &desc.vlnv.name))
+    }
+}
+
+enum Slot {
+    Open(&'static str, Box<dyn Any + Send>),
+    Taken(&'static str),
+    Failed(XipError),
+    Unknown,
+}
+
+/// The drivers of every IP of a `HwInfo`, by hierarchical path.
+///
+/// Opening a driver that fails does not fail the whole board; the error is
+/// kept and returned by `get` for that path, and listed by `report`.
+pub struct Board {
+    ips: BTreeMap<String, (IpDescriptor, Slot)>,
+}
+
+impl Board {
+    pub fn new(hw_info: &HwInfo) -> Self {
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &HwInfo, backend: &dyn Backend) -> Self {
+        Self::with_registry(hw_info, &Registry::new(), backend)
+    }
+
+    pub fn with_registry(hw_info: &HwInfo, registry: &Registry, backend: &dyn Backend) -> Self {
+        let mut ips = BTreeMap::new();
+        for desc in hw_info.iter() {
+            let slot = match registry.find(desc) {
+                Some(entry) => match (entry.constructor)(desc, backend) {
+                    Ok((name, driver)) => Slot::Open(name, driver),
+                    Err(e) => Slot::Failed(e),
+                },
+                None => Slot::Unknown,
+            };
+            ips.insert(desc.path.clone(), (desc.clone(), slot));
+        }
+        Board { ips }
+    }
+
+    /// The one driver of type `T` whose path matches the glob `pattern`.
+    /// `*` and `?` do not match `/`; `**` matches across levels.
+    pub fn get<T: Any>(&self, pattern: &str) -> Result<&T> {
+        let path = self.find::<T>(pattern)?;
+        match &self.ips[&path].1 {
+            Slot::Open(_, driver) => Ok(driver.downcast_ref().unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn get_mut<T: Any>(&mut self, pattern: &str) -> Result<&mut T> {
+        let path = self.find::<T>(pattern)?;
+        match &mut self.ips.get_mut(&path).unwrap().1 {
+            Slot::Open(_, driver) => Ok(driver.downcast_mut().unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Moves the driver out of the board.
+    pub fn take<T: Any>(&mut self, pattern: &str) -> Result<T> {
+        let path = self.find::<T>(pattern)?;
+        let slot = &mut self.ips.get_mut(&path).unwrap().1;
+        match std::mem::replace(slot, Slot::Taken(type_name::<T>())) {
+            Slot::Open(_, driver) => Ok(*driver.downcast().unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Paths of every open driver of type `T` matching `pattern`.
+    pub fn paths<T: Any>(&self, pattern: &str) -> Vec<&str> {
+        self.ips
+            .iter()
+            .filter(|(path, (_, slot))| {
+                glob_match(pattern, path)
+                    && matches!(slot, Slot::Open(_, driver) if driver.is::<T>())
+            })
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    pub fn desc(&self, path: &str) -> Option<&IpDescriptor> {
+        self.ips.get(path).map(|(desc, _)| desc)
+    }
+
+    /// IPs no registered driver handles.
+    pub fn unknown(&self) -> Vec<&IpDescriptor> {
+        self.ips
+            .values()
+            .filter(|(_, slot)| matches!(slot, Slot::Unknown))
+            .map(|(desc, _)| desc)
+            .collect()
+    }
+
+    /// IPs whose driver could not be opened, with the reason.
+    pub fn failed(&self) -> Vec<(&IpDescriptor, &XipError)> {
+        self.ips
+            .values()
+            .filter_map(|(desc, slot)| match slot {
+                Slot::Failed(e) => Some((desc, e)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fails if any IP is unknown or could not be opened.
+    pub fn ensure_all_open(&self) -> Result<()> {
+        ensure!(
+            self.unknown().is_empty() && self.failed().is_empty(),
+            Driver,
+            "some IPs have no driver:\n{}",
+            self.report()
+        );
+        Ok(())
+    }
+
+    /// One line per IP: the driver, or why there is none.
+    pub fn report(&self) -> BoardReport<'_> {
+        BoardReport(self)
+    }
+
+    fn find<T: Any>(&self, pattern: &str) -> Result<String> {
+        let matched: Vec<(&String, &(IpDescriptor, Slot))> = self
+            .ips
+            .iter()
+            .filter(|(path, _)| glob_match(pattern, path))
+            .collect();
+        let open: Vec<&String> = matched
+            .iter()
+            .filter(|(_, (_, slot))| matches!(slot, Slot::Open(_, driver) if driver.is::<T>()))
+            .map(|(path, _)| *path)
+            .collect();
+        match open.as_slice() {
+            [path] => return Ok(path.to_string()),
+            [] => {}
+            paths => bail!(
+                Driver,
+                "{} matches several {}: {}",
+                pattern,
+                type_name::<T>(),
+                paths
+                    .iter()
+                    .map(|p| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+        // Explain why nothing usable matched.
+        let reasons: Vec<String> = matched
+            .iter()
+            .map(|(path, (desc, slot))| match slot {
+                Slot::Open(driver, _) => format!("{} is a {}", path, driver),
+                Slot::Taken(driver) => format!("{} ({}) was already taken", path, driver),
+                Slot::Failed(e) => format!("{} could not be opened: {}", path, e),
+                Slot::Unknown => format!("{} ({}) has no driver", path, desc.vlnv),
+            })
+            .collect();
+        if reasons.is_empty() {
+            bail!(Driver, "no IP matches {}", pattern)
+        } else {
+            bail!(
+                Driver,
+                "no {} matches {}: {}",
+                type_name::<T>(),
+                pattern,
+                reasons.join("; ")
+            )
+        }
+    }
+}
+
+pub struct BoardReport<'a>(&'a Board);
+
+impl fmt::Display for BoardReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (path, (desc, slot)) in &self.0.ips {
+            match slot {
+                Slot::Open(driver, _) => writeln!(f, "{}: {}", path, driver)?,
+                Slot::Taken(driver) => writeln!(f, "{}: {} (taken)", path, driver)?,
+                Slot::Failed(e) => writeln!(f, "{}: failed to open: {}", path, e)?,
+                Slot::Unknown => writeln!(f, "{}: unknown IP {}", path, desc.vlnv)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `*` and `?` stay within one path level, `**` crosses levels.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            (0..=text.len()).any(|i| glob_match_bytes(&pattern[2..], &text[i..]))
+        }
+        Some(b'*') => {
+            let level = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=level).any(|i| glob_match_bytes(&pattern[1..], &text[i..]))
+        }
+        Some(b'?') => {
+            matches!(text.first(), Some(&c) if c != b'/')
+                && glob_match_bytes(&pattern[1..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match_bytes(&pattern[1..], &text[1..]),
+    }
+}
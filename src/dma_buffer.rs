@@ -0,0 +1,165 @@
+//! Zero-copy access to u-dma-buf memory.
+//!
+//! A `DmaBuffer` hands out slices directly over the buffer's CPU mapping, so
+//! frames can be rendered or decoded in place and handed to the PL without
+//! an intermediate `Vec`. Call `sync_for_device` after filling a buffer and
+//! `sync_for_cpu` before reading what the PL wrote; both are no-ops on the
+//! uncached buffers the drivers open by default.
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::backend::{Backend, BufIo, DeviceBackend};
+use crate::error::{ensure, Result};
+
+/// Plain data that any bit pattern in DMA memory is a valid value of.
+///
+/// # Safety
+/// Implementors must have no padding, no invalid bit patterns and no
+/// pointers.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Low and high register words of `phys_addr` for an IP whose address bus
+/// is `addr_width` bits wide. Fails if the IP cannot reach the address.
+pub fn split_phys_addr(phys_addr: usize, addr_width: u32) -> Result<(u32, u32)> {
+    let addr = phys_addr as u64;
+    ensure!(
+        addr_width >= 64 || addr >> addr_width == 0,
+        Unsupported,
+        "physical address 0x{:X} is beyond the {}-bit address bus of the IP",
+        addr,
+        addr_width
+    );
+    Ok((addr as u32, (addr >> 32) as u32))
+}
+
+/// A physically contiguous DMA buffer.
+///
+/// Dereferences to `dyn BufIo` for register-style and copying access.
+pub struct DmaBuffer {
+    io: Box<dyn BufIo>,
+}
+
+impl DmaBuffer {
+    /// Opens the u-dma-buf device `name`, uncached.
+    pub fn new(name: &str) -> Result<Self> {
+        Self::with_backend(name, &DeviceBackend)
+    }
+
+    pub fn with_backend(name: &str, backend: &dyn Backend) -> Result<Self> {
+        Ok(Self::from_io(backend.open_udmabuf(name, false)?))
+    }
+
+    pub fn from_io(io: Box<dyn BufIo>) -> Self {
+        DmaBuffer { io }
+    }
+
+    pub fn phys_addr(&self) -> usize {
+        self.io.phys_addr()
+    }
+
+    pub fn size(&self) -> usize {
+        self.io.size()
+    }
+
+    /// The whole buffer as `T`s; a partial trailing element is left out.
+    ///
+    /// # Panics
+    /// If the mapping is not aligned for `T`, which page-aligned u-dma-buf
+    /// mappings always are.
+    pub fn as_slice<T: Pod>(&self) -> &[T] {
+        self.slice(0, self.size() / core::mem::size_of::<T>())
+            .expect("DmaBuffer: mapping is not aligned")
+    }
+
+    /// See `as_slice`.
+    pub fn as_mut_slice<T: Pod>(&mut self) -> &mut [T] {
+        let len = self.size() / core::mem::size_of::<T>();
+        self.slice_mut(0, len)
+            .expect("DmaBuffer: mapping is not aligned")
+    }
+
+    /// `len` elements of `T` starting `offset` bytes into the buffer.
+    pub fn slice<T: Pod>(&self, offset: usize, len: usize) -> Result<&[T]> {
+        let ptr = self.checked_ptr::<T>(offset, len)?;
+        // The range is inside the mapping, aligned, and `T` is `Pod`.
+        Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+    }
+
+    pub fn slice_mut<T: Pod>(&mut self, offset: usize, len: usize) -> Result<&mut [T]> {
+        let ptr = self.checked_ptr::<T>(offset, len)?;
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, len) })
+    }
+
+    fn checked_ptr<T: Pod>(&self, offset: usize, len: usize) -> Result<*mut T> {
+        let bytes = len.checked_mul(core::mem::size_of::<T>());
+        ensure!(
+            matches!(bytes.and_then(|b| b.checked_add(offset)), Some(end) if end <= self.size()),
+            InvalidArgument,
+            "DmaBuffer: {} elements at 0x{:X} exceed the buffer (0x{:X} bytes)",
+            len,
+            offset,
+            self.size()
+        );
+        let ptr = self.io.as_mut_ptr().wrapping_add(offset);
+        ensure!(
+            (ptr as usize).is_multiple_of(core::mem::align_of::<T>()),
+            InvalidArgument,
+            "DmaBuffer: offset 0x{:X} is not aligned for {}",
+            offset,
+            std::any::type_name::<T>()
+        );
+        Ok(ptr as *mut T)
+    }
+
+    /// Call before reading `offset..offset + len` after the PL wrote it.
+    pub fn sync_for_cpu(&self, offset: usize, len: usize) -> Result<()> {
+        self.check_range(offset, len)?;
+        self.io.sync_for_cpu(offset, len)
+    }
+
+    /// Call after writing `offset..offset + len` and before the PL reads it.
+    pub fn sync_for_device(&self, offset: usize, len: usize) -> Result<()> {
+        self.check_range(offset, len)?;
+        self.io.sync_for_device(offset, len)
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<()> {
+        ensure!(
+            matches!(offset.checked_add(len), Some(end) if end <= self.size()),
+            InvalidArgument,
+            "DmaBuffer: range 0x{:X}+0x{:X} exceeds the buffer (0x{:X} bytes)",
+            offset,
+            len,
+            self.size()
+        );
+        Ok(())
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = dyn BufIo;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.io
+    }
+}
+
+impl fmt::Debug for DmaBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaBuffer")
+            .field("phys_addr", &format_args!("0x{:X}", self.phys_addr()))
+            .field("size", &self.size())
+            .finish()
+    }
+}
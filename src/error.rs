@@ -0,0 +1,106 @@
+//! Error type of every driver.
+//!
+//! `XipError` implements `std::error::Error + Send + Sync`, so `?` converts
+//! it into `anyhow::Error` in application code.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::timeout::TimeoutError;
+
+pub type Result<T, E = XipError> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum XipError {
+    /// hwinfo, .hwh or .xsa is malformed, or describes an IP the driver
+    /// does not support.
+    #[error("{0}")]
+    HwDescription(String),
+    /// A UIO or u-dma-buf device cannot be found, mapped or waited on.
+    #[error("{0}")]
+    Mapping(String),
+    #[error("DMA Internal Error (transfer length 0?), DMASR = 0x{status:08X}")]
+    DmaInternal { status: u32 },
+    #[error("DMA Slave Error (cannot access memory map interface), DMASR = 0x{status:08X}")]
+    DmaSlave { status: u32 },
+    #[error("DMA Decode Error (invalid address), DMASR = 0x{status:08X}")]
+    DmaDecode { status: u32 },
+    #[error("DMA SG Internal Error (descriptor already completed), DMASR = 0x{status:08X}")]
+    DmaSgInternal { status: u32 },
+    #[error("DMA SG Slave Error (cannot access descriptor), DMASR = 0x{status:08X}")]
+    DmaSgSlave { status: u32 },
+    #[error("DMA SG Decode Error (invalid descriptor address), DMASR = 0x{status:08X}")]
+    DmaSgDecode { status: u32 },
+    /// A pixel format or frame size the IP is not configured for.
+    #[error("{0}")]
+    Format(String),
+    #[error(transparent)]
+    Timeout(#[from] TimeoutError),
+    #[error("Array size too large ({size}/{capacity})")]
+    BufferTooLarge { size: usize, capacity: usize },
+    /// The IP was built without the feature, e.g. a DMA channel.
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// The call does not fit the driver's state, e.g. a stopped channel.
+    #[error("{0}")]
+    InvalidState(String),
+    /// `Board` has no usable driver for the requested IP.
+    #[error("{0}")]
+    Driver(String),
+    #[error("cannot {operation} {}", path.display())]
+    Io {
+        /// "open", "read" or "write".
+        operation: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl XipError {
+    /// Prefixes the message of the message-only variants with `context`.
+    pub(crate) fn context(self, context: impl fmt::Display) -> Self {
+        let prefix = |msg: String| format!("{}: {}", context, msg);
+        match self {
+            XipError::HwDescription(msg) => XipError::HwDescription(prefix(msg)),
+            XipError::Mapping(msg) => XipError::Mapping(prefix(msg)),
+            XipError::Format(msg) => XipError::Format(prefix(msg)),
+            XipError::Unsupported(msg) => XipError::Unsupported(prefix(msg)),
+            XipError::InvalidArgument(msg) => XipError::InvalidArgument(prefix(msg)),
+            XipError::InvalidState(msg) => XipError::InvalidState(prefix(msg)),
+            XipError::Driver(msg) => XipError::Driver(prefix(msg)),
+            e => e,
+        }
+    }
+
+    pub(crate) fn io(operation: &'static str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        XipError::Io {
+            operation,
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+/// `anyhow::ensure!` for the message variants: `ensure!(cond, Format, "...", args)`.
+macro_rules! ensure {
+    ($cond:expr, $kind:ident, $($arg:tt)+) => {
+        let ok: bool = $cond;
+        if !ok {
+            return Err($crate::error::XipError::$kind(format!($($arg)+)));
+        }
+    };
+}
+
+macro_rules! bail {
+    ($kind:ident, $($arg:tt)+) => {
+        return Err($crate::error::XipError::$kind(format!($($arg)+)))
+    };
+}
+
+pub(crate) use {bail, ensure};
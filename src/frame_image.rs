@@ -0,0 +1,233 @@
+//! Conversions between frames in memory and `image` buffers.
+//!
+//! Frames are laid out the way `VideoFrameBufWrite::read_frame` returns them
+//! and `VideoFrameBufRead::write_frame` takes them: planes one after the
+//! other, lines tightly packed. YUV formats use BT.601 limited range.
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+
+use crate::error::{bail, ensure, Result, XipError};
+use crate::frame_pool::FrameSpec;
+use crate::v_frmbuf::ColorFormat;
+
+/// A frame converted to the closest `image` buffer type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameImage {
+    /// RGB and BGR formats, padding dropped, and YUV formats converted.
+    Rgb(RgbImage),
+    /// Formats with an alpha channel.
+    Rgba(RgbaImage),
+    Luma(GrayImage),
+}
+
+impl FrameImage {
+    /// Converts a frame of `format`. Fails with `Unsupported` for formats
+    /// without a conversion.
+    pub fn from_frame(format: ColorFormat, width: u32, height: u32, frame: &[u8]) -> Result<Self> {
+        let size = FrameSpec::new(width, height, format).size();
+        ensure!(
+            frame.len() == size,
+            InvalidArgument,
+            "{}x{} {} frame is {} bytes, got {}",
+            width,
+            height,
+            format,
+            size,
+            frame.len()
+        );
+        let line = format.line_bytes(width) as usize;
+        let at = |x: u32, y: u32, bytes: usize| &frame[y as usize * line + x as usize * bytes..];
+        let img = match format {
+            ColorFormat::Rgb8 => FrameImage::Rgb(from_raw(width, height, frame)?),
+            ColorFormat::Rgba8 => FrameImage::Rgba(from_raw(width, height, frame)?),
+            ColorFormat::Y8 => FrameImage::Luma(from_raw(width, height, frame)?),
+            ColorFormat::Bgr8 => FrameImage::Rgb(ImageBuffer::from_fn(width, height, |x, y| {
+                let p = at(x, y, 3);
+                Rgb([p[2], p[1], p[0]])
+            })),
+            ColorFormat::Rgbx8 => FrameImage::Rgb(ImageBuffer::from_fn(width, height, |x, y| {
+                let p = at(x, y, 4);
+                Rgb([p[0], p[1], p[2]])
+            })),
+            ColorFormat::Bgrx8 => FrameImage::Rgb(ImageBuffer::from_fn(width, height, |x, y| {
+                let p = at(x, y, 4);
+                Rgb([p[2], p[1], p[0]])
+            })),
+            ColorFormat::Bgra8 => FrameImage::Rgba(ImageBuffer::from_fn(width, height, |x, y| {
+                let p = at(x, y, 4);
+                Rgba([p[2], p[1], p[0], p[3]])
+            })),
+            ColorFormat::Yuyv8 | ColorFormat::Uyvy8 => {
+                let (y_at, u_at, v_at) = match format {
+                    ColorFormat::Yuyv8 => (0, 1, 3),
+                    _ => (1, 0, 2),
+                };
+                FrameImage::Rgb(ImageBuffer::from_fn(width, height, |x, y| {
+                    let p = at(x & !1, y, 2);
+                    let luma = p[y_at + 2 * (x as usize & 1)];
+                    yuv_to_rgb(luma, p[u_at], p[v_at])
+                }))
+            }
+            ColorFormat::YUv8 | ColorFormat::YUv8420 => {
+                ensure!(
+                    width.is_multiple_of(2),
+                    Format,
+                    "{} frames need an even width, got {}",
+                    format,
+                    width
+                );
+                let chroma = &frame[line * height as usize..];
+                let sub = if format == ColorFormat::YUv8420 { 2 } else { 1 };
+                FrameImage::Rgb(ImageBuffer::from_fn(width, height, |x, y| {
+                    let uv = &chroma[(y / sub) as usize * line + (x & !1) as usize..];
+                    yuv_to_rgb(at(x, y, 1)[0], uv[0], uv[1])
+                }))
+            }
+            _ => bail!(
+                Unsupported,
+                "{} frames can't be converted to an image",
+                format
+            ),
+        };
+        Ok(img)
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            FrameImage::Rgb(img) => img.dimensions(),
+            FrameImage::Rgba(img) => img.dimensions(),
+            FrameImage::Luma(img) => img.dimensions(),
+        }
+    }
+
+    pub fn into_rgb8(self) -> RgbImage {
+        match self {
+            FrameImage::Rgb(img) => img,
+            img => DynamicImage::from(img).into_rgb8(),
+        }
+    }
+}
+
+impl From<FrameImage> for DynamicImage {
+    fn from(img: FrameImage) -> Self {
+        match img {
+            FrameImage::Rgb(img) => DynamicImage::ImageRgb8(img),
+            FrameImage::Rgba(img) => DynamicImage::ImageRgba8(img),
+            FrameImage::Luma(img) => DynamicImage::ImageLuma8(img),
+        }
+    }
+}
+
+/// Converts `img` into a frame of `format`, the reverse of
+/// `FrameImage::from_frame`. Chroma is averaged over the pixels sharing it.
+pub fn image_to_frame(format: ColorFormat, img: &DynamicImage) -> Result<Vec<u8>> {
+    let (width, height) = (img.width(), img.height());
+    let frame = match format {
+        ColorFormat::Rgb8 => img.to_rgb8().into_raw(),
+        ColorFormat::Rgba8 => img.to_rgba8().into_raw(),
+        ColorFormat::Y8 => img.to_luma8().into_raw(),
+        ColorFormat::Bgr8 => img
+            .to_rgb8()
+            .pixels()
+            .flat_map(|p| [p[2], p[1], p[0]])
+            .collect(),
+        ColorFormat::Rgbx8 => img
+            .to_rgb8()
+            .pixels()
+            .flat_map(|p| [p[0], p[1], p[2], 0])
+            .collect(),
+        ColorFormat::Bgrx8 => img
+            .to_rgb8()
+            .pixels()
+            .flat_map(|p| [p[2], p[1], p[0], 0])
+            .collect(),
+        ColorFormat::Bgra8 => img
+            .to_rgba8()
+            .pixels()
+            .flat_map(|p| [p[2], p[1], p[0], p[3]])
+            .collect(),
+        ColorFormat::Yuyv8 | ColorFormat::Uyvy8 => {
+            let rgb = img.to_rgb8();
+            let mut frame = Vec::with_capacity(format.line_bytes(width) as usize * height as usize);
+            for y in 0..height {
+                for x in (0..width).step_by(2) {
+                    let p0 = rgb_to_yuv(rgb.get_pixel(x, y));
+                    let p1 = rgb_to_yuv(rgb.get_pixel((x + 1).min(width - 1), y));
+                    let (u, v) = (to_u8((p0[1] + p1[1]) / 2.0), to_u8((p0[2] + p1[2]) / 2.0));
+                    let (y0, y1) = (to_u8(p0[0]), to_u8(p1[0]));
+                    match format {
+                        ColorFormat::Yuyv8 => frame.extend([y0, u, y1, v]),
+                        _ => frame.extend([u, y0, v, y1]),
+                    }
+                }
+            }
+            frame
+        }
+        ColorFormat::YUv8 | ColorFormat::YUv8420 => {
+            ensure!(
+                width.is_multiple_of(2),
+                Format,
+                "{} frames need an even width, got {}",
+                format,
+                width
+            );
+            let rgb = img.to_rgb8();
+            let mut frame: Vec<u8> = rgb.pixels().map(|p| to_u8(rgb_to_yuv(p)[0])).collect();
+            let sub = if format == ColorFormat::YUv8420 { 2 } else { 1 };
+            for y in (0..height).step_by(sub as usize) {
+                for x in (0..width).step_by(2) {
+                    let mut uv = [0.0; 2];
+                    let rows = y..(y + sub).min(height);
+                    let n = (rows.len() * 2) as f32;
+                    for yy in rows {
+                        for xx in [x, x + 1] {
+                            let p = rgb_to_yuv(rgb.get_pixel(xx, yy));
+                            uv[0] += p[1];
+                            uv[1] += p[2];
+                        }
+                    }
+                    frame.extend([to_u8(uv[0] / n), to_u8(uv[1] / n)]);
+                }
+            }
+            frame
+        }
+        _ => bail!(
+            Unsupported,
+            "images can't be converted to {} frames",
+            format
+        ),
+    };
+    Ok(frame)
+}
+
+fn from_raw<P: image::Pixel<Subpixel = u8>>(
+    width: u32,
+    height: u32,
+    frame: &[u8],
+) -> Result<ImageBuffer<P, Vec<u8>>> {
+    ImageBuffer::from_raw(width, height, frame.to_vec())
+        .ok_or_else(|| XipError::Format("Can't convert to image".to_string()))
+}
+
+fn to_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> Rgb<u8> {
+    let y = 1.164 * (y as f32 - 16.0);
+    let (u, v) = (u as f32 - 128.0, v as f32 - 128.0);
+    Rgb([
+        to_u8(y + 1.596 * v),
+        to_u8(y - 0.392 * u - 0.813 * v),
+        to_u8(y + 2.017 * u),
+    ])
+}
+
+fn rgb_to_yuv(p: &Rgb<u8>) -> [f32; 3] {
+    let [r, g, b] = p.0.map(|c| c as f32);
+    [
+        0.257 * r + 0.504 * g + 0.098 * b + 16.0,
+        -0.148 * r - 0.291 * g + 0.439 * b + 128.0,
+        0.439 * r - 0.368 * g - 0.071 * b + 128.0,
+    ]
+}
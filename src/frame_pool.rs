@@ -0,0 +1,400 @@
+//! Frame buffers shared between IPs.
+//!
+//! A `FramePool` owns the udmabufs one IP fills and others read. Handles are
+//! clones of the same pool, so it lives as long as any attached driver.
+//!
+//! Writers take a buffer with `acquire_write`, fill it and hand it over with
+//! `publish`; readers take the latest published frame with `acquire_read` and
+//! give it back with `release_read`. A buffer being filled is never handed to
+//! a reader, and a buffer a reader holds is never handed to a writer, so with
+//! two or more buffers the IPs ping-pong without tearing.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::backend::{Backend, DeviceBackend};
+use crate::dma_buffer::DmaBuffer;
+use crate::error::{bail, ensure, Result, XipError};
+use crate::v_frmbuf::ColorFormat;
+
+/// Memory layout of the frames in a pool, or the one an IP expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameSpec {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes of pixel data in one line.
+    pub line_bytes: u32,
+    /// Bytes between the starts of two lines.
+    pub stride: u32,
+    /// `None` for IPs that only know the pixel size, which then match any
+    /// format with the same line size.
+    pub format: Option<ColorFormat>,
+}
+
+impl FrameSpec {
+    /// Frames of `format`, lines tightly packed.
+    pub fn new(width: u32, height: u32, format: ColorFormat) -> Self {
+        let line_bytes = format.line_bytes(width);
+        FrameSpec {
+            width,
+            height,
+            line_bytes,
+            stride: line_bytes,
+            format: Some(format),
+        }
+    }
+
+    /// Single-plane frames of `bytes_per_pix` byte pixels, lines tightly
+    /// packed.
+    pub fn packed(width: u32, height: u32, bytes_per_pix: u32) -> Self {
+        FrameSpec {
+            width,
+            height,
+            line_bytes: width * bytes_per_pix,
+            stride: width * bytes_per_pix,
+            format: None,
+        }
+    }
+
+    pub fn with_stride(self, stride: u32) -> Self {
+        FrameSpec { stride, ..self }
+    }
+
+    fn planes(&self) -> usize {
+        self.format.map_or(1, ColorFormat::planes)
+    }
+
+    fn plane_lines(&self, plane: usize) -> usize {
+        self.format
+            .map_or(self.height, |fmt| fmt.plane_height(plane, self.height)) as usize
+    }
+
+    /// Bytes of one frame in memory, planes one after the other.
+    pub fn size(&self) -> usize {
+        (0..self.planes())
+            .map(|i| self.stride as usize * self.plane_lines(i))
+            .sum()
+    }
+
+    /// Fails with `Format` unless an IP expecting `other` can use frames laid
+    /// out as `self`.
+    pub fn check(&self, other: &FrameSpec) -> Result<()> {
+        if let (Some(a), Some(b)) = (self.format, other.format) {
+            ensure!(a == b, Format, "{} frames, the pool holds {}", b, a);
+        }
+        ensure!(
+            (self.width, self.height) == (other.width, other.height),
+            Format,
+            "{}x{} frames, the pool holds {}x{}",
+            other.width,
+            other.height,
+            self.width,
+            self.height
+        );
+        ensure!(
+            self.line_bytes == other.line_bytes,
+            Format,
+            "lines of {} bytes, the pool holds lines of {}",
+            other.line_bytes,
+            self.line_bytes
+        );
+        ensure!(
+            self.stride == other.stride,
+            Format,
+            "stride {}, the pool uses {}",
+            other.stride,
+            self.stride
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for FrameSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some(format) = self.format {
+            write!(f, " {}", format)?;
+        }
+        write!(f, " stride {}", self.stride)
+    }
+}
+
+/// A frame taken with `FramePool::acquire_read`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolFrame {
+    pub index: usize,
+    /// Frames published before this one. Gaps mean frames were replaced
+    /// before anyone read them.
+    pub sequence: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BufState {
+    Free,
+    Writing,
+    /// The latest published frame.
+    Ready(u64),
+}
+
+struct PoolState {
+    spec: FrameSpec,
+    buffers: Vec<DmaBuffer>,
+    states: Vec<BufState>,
+    readers: Vec<u32>,
+    last_written: usize,
+    sequence: u64,
+}
+
+impl PoolState {
+    fn check_index(&self, index: usize) -> Result<()> {
+        ensure!(
+            index < self.buffers.len(),
+            InvalidArgument,
+            "FramePool: buffer {} out of range, the pool has {}",
+            index,
+            self.buffers.len()
+        );
+        Ok(())
+    }
+}
+
+/// Reference-counted set of frame buffers shared by writer and reader IPs.
+#[derive(Clone)]
+pub struct FramePool {
+    state: Arc<Mutex<PoolState>>,
+}
+
+impl FramePool {
+    /// Opens the u-dma-buf devices `names`, uncached.
+    pub fn new(spec: FrameSpec, names: &[&str]) -> Result<Self> {
+        Self::with_backend(spec, names, &DeviceBackend)
+    }
+
+    pub fn with_backend(spec: FrameSpec, names: &[&str], backend: &dyn Backend) -> Result<Self> {
+        let mut buffers = Vec::new();
+        for name in names {
+            buffers.push(DmaBuffer::from_io(backend.open_udmabuf(name, false)?));
+        }
+        Self::from_buffers(spec, buffers)
+    }
+
+    /// Needs two buffers at least, each holding a whole frame.
+    pub fn from_buffers(spec: FrameSpec, buffers: Vec<DmaBuffer>) -> Result<Self> {
+        ensure!(
+            buffers.len() >= 2,
+            InvalidArgument,
+            "FramePool needs 2 buffers at least, got {}",
+            buffers.len()
+        );
+        ensure!(
+            spec.stride >= spec.line_bytes,
+            InvalidArgument,
+            "FramePool: stride {} is shorter than a line of {} bytes",
+            spec.stride,
+            spec.line_bytes
+        );
+        for buf in &buffers {
+            if spec.size() > buf.size() {
+                return Err(XipError::BufferTooLarge {
+                    size: spec.size(),
+                    capacity: buf.size(),
+                });
+            }
+        }
+        let n = buffers.len();
+        Ok(FramePool {
+            state: Arc::new(Mutex::new(PoolState {
+                spec,
+                buffers,
+                states: vec![BufState::Free; n],
+                readers: vec![0; n],
+                last_written: n - 1,
+                sequence: 0,
+            })),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn spec(&self) -> FrameSpec {
+        self.lock().spec
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Handles to this pool, including this one.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.state)
+    }
+
+    /// A new handle for an IP expecting frames laid out as `spec`.
+    pub fn attach(&self, spec: &FrameSpec) -> Result<FramePool> {
+        self.spec()
+            .check(spec)
+            .map_err(|e| e.context("FramePool::attach"))?;
+        Ok(self.clone())
+    }
+
+    pub fn phys_addr(&self, index: usize) -> Result<usize> {
+        let state = self.lock();
+        state.check_index(index)?;
+        Ok(state.buffers[index].phys_addr())
+    }
+
+    /// Physical address of every plane of buffer `index`.
+    pub fn plane_addrs(&self, index: usize) -> Result<Vec<usize>> {
+        let state = self.lock();
+        state.check_index(index)?;
+        let spec = state.spec;
+        let mut addr = state.buffers[index].phys_addr();
+        let mut addrs = Vec::new();
+        for i in 0..spec.planes() {
+            addrs.push(addr);
+            addr += spec.stride as usize * spec.plane_lines(i);
+        }
+        Ok(addrs)
+    }
+
+    /// A buffer to fill. Buffers nobody uses come first; failing that, the
+    /// latest frame is overwritten if no reader has taken it.
+    pub fn acquire_write(&self) -> Result<usize> {
+        let mut state = self.lock();
+        let n = state.states.len();
+        let start = state.last_written + 1;
+        let unread = |i: &usize| state.readers[*i] == 0;
+        let order = || (0..n).map(|i| (start + i) % n).filter(unread);
+        let index = order()
+            .find(|&i| state.states[i] == BufState::Free)
+            .or_else(|| order().find(|&i| matches!(state.states[i], BufState::Ready(_))));
+        let Some(index) = index else {
+            bail!(InvalidState, "FramePool: all {} buffers are in use", n);
+        };
+        state.states[index] = BufState::Writing;
+        state.last_written = index;
+        Ok(index)
+    }
+
+    /// Makes the frame in buffer `index` the latest one and returns its
+    /// sequence number. The previous latest frame is dropped once its
+    /// readers are done with it.
+    pub fn publish(&self, index: usize) -> Result<u64> {
+        let mut state = self.lock();
+        ensure!(
+            state.states.get(index) == Some(&BufState::Writing),
+            InvalidState,
+            "FramePool: buffer {} is not being written",
+            index
+        );
+        for s in state.states.iter_mut() {
+            if matches!(s, BufState::Ready(_)) {
+                *s = BufState::Free;
+            }
+        }
+        let sequence = state.sequence;
+        state.states[index] = BufState::Ready(sequence);
+        state.sequence += 1;
+        Ok(sequence)
+    }
+
+    /// Gives back a buffer taken with `acquire_write` without publishing it.
+    pub fn release_write(&self, index: usize) -> Result<()> {
+        let mut state = self.lock();
+        ensure!(
+            state.states.get(index) == Some(&BufState::Writing),
+            InvalidState,
+            "FramePool: buffer {} is not being written",
+            index
+        );
+        state.states[index] = BufState::Free;
+        Ok(())
+    }
+
+    /// The latest published frame, held until `release_read`. `None` if
+    /// nothing was published yet.
+    pub fn acquire_read(&self) -> Option<PoolFrame> {
+        let mut state = self.lock();
+        let frame = state
+            .states
+            .iter()
+            .enumerate()
+            .find_map(|(index, s)| match s {
+                BufState::Ready(sequence) => Some(PoolFrame {
+                    index,
+                    sequence: *sequence,
+                }),
+                _ => None,
+            })?;
+        state.readers[frame.index] += 1;
+        Some(frame)
+    }
+
+    pub fn release_read(&self, index: usize) -> Result<()> {
+        let mut state = self.lock();
+        ensure!(
+            state.readers.get(index).is_some_and(|&r| r > 0),
+            InvalidState,
+            "FramePool: buffer {} is not being read",
+            index
+        );
+        state.readers[index] -= 1;
+        Ok(())
+    }
+
+    /// Copies a frame with its planes one after the other, lines tightly
+    /// packed, into buffer `index` for the PL to read.
+    pub fn write_frame(&self, index: usize, frame: &[u8]) -> Result<()> {
+        let mut state = self.lock();
+        state.check_index(index)?;
+        let spec = state.spec;
+        let lines: usize = (0..spec.planes()).map(|i| spec.plane_lines(i)).sum();
+        let line = spec.line_bytes as usize;
+        ensure!(
+            frame.len() == line * lines,
+            InvalidArgument,
+            "FramePool: frame is {} bytes, {} expected",
+            frame.len(),
+            line * lines
+        );
+        let size = spec.size();
+        let buf = &mut state.buffers[index];
+        let dst = buf.slice_mut::<u8>(0, size)?;
+        for (y, src) in frame.chunks(line).enumerate() {
+            dst[y * spec.stride as usize..][..line].copy_from_slice(src);
+        }
+        buf.sync_for_device(0, size)
+    }
+
+    /// Copies buffer `index` out the way `write_frame` takes it.
+    pub fn read_frame(&self, index: usize) -> Result<Vec<u8>> {
+        let state = self.lock();
+        state.check_index(index)?;
+        let spec = state.spec;
+        let buf = &state.buffers[index];
+        buf.sync_for_cpu(0, spec.size())?;
+        let src = buf.slice::<u8>(0, spec.size())?;
+        let line = spec.line_bytes as usize;
+        Ok(src
+            .chunks(spec.stride as usize)
+            .flat_map(|row| &row[..line])
+            .copied()
+            .collect())
+    }
+}
+
+impl fmt::Debug for FramePool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("FramePool")
+            .field("spec", &state.spec)
+            .field("buffers", &state.buffers)
+            .finish()
+    }
+}
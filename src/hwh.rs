@@ -0,0 +1,273 @@
+//! Builds `HwInfo` from a Vivado hardware handoff (`.hwh`), either on its own
+//! or packed inside an `.xsa` archive.
+//!
+//! The `.hwh` describes what is in the PL, not how Linux exposes it, so the
+//! UIO and udmabuf names come from a separate `DeviceMap`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::error::{bail, ensure, Result, XipError};
+use crate::hwinfo::{HwInfo, IpDescriptor, ParamValue, Vlnv};
+
+/// UIO / udmabuf device names of the IPs, keyed by hierarchical path.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceMap {
+    devices: BTreeMap<String, (Option<String>, Vec<String>)>,
+}
+
+impl DeviceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, uio: Option<&str>, udmabuf: &[&str]) -> &mut Self {
+        self.devices.insert(
+            path.to_string(),
+            (
+                uio.map(str::to_string),
+                udmabuf.iter().map(|name| name.to_string()).collect(),
+            ),
+        );
+        self
+    }
+
+    /// Reads a mapping file, e.g.
+    /// `{ "/lane_detection/v_frmbuf_wr": { "uio": "v_frmbuf_wr", "udmabuf": ["udmabuf0"] } }`.
+    pub fn read(filepath: &str) -> Result<Self> {
+        Self::from_json(&crate::hwinfo::read(filepath)?)
+            .map_err(|e| e.context(format!("in {}", filepath)))
+    }
+
+    pub fn from_json(map_json: &serde_json::Value) -> Result<Self> {
+        let map_object = map_json.as_object().ok_or_else(|| {
+            XipError::HwDescription(
+                "device map: the top level must be an object of IP paths".to_string(),
+            )
+        })?;
+        let mut devices = DeviceMap::new();
+        for (path, entry) in map_object {
+            let uio = match &entry["uio"] {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(name) => Some(name.as_str()),
+                v => bail!(
+                    HwDescription,
+                    "device map: {}: \"uio\" must be a string, found {}",
+                    path,
+                    v
+                ),
+            };
+            let udmabuf: Vec<&str> = match &entry["udmabuf"] {
+                serde_json::Value::Null => Vec::new(),
+                serde_json::Value::String(name) => vec![name.as_str()],
+                serde_json::Value::Array(names) => names
+                    .iter()
+                    .map(|name| {
+                        name.as_str().ok_or_else(|| {
+                            XipError::HwDescription(format!(
+                                "device map: {}: udmabuf names must be strings",
+                                path
+                            ))
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+                v => bail!(
+                    HwDescription,
+                    "device map: {}: \"udmabuf\" must be a list of names, found {}",
+                    path,
+                    v
+                ),
+            };
+            devices.insert(path, uio, &udmabuf);
+        }
+        Ok(devices)
+    }
+
+    /// Fills in the device names of `hw_info`. Every mapped path must exist.
+    pub fn apply(&self, hw_info: &mut HwInfo) -> Result<()> {
+        for (path, (uio, udmabuf)) in &self.devices {
+            let ip = hw_info.ips.get_mut(path).ok_or_else(|| {
+                XipError::HwDescription(format!(
+                    "device map: {} is not in the hardware description",
+                    path
+                ))
+            })?;
+            ip.uio = uio.clone();
+            ip.udmabuf = udmabuf.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Reads a `.hwh` or an `.xsa`, depending on the extension.
+pub fn read(filepath: &str, devices: &DeviceMap) -> Result<HwInfo> {
+    let extension = Path::new(filepath)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("hwh") => read_hwh(filepath, devices),
+        Some("xsa") => read_xsa(filepath, devices),
+        _ => bail!(HwDescription, "{}: expected a .hwh or .xsa file", filepath),
+    }
+}
+
+pub fn read_hwh(filepath: &str, devices: &DeviceMap) -> Result<HwInfo> {
+    let hwh = std::fs::read_to_string(filepath).map_err(|e| XipError::io("read", filepath, e))?;
+    parse(&hwh, devices).map_err(|e| e.context(format!("in {}", filepath)))
+}
+
+/// Reads the `.hwh` of an `.xsa`. The archive must contain exactly one;
+/// use `read_xsa_entry` to pick one otherwise.
+pub fn read_xsa(filepath: &str, devices: &DeviceMap) -> Result<HwInfo> {
+    let mut archive = open_xsa(filepath)?;
+    let hwh_names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".hwh"))
+        .map(str::to_string)
+        .collect();
+    ensure!(
+        hwh_names.len() == 1,
+        HwDescription,
+        "{}: expected one .hwh, found {:?}",
+        filepath,
+        hwh_names
+    );
+    parse_xsa_entry(&mut archive, filepath, &hwh_names[0], devices)
+}
+
+pub fn read_xsa_entry(filepath: &str, entry: &str, devices: &DeviceMap) -> Result<HwInfo> {
+    let mut archive = open_xsa(filepath)?;
+    parse_xsa_entry(&mut archive, filepath, entry, devices)
+}
+
+fn parse_xsa_entry(
+    archive: &mut XsaArchive,
+    filepath: &str,
+    entry: &str,
+    devices: &DeviceMap,
+) -> Result<HwInfo> {
+    let mut hwh = Vec::new();
+    archive
+        .by_name(entry)
+        .map_err(|e| match e {
+            zip::result::ZipError::FileNotFound => {
+                XipError::HwDescription(format!("{}: {} not found", filepath, entry))
+            }
+            e => XipError::HwDescription(format!("{}: cannot locate {}: {}", filepath, entry, e)),
+        })?
+        .read_to_end(&mut hwh)
+        .map_err(|e| {
+            XipError::HwDescription(format!("{}: cannot inflate {}: {}", filepath, entry, e))
+        })?;
+    let hwh = String::from_utf8(hwh)
+        .map_err(|_| XipError::HwDescription(format!("{}: {} is not UTF-8", filepath, entry)))?;
+    parse(&hwh, devices).map_err(|e| e.context(format!("in {}:{}", filepath, entry)))
+}
+
+/// Parses the contents of a `.hwh`.
+pub fn parse(hwh: &str, devices: &DeviceMap) -> Result<HwInfo> {
+    let doc = roxmltree::Document::parse(hwh)
+        .map_err(|e| XipError::HwDescription(format!("invalid hwh: {}", e)))?;
+    let root = doc.root_element();
+    ensure!(
+        root.has_tag_name("EDKSYSTEM"),
+        HwDescription,
+        "invalid hwh: the root element is <{}>, expected <EDKSYSTEM>",
+        root.tag_name().name()
+    );
+
+    let modules: Vec<roxmltree::Node> = root
+        .descendants()
+        .filter(|n| n.has_tag_name("MODULE"))
+        .collect();
+
+    // Address ranges are listed in the memory map of the bus masters,
+    // keyed by instance name rather than by hierarchical path.
+    let mut ranges: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for module in &modules {
+        for memrange in module.descendants().filter(|n| n.has_tag_name("MEMRANGE")) {
+            let instance = memrange.attribute("INSTANCE");
+            let base = memrange.attribute("BASEVALUE").and_then(parse_u64);
+            let high = memrange.attribute("HIGHVALUE").and_then(parse_u64);
+            if let (Some(instance), Some(base), Some(high)) = (instance, base, high) {
+                if high < base {
+                    continue;
+                }
+                let is_register = memrange.attribute("MEMTYPE") == Some("REGISTER");
+                if is_register || !ranges.contains_key(instance) {
+                    ranges.insert(instance, (base, high - base + 1));
+                }
+            }
+        }
+    }
+
+    let mut hw_info = HwInfo::default();
+    for module in &modules {
+        let instance = module_attr(module, "INSTANCE")?;
+        let path = match module.attribute("FULLNAME") {
+            Some(path) => path.to_string(),
+            None => format!("/{}", instance),
+        };
+        let vlnv = Vlnv::parse(module_attr(module, "VLNV")?)
+            .map_err(|e| e.context(format!("invalid hwh: {}", path)))?;
+
+        let mut ip = IpDescriptor::new(&path, vlnv);
+        let params = module
+            .children()
+            .filter(|n| n.has_tag_name("PARAMETERS"))
+            .flat_map(|n| n.children())
+            .filter(|n| n.has_tag_name("PARAMETER"));
+        for param in params {
+            if let (Some(name), Some(value)) = (param.attribute("NAME"), param.attribute("VALUE")) {
+                ip.params
+                    .insert(name.to_string(), ParamValue::Str(value.to_string()));
+            }
+        }
+
+        let range = ranges.get(instance).copied().or_else(|| {
+            let base = ip.params.get("C_BASEADDR")?.as_u64()?;
+            let high = ip.params.get("C_HIGHADDR")?.as_u64()?;
+            (high >= base).then(|| (base, high - base + 1))
+        });
+        if let Some((base_addr, range)) = range {
+            ip.base_addr = Some(base_addr);
+            ip.range = Some(range);
+        }
+
+        ensure!(
+            !hw_info.ips.contains_key(&path),
+            HwDescription,
+            "invalid hwh: {} appears twice",
+            path
+        );
+        hw_info.ips.insert(path, ip);
+    }
+
+    devices.apply(&mut hw_info)?;
+    Ok(hw_info)
+}
+
+fn module_attr<'a>(module: &roxmltree::Node<'a, '_>, name: &'a str) -> Result<&'a str> {
+    module
+        .attribute(name)
+        .ok_or_else(|| XipError::HwDescription(format!("invalid hwh: <MODULE> without {}", name)))
+}
+
+fn parse_u64(value: &str) -> Option<u64> {
+    ParamValue::Str(value.to_string()).as_u64()
+}
+
+type XsaArchive = zip::ZipArchive<BufReader<File>>;
+
+fn open_xsa(filepath: &str) -> Result<XsaArchive> {
+    let file = File::open(filepath).map_err(|e| XipError::io("open", filepath, e))?;
+    zip::ZipArchive::new(BufReader::new(file)).map_err(|e| {
+        XipError::HwDescription(format!(
+            "{} is not a valid .xsa (zip) file: {}",
+            filepath, e
+        ))
+    })
+}
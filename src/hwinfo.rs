@@ -1,55 +1,78 @@
-use anyhow::{anyhow, Result, Context};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 
+use crate::error::{bail, ensure, Result, XipError};
+use crate::sysfs::{Sysfs, UioDevice};
+
 #[macro_export]
 macro_rules! json_as_map {
     ($json_value: expr) => {
-        $json_value.as_object().context(format!("{} is not an object type", stringify!($json_value)))?
+        $json_value.as_object().ok_or_else(|| {
+            $crate::error::XipError::HwDescription(format!("{} is not an object type", stringify!($json_value)))
+        })?
     };
 }
 
 #[macro_export]
 macro_rules! json_as_vec {
     ($json_value: expr) => {
-        $json_value.as_array().context(format!("{} is not an array", stringify!($json_value)))?
+        $json_value.as_array().ok_or_else(|| {
+            $crate::error::XipError::HwDescription(format!("{} is not an array", stringify!($json_value)))
+        })?
     };
 }
 
 #[macro_export]
 macro_rules! json_as_u32 {
     ($json_value: expr) => {
-        $json_value.as_i64().context(format!("{} is not numeric", stringify!($json_value)))? as u32
+        $json_value.as_i64().ok_or_else(|| {
+            $crate::error::XipError::HwDescription(format!("{} is not numeric", stringify!($json_value)))
+        })? as u32
     };
 }
 
 #[macro_export]
 macro_rules! json_as_i32 {
     ($json_value: expr) => {
-        $json_value.as_i64().context(format!("{} is not numeric", stringify!($json_value)))? as i32
+        $json_value.as_i64().ok_or_else(|| {
+            $crate::error::XipError::HwDescription(format!("{} is not numeric", stringify!($json_value)))
+        })? as i32
     };
 }
 
 #[macro_export]
 macro_rules! json_as_str {
     ($json_value: expr) => {
-        $json_value.as_str().context(format!("{} is not string", stringify!($json_value)))?
+        $json_value.as_str().ok_or_else(|| {
+            $crate::error::XipError::HwDescription(format!("{} is not string", stringify!($json_value)))
+        })?
     };
 }
 
 #[macro_export]
 macro_rules! json_as_f32 {
     ($json_value: expr) => {
-        $json_value.as_str().context(format!("{} is not string", stringify!($json_value)))?.parse::<f32>()?
+        $json_value
+            .as_str()
+            .ok_or_else(|| {
+                $crate::error::XipError::HwDescription(format!("{} is not string", stringify!($json_value)))
+            })?
+            .parse::<f32>()
+            .map_err(|e| {
+                $crate::error::XipError::HwDescription(format!("{}: {}", stringify!($json_value), e))
+            })?
     };
 }
 
 pub fn read(filepath: &str) -> Result<serde_json::Value> {
-    let file = File::open(filepath)?;
+    let file = File::open(filepath).map_err(|e| XipError::io("open", filepath, e))?;
     let reader = BufReader::new(file);
 
-    // Read the JSON contents of the file as an instance of `User`.
-    let hw_json: serde_json::Value = serde_json::from_reader(reader).unwrap();
+    let hw_json: serde_json::Value = serde_json::from_reader(reader).map_err(|e| {
+        XipError::HwDescription(format!("{} is not a valid hwinfo file: {}", filepath, e))
+    })?;
     Ok(hw_json)
 }
 
@@ -57,11 +80,604 @@ pub fn read(filepath: &str) -> Result<serde_json::Value> {
 pub fn match_hw(hw_json: &serde_json::Value, hier_name: &str, hw_name: &str) -> Result<String> {
     let hw_object = json_as_map!(hw_json);
     for k in hw_object.keys() {
-        if let Some(_) = k.find(hier_name) {
-            if json_as_str!(hw_object[k]["name"])== hw_name {
-                return Ok(k.clone());
+        if k.contains(hier_name) && json_as_str!(hw_object[k]["name"]) == hw_name {
+            return Ok(k.clone());
+        }
+    }
+    bail!(HwDescription, "hw object not found: {}, {}", hier_name, hw_name)
+}
+
+/// Vendor / library / name / version of an IP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vlnv {
+    pub vendor: String,
+    pub library: String,
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl Vlnv {
+    pub fn new(vendor: &str, library: &str, name: &str) -> Self {
+        Vlnv {
+            vendor: vendor.to_string(),
+            library: library.to_string(),
+            name: name.to_string(),
+            version: None,
+        }
+    }
+
+    /// Parses `vendor:library:name[:version]`.
+    pub fn parse(vlnv: &str) -> Result<Self> {
+        let fields: Vec<&str> = vlnv.split(':').collect();
+        ensure!(
+            fields.len() == 3 || fields.len() == 4,
+            HwDescription,
+            "invalid VLNV: {:?} (expected vendor:library:name[:version])",
+            vlnv
+        );
+        Ok(Vlnv {
+            vendor: fields[0].to_string(),
+            library: fields[1].to_string(),
+            name: fields[2].to_string(),
+            version: fields.get(3).map(|v| v.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Vlnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.vendor, self.library, self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, ":{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Value of an IP configuration parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl ParamValue {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::Bool(b) => Some(ParamValue::Bool(*b)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Some(ParamValue::Int(i)),
+                None => n.as_f64().map(ParamValue::Float),
+            },
+            serde_json::Value::String(s) => Some(ParamValue::Str(s.clone())),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            ParamValue::Int(i) => (*i).into(),
+            ParamValue::Float(f) => (*f).into(),
+            ParamValue::Bool(b) => (*b).into(),
+            ParamValue::Str(s) => s.clone().into(),
+        }
+    }
+
+    /// Integer value. Strings such as `"12"`, `"0x1F"` or `"true"` are
+    /// accepted since Vivado stores most parameters as strings.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ParamValue::Int(i) => Some(*i),
+            ParamValue::Bool(b) => Some(*b as i64),
+            ParamValue::Float(_) => None,
+            ParamValue::Str(s) => {
+                let s = s.trim();
+                if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                    i64::from_str_radix(hex, 16).ok()
+                } else if s.eq_ignore_ascii_case("true") {
+                    Some(1)
+                } else if s.eq_ignore_ascii_case("false") {
+                    Some(0)
+                } else {
+                    s.parse().ok()
+                }
+            }
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            ParamValue::Str(s) => {
+                let s = s.trim();
+                match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                    Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                    None => s.parse().ok(),
+                }
+            }
+            _ => self.as_i64().and_then(|i| u64::try_from(i).ok()),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ParamValue::Int(i) => Some(*i as f64),
+            ParamValue::Float(f) => Some(*f),
+            ParamValue::Bool(_) => None,
+            ParamValue::Str(s) => s.trim().parse().ok(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ParamValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamValue::Int(i) => write!(f, "{}", i),
+            ParamValue::Float(v) => write!(f, "{}", v),
+            ParamValue::Bool(b) => write!(f, "{}", b),
+            ParamValue::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// One IP of a hwinfo file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IpDescriptor {
+    /// Hierarchical path of the instance, e.g. `/lane_detection/v_frmbuf_rd`.
+    pub path: String,
+    pub vlnv: Vlnv,
+    /// AXI-Lite base address and size of the register window, if known.
+    pub base_addr: Option<u64>,
+    pub range: Option<u64>,
+    /// UIO device name, or node name such as `uio3` when the name is not unique.
+    pub uio: Option<String>,
+    pub udmabuf: Vec<String>,
+    /// Map the udmabufs cached; the drivers then sync them around every
+    /// transfer. Off unless `"udmabuf_cached": true`.
+    pub udmabuf_cached: bool,
+    pub params: BTreeMap<String, ParamValue>,
+}
+
+impl IpDescriptor {
+    pub fn new(path: &str, vlnv: Vlnv) -> Self {
+        IpDescriptor {
+            path: path.to_string(),
+            vlnv,
+            base_addr: None,
+            range: None,
+            uio: None,
+            udmabuf: Vec::new(),
+            udmabuf_cached: false,
+            params: BTreeMap::new(),
+        }
+    }
+
+    /// Builds a descriptor from one entry of a hwinfo file.
+    pub fn from_json(hw_info: &serde_json::Value) -> Result<Self> {
+        Self::from_json_at("", hw_info)
+    }
+
+    pub fn from_json_at(path: &str, hw_info: &serde_json::Value) -> Result<Self> {
+        let label = if path.is_empty() { "hw_info" } else { path };
+        let hw_object = hw_info
+            .as_object()
+            .ok_or_else(|| XipError::HwDescription(format!("{}: not an object", label)))?;
+        let get_str = |key: &str| -> Result<String> {
+            match hw_object.get(key) {
+                Some(serde_json::Value::String(s)) => Ok(s.clone()),
+                Some(v) => bail!(HwDescription, "{}: \"{}\" must be a string, found {}", label, key, v),
+                None => bail!(HwDescription, "{}: \"{}\" is missing", label, key),
+            }
+        };
+        let vlnv = Vlnv {
+            vendor: get_str("vendor")?,
+            library: get_str("library")?,
+            name: get_str("name")?,
+            version: get_str("version").ok(),
+        };
+        let get_addr = |key: &str| -> Result<Option<u64>> {
+            match hw_object.get(key) {
+                None | Some(serde_json::Value::Null) => Ok(None),
+                Some(v) => ParamValue::from_json(v)
+                    .and_then(|v| v.as_u64())
+                    .map(Some)
+                    .ok_or_else(|| {
+                        XipError::HwDescription(format!(
+                            "{}: \"{}\" is not an address, found {}",
+                            label, key, v
+                        ))
+                    }),
+            }
+        };
+        let base_addr = get_addr("base_addr")?;
+        let range = get_addr("range")?;
+        let uio = match hw_object.get("uio") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(_) => Some(get_str("uio")?),
+        };
+        let udmabuf = match hw_object.get("udmabuf") {
+            None | Some(serde_json::Value::Null) => Vec::new(),
+            Some(serde_json::Value::String(s)) => vec![s.clone()],
+            Some(serde_json::Value::Array(names)) => names
+                .iter()
+                .map(|name| {
+                    name.as_str().map(str::to_string).ok_or_else(|| {
+                        XipError::HwDescription(format!(
+                            "{}: udmabuf names must be strings, found {}",
+                            label,
+                            name
+                        ))
+                    })
+                })
+                .collect::<Result<_>>()?,
+            Some(v) => bail!(HwDescription, "{}: \"udmabuf\" must be a list of names, found {}", label, v),
+        };
+        let udmabuf_cached = match hw_object.get("udmabuf_cached") {
+            None | Some(serde_json::Value::Null) => false,
+            Some(serde_json::Value::Bool(b)) => *b,
+            Some(v) => bail!(HwDescription, "{}: \"udmabuf_cached\" must be a bool, found {}", label, v),
+        };
+        let mut params = BTreeMap::new();
+        match hw_object.get("params") {
+            None | Some(serde_json::Value::Null) => {}
+            Some(serde_json::Value::Object(map)) => {
+                for (k, v) in map {
+                    let value = ParamValue::from_json(v).ok_or_else(|| {
+                        XipError::HwDescription(format!(
+                            "{}: parameter {} has an unsupported value {}",
+                            label,
+                            k,
+                            v
+                        ))
+                    })?;
+                    params.insert(k.clone(), value);
+                }
+            }
+            Some(v) => bail!(HwDescription, "{}: \"params\" must be an object, found {}", label, v),
+        }
+        Ok(IpDescriptor {
+            path: path.to_string(),
+            vlnv,
+            base_addr,
+            range,
+            uio,
+            udmabuf,
+            udmabuf_cached,
+            params,
+        })
+    }
+
+    /// Inverse of `from_json`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut hw_object = serde_json::Map::new();
+        hw_object.insert("vendor".into(), self.vlnv.vendor.clone().into());
+        hw_object.insert("library".into(), self.vlnv.library.clone().into());
+        hw_object.insert("name".into(), self.vlnv.name.clone().into());
+        if let Some(version) = &self.vlnv.version {
+            hw_object.insert("version".into(), version.clone().into());
+        }
+        if let Some(base_addr) = self.base_addr {
+            hw_object.insert("base_addr".into(), format!("0x{:X}", base_addr).into());
+        }
+        if let Some(range) = self.range {
+            hw_object.insert("range".into(), format!("0x{:X}", range).into());
+        }
+        if let Some(uio) = &self.uio {
+            hw_object.insert("uio".into(), uio.clone().into());
+        }
+        if !self.udmabuf.is_empty() {
+            hw_object.insert("udmabuf".into(), self.udmabuf.clone().into());
+        }
+        if self.udmabuf_cached {
+            hw_object.insert("udmabuf_cached".into(), true.into());
+        }
+        let params = self
+            .params
+            .iter()
+            .map(|(k, v)| (k.clone(), v.to_json()))
+            .collect();
+        hw_object.insert("params".into(), serde_json::Value::Object(params));
+        serde_json::Value::Object(hw_object)
+    }
+
+    fn label(&self) -> String {
+        if self.path.is_empty() {
+            self.vlnv.to_string()
+        } else {
+            self.path.clone()
+        }
+    }
+
+    /// Checks that this IP is one a driver supports.
+    pub fn ensure_ip(&self, vendor: &str, libraries: &[&str], name: &str, driver: &str) -> Result<()> {
+        ensure!(
+            self.vlnv.vendor == vendor
+                && libraries.contains(&self.vlnv.library.as_str())
+                && self.vlnv.name == name,
+            HwDescription,
+            "{}: This IP is not supported. ({} is {}, expected {}:{}:{})",
+            driver,
+            self.label(),
+            self.vlnv,
+            vendor,
+            libraries.join("|"),
+            name
+        );
+        Ok(())
+    }
+
+    pub fn uio(&self) -> Result<&str> {
+        self.uio.as_deref().ok_or_else(|| {
+            XipError::HwDescription(format!("{}: no uio device is assigned", self.label()))
+        })
+    }
+
+    pub fn udmabuf(&self, index: usize) -> Result<&str> {
+        self.udmabuf.get(index).map(String::as_str).ok_or_else(|| {
+            XipError::HwDescription(format!(
+                "{}: udmabuf #{} is required but only {} assigned",
+                self.label(),
+                index,
+                self.udmabuf.len()
+            ))
+        })
+    }
+
+    pub fn param(&self, key: &str) -> Result<&ParamValue> {
+        self.params.get(key).ok_or_else(|| {
+            XipError::HwDescription(format!("{}: parameter {} is missing", self.label(), key))
+        })
+    }
+
+    pub fn param_i64(&self, key: &str) -> Result<i64> {
+        let value = self.param(key)?;
+        value.as_i64().ok_or_else(|| {
+            XipError::HwDescription(format!(
+                "{}: parameter {} is not an integer ({})",
+                self.label(),
+                key,
+                value
+            ))
+        })
+    }
+
+    pub fn param_u32(&self, key: &str) -> Result<u32> {
+        let value = self.param_i64(key)?;
+        u32::try_from(value).map_err(|_| self.out_of_range(key, value))
+    }
+
+    pub fn param_i32(&self, key: &str) -> Result<i32> {
+        let value = self.param_i64(key)?;
+        i32::try_from(value).map_err(|_| self.out_of_range(key, value))
+    }
+
+    fn out_of_range(&self, key: &str, value: i64) -> XipError {
+        XipError::HwDescription(format!(
+            "{}: parameter {} is out of range ({})",
+            self.label(),
+            key,
+            value
+        ))
+    }
+
+    pub fn param_f32(&self, key: &str) -> Result<f32> {
+        let value = self.param(key)?;
+        value.as_f64().map(|v| v as f32).ok_or_else(|| {
+            XipError::HwDescription(format!(
+                "{}: parameter {} is not a number ({})",
+                self.label(),
+                key,
+                value
+            ))
+        })
+    }
+
+    pub fn param_bool(&self, key: &str) -> Result<bool> {
+        Ok(self.param_i64(key)? != 0)
+    }
+
+    /// Like `param_u32`, but returns `default` when the parameter is absent.
+    pub fn param_u32_or(&self, key: &str, default: u32) -> Result<u32> {
+        if self.params.contains_key(key) {
+            self.param_u32(key)
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// Like `param_bool`, but returns `default` when the parameter is absent.
+    pub fn param_bool_or(&self, key: &str, default: bool) -> Result<bool> {
+        if self.params.contains_key(key) {
+            self.param_bool(key)
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// Width of the address bus given by parameter `key`, 32 if unset.
+    pub fn addr_width(&self, key: &str) -> Result<u32> {
+        let width = self.param_u32_or(key, 32)?;
+        ensure!(
+            (32..=64).contains(&width),
+            HwDescription,
+            "{}: {} must be 32 to 64, found {}",
+            self.label(),
+            key,
+            width
+        );
+        Ok(width)
+    }
+
+    /// Widest `C_M_AXI_*_ADDR_WIDTH` of an HLS core, 32 if it has none.
+    pub fn m_axi_addr_width(&self) -> Result<u32> {
+        let mut width = 32;
+        for key in self.params.keys() {
+            if key.starts_with("C_M_AXI_") && key.ends_with("_ADDR_WIDTH") {
+                width = width.max(self.addr_width(key)?);
             }
         }
+        Ok(width)
+    }
+}
+
+/// Typed contents of a hwinfo file, keyed by hierarchical path.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HwInfo {
+    pub ips: BTreeMap<String, IpDescriptor>,
+}
+
+impl HwInfo {
+    pub fn read(filepath: &str) -> Result<Self> {
+        Self::from_json(&read(filepath)?).map_err(|e| e.context(format!("in {}", filepath)))
+    }
+
+    pub fn from_json(hw_json: &serde_json::Value) -> Result<Self> {
+        let hw_object = hw_json
+            .as_object()
+            .ok_or_else(|| {
+                XipError::HwDescription("hwinfo: the top level must be an object of IPs".to_string())
+            })?;
+        let mut ips = BTreeMap::new();
+        for (path, hw_info) in hw_object {
+            ips.insert(path.clone(), IpDescriptor::from_json_at(path, hw_info)?);
+        }
+        Ok(HwInfo { ips })
+    }
+
+    pub fn get(&self, path: &str) -> Result<&IpDescriptor> {
+        self.ips
+            .get(path)
+            .ok_or_else(|| XipError::HwDescription(format!("hw object not found: {}", path)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IpDescriptor> {
+        self.ips.values()
+    }
+
+    /// Inverse of `from_json`: the structure `match_hw` and the drivers' `new` consume.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.ips
+                .iter()
+                .map(|(path, ip)| (path.clone(), ip.to_json()))
+                .collect(),
+        )
+    }
+
+    /// Assigns UIO devices to the IPs without one by matching their base
+    /// address against `maps/map0/addr` of every UIO node, and checks the
+    /// UIO / udmabuf names already present against sysfs.
+    ///
+    /// IPs whose address no UIO node maps are left unassigned; every other
+    /// inconsistency is collected into the returned error.
+    pub fn resolve_devices(&mut self, sysfs: &Sysfs) -> Result<()> {
+        let uios = sysfs.uio_devices()?;
+        let udmabufs = sysfs.udmabuf_devices()?;
+        let device_name = |uio: &UioDevice| {
+            if uios.iter().filter(|u| u.name == uio.name).count() == 1 {
+                uio.name.clone()
+            } else {
+                uio.node.clone()
+            }
+        };
+
+        let mut problems = Vec::new();
+        for ip in self.ips.values_mut() {
+            match (&ip.uio, ip.base_addr) {
+                (Some(name), base_addr) => {
+                    let nodes: Vec<&UioDevice> = uios
+                        .iter()
+                        .filter(|u| &u.name == name || &u.node == name)
+                        .collect();
+                    let listing = nodes
+                        .iter()
+                        .map(|u| format!("{} at 0x{:X}", u.node, u.addr))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    if nodes.is_empty() {
+                        problems.push(format!(
+                            "{}: uio device {:?} not found in {}",
+                            ip.path,
+                            name,
+                            sysfs.root().join("class/uio").display()
+                        ));
+                    } else if let Some(base_addr) = base_addr {
+                        match nodes.iter().find(|u| u.addr == base_addr) {
+                            Some(uio) if nodes.len() > 1 => ip.uio = Some(uio.node.clone()),
+                            Some(_) => {}
+                            None => problems.push(format!(
+                                "{}: uio device {:?} ({}) does not map the IP's base address 0x{:X}",
+                                ip.path, name, listing, base_addr
+                            )),
+                        }
+                    } else if nodes.len() > 1 {
+                        problems.push(format!(
+                            "{}: uio device name {:?} is ambiguous ({})",
+                            ip.path, name, listing
+                        ));
+                    }
+                }
+                (None, Some(base_addr)) => {
+                    let nodes: Vec<&UioDevice> = uios.iter().filter(|u| u.addr == base_addr).collect();
+                    match nodes.as_slice() {
+                        [] => {}
+                        [uio] => ip.uio = Some(device_name(uio)),
+                        _ => problems.push(format!(
+                            "{}: several uio nodes map 0x{:X} ({})",
+                            ip.path,
+                            base_addr,
+                            nodes.iter().map(|u| u.node.as_str()).collect::<Vec<_>>().join(", ")
+                        )),
+                    }
+                }
+                (None, None) => {}
+            }
+            for name in &ip.udmabuf {
+                if !udmabufs.iter().any(|u| &u.name == name) {
+                    problems.push(format!(
+                        "{}: udmabuf {:?} not found in {} (available: {})",
+                        ip.path,
+                        name,
+                        sysfs.root().join("class/u-dma-buf").display(),
+                        udmabufs.iter().map(|u| u.name.as_str()).collect::<Vec<_>>().join(", ")
+                    ));
+                }
+            }
+        }
+        ensure!(
+            problems.is_empty(),
+            Mapping,
+            "hwinfo does not match the devices in sysfs:\n  {}",
+            problems.join("\n  ")
+        );
+        Ok(())
+    }
+
+    /// Typed counterpart of `match_hw`.
+    pub fn find(&self, hier_name: &str, hw_name: &str) -> Result<&IpDescriptor> {
+        self.iter()
+            .find(|ip| ip.path.contains(hier_name) && ip.vlnv.name == hw_name)
+            .ok_or_else(|| {
+                XipError::HwDescription(format!("hw object not found: {}, {}", hier_name, hw_name))
+            })
+    }
+}
+
+impl std::ops::Index<&str> for HwInfo {
+    type Output = IpDescriptor;
+
+    fn index(&self, path: &str) -> &IpDescriptor {
+        match self.ips.get(path) {
+            Some(ip) => ip,
+            None => panic!("hw object not found: {}", path),
+        }
     }
-    Err(anyhow!("hw object not found: {}, {}", hier_name, hw_name))
 }
@@ -1,8 +1,20 @@
+pub mod ap_ctrl;
 pub mod axidma;
 pub mod axigpio;
 pub mod axis_switch;
+pub mod backend;
 pub mod bird_eye_view;
+pub mod board;
+pub mod dma_buffer;
+pub mod error;
+pub mod frame_image;
+pub mod frame_pool;
+pub mod hwh;
 pub mod hwinfo;
+pub mod sim;
+pub mod sim_models;
+pub mod sysfs;
+pub mod timeout;
 pub mod umv_lane_detector;
 pub mod umv_motor_controller;
 pub mod v_frmbuf;
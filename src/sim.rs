@@ -0,0 +1,363 @@
+//! In-memory backend for running the drivers without an FPGA.
+//!
+//! Register windows and DMA buffers are plain byte vectors shared between the
+//! driver and the test, so a test can preset status registers, inspect what a
+//! driver programmed and fill or check buffer contents. A `SimModel` attached
+//! to a register window makes it react to driver accesses like the real IP
+//! (see `crate::sim_models`).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use crate::backend::{Backend, BufIo, RegIo};
+use crate::error::{bail, ensure, Result};
+
+const UIO_PHYS_BASE: usize = 0xA000_0000;
+const UDMABUF_PHYS_BASE: usize = 0x7000_0000;
+const PHYS_ALIGN: usize = 0x1000;
+
+struct SimMem {
+    data: Vec<u8>,
+    irq_enable: bool,
+    irq_pending: usize,
+    cached: bool,
+    syncs: Vec<SimSync>,
+}
+
+/// A cache sync a driver requested on a udmabuf opened cached, as
+/// `(offset, len)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimSync {
+    ForCpu(usize, usize),
+    ForDevice(usize, usize),
+}
+
+/// Behaviour of a simulated IP behind its register window.
+///
+/// Only accesses made by a driver (through `RegIo`) go through the model;
+/// `SimRegion::read32` / `write32` always access the raw register file.
+pub trait SimModel: Send {
+    /// Called once when the model is attached.
+    fn reset(&mut self, _ctx: &SimContext) {}
+
+    /// Handles a driver write. The default stores the value.
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        ctx.regs().write32(offset, data);
+    }
+
+    /// Handles a driver read. The default returns the stored value.
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        ctx.regs().read32(offset)
+    }
+}
+
+/// What a `SimModel` can see: its own register file and the buffers of the
+/// backend it belongs to.
+pub struct SimContext<'a> {
+    regs: &'a SimRegion,
+}
+
+impl SimContext<'_> {
+    pub fn regs(&self) -> &SimRegion {
+        self.regs
+    }
+
+    /// Finds the udmabuf containing `phys_addr` and the offset into it.
+    pub fn buffer_at(&self, phys_addr: usize) -> Option<(SimRegion, usize)> {
+        let state = self.regs.bus.upgrade()?;
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.udmabuf.values().find_map(|buf| {
+            let offset = phys_addr.checked_sub(buf.phys_addr)?;
+            (offset < buf.size()).then(|| (buf.clone(), offset))
+        })
+    }
+
+    pub fn raise_irq(&self) {
+        if self.regs.irq_enabled() {
+            self.regs.raise_irq();
+        }
+    }
+}
+
+type SharedModel = Arc<Mutex<Option<Box<dyn SimModel>>>>;
+
+/// Shared handle to one simulated register window or buffer.
+#[derive(Clone)]
+pub struct SimRegion {
+    phys_addr: usize,
+    mem: Arc<Mutex<SimMem>>,
+    model: SharedModel,
+    bus: Weak<Mutex<SimState>>,
+}
+
+impl SimRegion {
+    pub fn new(phys_addr: usize, size: usize) -> Self {
+        Self::with_bus(phys_addr, size, Weak::new())
+    }
+
+    fn with_bus(phys_addr: usize, size: usize, bus: Weak<Mutex<SimState>>) -> Self {
+        SimRegion {
+            phys_addr,
+            mem: Arc::new(Mutex::new(SimMem {
+                data: vec![0; size],
+                irq_enable: false,
+                irq_pending: 0,
+                cached: false,
+                syncs: Vec::new(),
+            })),
+            model: Arc::new(Mutex::new(None)),
+            bus,
+        }
+    }
+
+    /// Attaches `model`, replacing any previous one.
+    pub fn set_model<M: SimModel + 'static>(&self, model: M) {
+        let mut model: Box<dyn SimModel> = Box::new(model);
+        model.reset(&SimContext { regs: self });
+        *self.model.lock().unwrap_or_else(|e| e.into_inner()) = Some(model);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimMem> {
+        self.mem.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+
+    pub fn size(&self) -> usize {
+        self.lock().data.len()
+    }
+
+    pub fn read32(&self, offset: usize) -> u32 {
+        let mem = self.lock();
+        let mut word = [0; 4];
+        word.copy_from_slice(&mem.data[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    /// A 64-bit address split over two registers, LSB word first.
+    pub fn read_addr(&self, offset: usize) -> usize {
+        (self.read32(offset) as u64 | (self.read32(offset + 4) as u64) << 32) as usize
+    }
+
+    pub fn write32(&self, offset: usize, data: u32) {
+        let mut mem = self.lock();
+        mem.data[offset..offset + 4].copy_from_slice(&data.to_le_bytes());
+    }
+
+    pub fn read_bytes(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.lock().data[offset..offset + len].to_vec()
+    }
+
+    pub fn write_bytes(&self, offset: usize, data: &[u8]) {
+        let mut mem = self.lock();
+        mem.data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    pub fn fill(&self, value: u8) {
+        self.lock().data.fill(value);
+    }
+
+    pub fn irq_enabled(&self) -> bool {
+        self.lock().irq_enable
+    }
+
+    /// Queues one interrupt for the next `wait_irq()`.
+    pub fn raise_irq(&self) {
+        self.lock().irq_pending += 1;
+    }
+
+    /// Whether a driver opened this udmabuf cached.
+    pub fn cached(&self) -> bool {
+        self.lock().cached
+    }
+
+    /// Syncs requested since the last call, oldest first.
+    pub fn take_syncs(&self) -> Vec<SimSync> {
+        std::mem::take(&mut self.lock().syncs)
+    }
+}
+
+impl RegIo for SimRegion {
+    fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+    fn size(&self) -> usize {
+        SimRegion::size(self)
+    }
+    unsafe fn read_mem32(&self, offset: usize) -> u32 {
+        let mut model = self.model.lock().unwrap_or_else(|e| e.into_inner());
+        match model.as_mut() {
+            Some(model) => model.read(&SimContext { regs: self }, offset),
+            None => self.read32(offset),
+        }
+    }
+    unsafe fn write_mem32(&self, offset: usize, data: u32) {
+        let mut model = self.model.lock().unwrap_or_else(|e| e.into_inner());
+        match model.as_mut() {
+            Some(model) => model.write(&SimContext { regs: self }, offset, data),
+            None => self.write32(offset, data),
+        }
+    }
+    fn set_irq_enable(&mut self, enable: bool) -> Result<()> {
+        self.lock().irq_enable = enable;
+        Ok(())
+    }
+    fn wait_irq(&mut self) -> Result<()> {
+        let mut mem = self.lock();
+        ensure!(mem.irq_enable, Mapping, "SimRegion: interrupt is not enabled");
+        // Nothing else can raise an interrupt while we are blocked here,
+        // so waiting without a pending one would hang forever.
+        ensure!(mem.irq_pending > 0, Mapping, "SimRegion: no interrupt pending");
+        mem.irq_pending -= 1;
+        Ok(())
+    }
+    /// Returns at once: nothing else can raise an interrupt while we wait,
+    /// so a missing one would never come.
+    fn wait_irq_timeout(&mut self, _timeout: Duration) -> Result<bool> {
+        let mut mem = self.lock();
+        ensure!(mem.irq_enable, Mapping, "SimRegion: interrupt is not enabled");
+        if mem.irq_pending == 0 {
+            return Ok(false);
+        }
+        mem.irq_pending -= 1;
+        Ok(true)
+    }
+}
+
+impl BufIo for SimRegion {
+    fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+    fn size(&self) -> usize {
+        SimRegion::size(self)
+    }
+    unsafe fn read_mem32(&self, offset: usize) -> u32 {
+        self.read32(offset)
+    }
+    unsafe fn write_mem32(&self, offset: usize, data: u32) {
+        self.write32(offset, data)
+    }
+    unsafe fn copy_to_bytes(&self, src_adr: usize, dst_ptr: *mut u8, len: usize) {
+        let mem = self.lock();
+        let src = &mem.data[src_adr..src_adr + len];
+        core::ptr::copy_nonoverlapping(src.as_ptr(), dst_ptr, len);
+    }
+    unsafe fn copy_from_bytes(&self, src_ptr: *const u8, dst_adr: usize, len: usize) {
+        let mut mem = self.lock();
+        let dst = &mut mem.data[dst_adr..dst_adr + len];
+        core::ptr::copy_nonoverlapping(src_ptr, dst.as_mut_ptr(), len);
+    }
+    /// The data vector is never resized, so the pointer stays valid; accesses
+    /// through it bypass the lock like DMA does on the real bus.
+    fn as_mut_ptr(&self) -> *mut u8 {
+        self.lock().data.as_mut_ptr()
+    }
+    fn sync_for_cpu(&self, offset: usize, len: usize) -> Result<()> {
+        let mut mem = self.lock();
+        if mem.cached {
+            mem.syncs.push(SimSync::ForCpu(offset, len));
+        }
+        Ok(())
+    }
+    fn sync_for_device(&self, offset: usize, len: usize) -> Result<()> {
+        let mut mem = self.lock();
+        if mem.cached {
+            mem.syncs.push(SimSync::ForDevice(offset, len));
+        }
+        Ok(())
+    }
+}
+
+struct SimState {
+    uio: HashMap<String, SimRegion>,
+    udmabuf: HashMap<String, SimRegion>,
+    next_uio_addr: usize,
+    next_udmabuf_addr: usize,
+}
+
+/// `Backend` whose devices live in memory.
+///
+/// Devices must be registered with `add_uio` / `add_udmabuf` before a driver
+/// opens them. Opening the same name twice yields views of the same memory.
+#[derive(Clone)]
+pub struct SimBackend {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl Default for SimBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimBackend {
+    pub fn new() -> Self {
+        SimBackend {
+            state: Arc::new(Mutex::new(SimState {
+                uio: HashMap::new(),
+                udmabuf: HashMap::new(),
+                next_uio_addr: UIO_PHYS_BASE,
+                next_udmabuf_addr: UDMABUF_PHYS_BASE,
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn add_uio(&self, name: &str, size: usize) -> SimRegion {
+        let mut state = self.lock();
+        let region = SimRegion::with_bus(state.next_uio_addr, size, Arc::downgrade(&self.state));
+        state.next_uio_addr += size.next_multiple_of(PHYS_ALIGN).max(PHYS_ALIGN);
+        state.uio.insert(name.to_string(), region.clone());
+        region
+    }
+
+    pub fn add_udmabuf(&self, name: &str, size: usize) -> SimRegion {
+        let phys_addr = self.lock().next_udmabuf_addr;
+        self.add_udmabuf_at(name, phys_addr, size)
+    }
+
+    /// Places the udmabuf at `phys_addr`, e.g. above 4 GiB. Later buffers
+    /// follow it.
+    pub fn add_udmabuf_at(&self, name: &str, phys_addr: usize, size: usize) -> SimRegion {
+        let mut state = self.lock();
+        let region = SimRegion::with_bus(phys_addr, size, Arc::downgrade(&self.state));
+        state.next_udmabuf_addr = phys_addr + size.next_multiple_of(PHYS_ALIGN).max(PHYS_ALIGN);
+        state.udmabuf.insert(name.to_string(), region.clone());
+        region
+    }
+
+    pub fn uio(&self, name: &str) -> Option<SimRegion> {
+        self.lock().uio.get(name).cloned()
+    }
+
+    pub fn udmabuf(&self, name: &str) -> Option<SimRegion> {
+        self.lock().udmabuf.get(name).cloned()
+    }
+}
+
+impl Backend for SimBackend {
+    fn open_uio(&self, name: &str) -> Result<Box<dyn RegIo>> {
+        match self.uio(name) {
+            Some(region) => Ok(Box::new(region)),
+            None => bail!(Mapping, "SimBackend: uio device not found: {}", name),
+        }
+    }
+
+    /// Only records whether the buffer was opened cached; see
+    /// `SimRegion::take_syncs`.
+    fn open_udmabuf(&self, name: &str, cache_enable: bool) -> Result<Box<dyn BufIo>> {
+        match self.udmabuf(name) {
+            Some(region) => {
+                region.lock().cached |= cache_enable;
+                Ok(Box::new(region))
+            }
+            None => bail!(Mapping, "SimBackend: udmabuf device not found: {}", name),
+        }
+    }
+}
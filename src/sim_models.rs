@@ -0,0 +1,946 @@
+//! Behavioural models of the IPs for `crate::sim::SimBackend`.
+//!
+//! The models only reproduce what the drivers can observe: the register
+//! handshake and the data they move through udmabuf memory. Every `ap_start`
+//! processes exactly one frame immediately, so no time passes in between.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::ap_ctrl::{
+    AP_AUTO_RESTART, AP_CTRL, AP_DONE, AP_GIE, AP_IDLE, AP_IER, AP_INT_DONE, AP_ISR, AP_READY,
+    AP_START,
+};
+use crate::sim::{SimContext, SimModel};
+use crate::umv_lane_detector::LanePoint;
+use crate::v_frmbuf::ColorFormat;
+
+fn ap_ctrl_reset(ctx: &SimContext) {
+    ctx.regs().write32(AP_CTRL, AP_IDLE);
+}
+
+/// HLS `ap_ctrl_hs` handshake with the `s_axilite` interrupt registers.
+/// `run` is called when the driver sets `ap_start`.
+fn ap_ctrl_write(ctx: &SimContext, offset: usize, data: u32, run: impl FnOnce(&SimContext)) {
+    let regs = ctx.regs();
+    match offset {
+        AP_CTRL => {
+            let auto_restart = data & AP_AUTO_RESTART;
+            if data & AP_START != 0 {
+                run(ctx);
+                // With auto-restart the core immediately starts the next frame.
+                let state = if auto_restart != 0 { AP_START } else { AP_IDLE };
+                regs.write32(AP_CTRL, auto_restart | AP_DONE | AP_READY | state);
+                if regs.read32(AP_IER) & AP_INT_DONE != 0 {
+                    regs.write32(AP_ISR, regs.read32(AP_ISR) | AP_INT_DONE);
+                }
+                if regs.read32(AP_GIE) & 1 != 0 && regs.read32(AP_ISR) != 0 {
+                    ctx.raise_irq();
+                }
+            } else {
+                let ctrl = regs.read32(AP_CTRL);
+                regs.write32(AP_CTRL, (ctrl & AP_DONE) | AP_IDLE | auto_restart);
+            }
+        }
+        // ISR is toggle-on-write.
+        AP_ISR => regs.write32(AP_ISR, regs.read32(AP_ISR) ^ data),
+        _ => regs.write32(offset, data),
+    }
+}
+
+fn ap_ctrl_read(ctx: &SimContext, offset: usize) -> u32 {
+    let value = ctx.regs().read32(offset);
+    if offset == AP_CTRL {
+        // ap_done is clear-on-read.
+        ctx.regs().write32(AP_CTRL, value & !AP_DONE);
+    }
+    value
+}
+
+fn lock<T>(inner: &Mutex<T>) -> MutexGuard<'_, T> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+struct FrameGeometry {
+    width: usize,
+    stride: usize,
+    format: Option<ColorFormat>,
+    /// Address and lines of each plane.
+    planes: Vec<(usize, usize)>,
+}
+
+impl FrameGeometry {
+    /// `plane_regs` are the plane address registers of the direction.
+    fn read(ctx: &SimContext, plane_regs: [usize; 3]) -> Self {
+        let regs = ctx.regs();
+        let height = regs.read32(0x18);
+        let format = ColorFormat::from_id(regs.read32(0x28));
+        let planes = format.map_or(1, ColorFormat::planes);
+        FrameGeometry {
+            width: regs.read32(0x10) as usize,
+            stride: regs.read32(0x20) as usize,
+            format,
+            planes: plane_regs[..planes]
+                .iter()
+                .enumerate()
+                .map(|(i, &reg)| {
+                    let lines = format.map_or(height, |fmt| fmt.plane_height(i, height));
+                    (regs.read_addr(reg), lines as usize)
+                })
+                .collect(),
+        }
+    }
+
+    fn row_bytes(&self) -> usize {
+        match self.format {
+            Some(fmt) => fmt.line_bytes(self.width as u32) as usize,
+            None => self.width * 4,
+        }
+    }
+}
+
+#[derive(Default)]
+struct VideoFrameBufWriteState {
+    frame: Option<Vec<u8>>,
+    frame_count: u32,
+}
+
+/// v_frmbuf_wr: every `ap_start` writes one frame into the programmed buffer.
+///
+/// Without `set_frame` the frame is a gradient, byte `x` of row `y` of frame
+/// `n` being `(x + y + n) as u8`.
+#[derive(Clone, Default)]
+pub struct VideoFrameBufWriteModel {
+    state: Arc<Mutex<VideoFrameBufWriteState>>,
+}
+
+impl VideoFrameBufWriteModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `frame` (tightly packed rows) for every following capture.
+    pub fn set_frame(&self, frame: Vec<u8>) {
+        lock(&self.state).frame = Some(frame);
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        lock(&self.state).frame_count
+    }
+
+    fn capture(&self, ctx: &SimContext) {
+        let mut state = lock(&self.state);
+        let geom = FrameGeometry::read(ctx, [0x30, 0x3C, 0x5C]);
+        let row_bytes = geom.row_bytes();
+        // Rows are numbered across the planes, as they follow each other in
+        // `set_frame`.
+        let mut y = 0;
+        for &(addr, lines) in &geom.planes {
+            if let Some((buf, offset)) = ctx.buffer_at(addr) {
+                for line in 0..lines {
+                    let row: Vec<u8> = match &state.frame {
+                        Some(frame) => frame[(y + line) * row_bytes..][..row_bytes].to_vec(),
+                        None => (0..row_bytes)
+                            .map(|x| (x + y + line + state.frame_count as usize) as u8)
+                            .collect(),
+                    };
+                    buf.write_bytes(offset + line * geom.stride, &row);
+                }
+            }
+            y += lines;
+        }
+        state.frame_count += 1;
+    }
+}
+
+impl SimModel for VideoFrameBufWriteModel {
+    fn reset(&mut self, ctx: &SimContext) {
+        ap_ctrl_reset(ctx);
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        ap_ctrl_write(ctx, offset, data, |ctx| self.capture(ctx));
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        ap_ctrl_read(ctx, offset)
+    }
+}
+
+#[derive(Default)]
+struct VideoFrameBufReadState {
+    last_frame: Option<Vec<u8>>,
+    frame_count: u32,
+}
+
+/// v_frmbuf_rd: every `ap_start` reads one frame out of the programmed buffer.
+/// Planes follow each other in `last_frame`.
+#[derive(Clone, Default)]
+pub struct VideoFrameBufReadModel {
+    state: Arc<Mutex<VideoFrameBufReadState>>,
+}
+
+impl VideoFrameBufReadModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The last frame the core streamed out, with rows tightly packed.
+    pub fn last_frame(&self) -> Option<Vec<u8>> {
+        lock(&self.state).last_frame.clone()
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        lock(&self.state).frame_count
+    }
+
+    fn stream_out(&self, ctx: &SimContext) {
+        let mut state = lock(&self.state);
+        let geom = FrameGeometry::read(ctx, [0x30, 0x3C, 0x74]);
+        let row_bytes = geom.row_bytes();
+        let mut frame = Vec::new();
+        for &(addr, lines) in &geom.planes {
+            let Some((buf, offset)) = ctx.buffer_at(addr) else {
+                break;
+            };
+            for line in 0..lines {
+                frame.extend(buf.read_bytes(offset + line * geom.stride, row_bytes));
+            }
+        }
+        if !frame.is_empty() {
+            state.last_frame = Some(frame);
+        }
+        state.frame_count += 1;
+    }
+}
+
+impl SimModel for VideoFrameBufReadModel {
+    fn reset(&mut self, ctx: &SimContext) {
+        ap_ctrl_reset(ctx);
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        ap_ctrl_write(ctx, offset, data, |ctx| self.stream_out(ctx));
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        ap_ctrl_read(ctx, offset)
+    }
+}
+
+const FINDLINES_STATUS: usize = 0x00;
+const FINDLINES_START: usize = 0x04;
+const MEM_BASE_ADDR: usize = 0x28;
+const MEM_SIZE: usize = 0x2C;
+const FINDLINES_DETECT_COUNT: usize = 0x30;
+
+/// umv_lane_detector: after `FINDLINES_START` the status register advances
+/// 1 (waiting) -> 2 (running) -> 3 (done) on every status read, and the
+/// configured points are written to the result buffer on the way to done.
+#[derive(Clone, Default)]
+pub struct UmvLaneDetectorModel {
+    points: Arc<Mutex<Vec<LanePoint>>>,
+}
+
+impl UmvLaneDetectorModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Points reported by the following detections.
+    pub fn set_points(&self, points: Vec<LanePoint>) {
+        *lock(&self.points) = points;
+    }
+
+    fn finish(&self, ctx: &SimContext) {
+        let regs = ctx.regs();
+        let points = lock(&self.points);
+        let mem_words = regs.read32(MEM_SIZE) as usize;
+        let mut count = 0;
+        if let Some((buf, offset)) = ctx.buffer_at(regs.read32(MEM_BASE_ADDR) as usize) {
+            for (i, p) in points.iter().take(mem_words).enumerate() {
+                let word = ((p.direction & 0xf) << 28) | ((p.x & 0x3fff) << 14) | (p.y & 0x3fff);
+                buf.write32(offset + 4 * i, word);
+                count += 1;
+            }
+        }
+        regs.write32(FINDLINES_DETECT_COUNT, count);
+    }
+}
+
+impl SimModel for UmvLaneDetectorModel {
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        let regs = ctx.regs();
+        regs.write32(offset, data);
+        if offset == FINDLINES_START && data & 1 != 0 {
+            regs.write32(FINDLINES_STATUS, 1);
+        }
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        let regs = ctx.regs();
+        let value = regs.read32(offset);
+        if offset == FINDLINES_STATUS {
+            match value {
+                1 => regs.write32(FINDLINES_STATUS, 2),
+                2 => {
+                    self.finish(ctx);
+                    regs.write32(FINDLINES_STATUS, 3);
+                }
+                _ => {}
+            }
+        }
+        value
+    }
+}
+
+const BEV_IMG_IN: usize = 0x18;
+const BEV_IMG_MAP: usize = 0x24;
+const BEV_IMG_OUT: usize = 0x30;
+
+/// bird_eye_view: a look-up remap. Output pixel `i` is input pixel `map[i]`,
+/// or 0 when the index is out of range.
+#[derive(Clone)]
+pub struct BirdEyeViewModel {
+    width: usize,
+    height: usize,
+}
+
+impl BirdEyeViewModel {
+    pub fn new(width: usize, height: usize) -> Self {
+        BirdEyeViewModel { width, height }
+    }
+
+    fn remap(&self, ctx: &SimContext) {
+        let regs = ctx.regs();
+        let pixels = self.width * self.height;
+        let img_in = ctx.buffer_at(regs.read_addr(BEV_IMG_IN));
+        let img_map = ctx.buffer_at(regs.read_addr(BEV_IMG_MAP));
+        let img_out = ctx.buffer_at(regs.read_addr(BEV_IMG_OUT));
+        if let (Some((src, src_off)), Some((map, map_off)), Some((dst, dst_off))) =
+            (img_in, img_map, img_out)
+        {
+            let src_pixels = (src.size() - src_off) / 4;
+            for i in 0..pixels {
+                let index = map.read32(map_off + 4 * i) as usize;
+                let pixel = if index < src_pixels {
+                    src.read32(src_off + 4 * index)
+                } else {
+                    0
+                };
+                dst.write32(dst_off + 4 * i, pixel);
+            }
+        }
+    }
+}
+
+impl SimModel for BirdEyeViewModel {
+    fn reset(&mut self, ctx: &SimContext) {
+        ap_ctrl_reset(ctx);
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        ap_ctrl_write(ctx, offset, data, |ctx| self.remap(ctx));
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        ap_ctrl_read(ctx, offset)
+    }
+}
+
+const DMA_DMACR: usize = 0x00;
+const DMA_DMASR: usize = 0x04;
+const DMA_CURDESC: usize = 0x08;
+const DMA_CURDESC_MSB: usize = 0x0C;
+const DMA_TAILDESC: usize = 0x10;
+const DMA_TAILDESC_MSB: usize = 0x14;
+const DMA_SA: usize = 0x18;
+const DMA_LENGTH: usize = 0x28;
+const DMA_S2MM_OFFSET: usize = 0x30;
+
+const DMACR_RESET: u32 = 0x04;
+const DMACR_DEFAULT: u32 = 0x0001_0000;
+const DMASR_HALTED: u32 = 0x01;
+const DMASR_IDLE: u32 = 0x02;
+const DMASR_SG_INCLD: u32 = 0x08;
+const DMA_IRQ_IOC: u32 = 1 << 12;
+const DMA_IRQ_DELAY: u32 = 1 << 13;
+const DMA_IRQ_ALL: u32 = 0x7000;
+
+const BD_NXTDESC: usize = 0x00;
+const BD_BUFFER_ADDRESS: usize = 0x08;
+const BD_CONTROL: usize = 0x18;
+const BD_STATUS: usize = 0x1C;
+const BD_LEN_MASK: u32 = 0x03FF_FFFF;
+const BD_CONTROL_EOF: u32 = 1 << 26;
+const BD_STATUS_CMPLT: u32 = 1 << 31;
+const BD_STATUS_SOF: u32 = 1 << 27;
+const BD_STATUS_EOF: u32 = 1 << 26;
+
+#[derive(Default)]
+struct AxiDmaState {
+    /// Packets MM2S streamed out, and the one being assembled.
+    sent: Vec<Vec<u8>>,
+    partial: Vec<u8>,
+    /// Packets waiting on the S2MM stream; the front one may be partly
+    /// consumed.
+    incoming: VecDeque<Vec<u8>>,
+    received: usize,
+    /// Next descriptor to process and the tail, per channel.
+    next_bd: [Option<usize>; 2],
+    tail_bd: [Option<usize>; 2],
+    /// Completed descriptors not yet reported by an interrupt.
+    ioc_count: [u32; 2],
+}
+
+/// axi_dma: transfers complete as soon as they are programmed.
+///
+/// In simple mode the `LENGTH` write moves the data; in scatter-gather mode
+/// the `TAILDESC` write processes every descriptor up to the tail. S2MM
+/// stops at the first descriptor with no stream data to fill it and resumes
+/// when the driver next reads `DMASR`.
+///
+/// Interrupts follow DMACR: IOC after `IRQThreshold` descriptors, and the
+/// delay interrupt, which takes no time here, as soon as the channel runs
+/// out of work with completions left unreported.
+///
+/// With an address width above 32 (`with_addr_width`) the descriptor fetch
+/// starts on the `TAILDESC_MSB` write instead.
+#[derive(Clone, Default)]
+pub struct AxiDmaModel {
+    scatter_gather: bool,
+    ext_addr: bool,
+    state: Arc<Mutex<AxiDmaState>>,
+}
+
+impl AxiDmaModel {
+    pub fn new(scatter_gather: bool) -> Self {
+        AxiDmaModel {
+            scatter_gather,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_addr_width(mut self, addr_width: u32) -> Self {
+        self.ext_addr = addr_width > 32;
+        self
+    }
+
+    /// Packets streamed out by MM2S so far.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        lock(&self.state).sent.clone()
+    }
+
+    /// Queues a packet on the S2MM stream.
+    pub fn push_packet(&self, packet: Vec<u8>) {
+        lock(&self.state).incoming.push_back(packet);
+    }
+
+    fn simple_transfer(&self, ctx: &SimContext, base: usize, len: usize) {
+        let mut state = lock(&self.state);
+        let addr = ctx.regs().read_addr(base + DMA_SA);
+        let (buf, offset) = match ctx.buffer_at(addr) {
+            Some(found) => found,
+            None => return,
+        };
+        if base == 0 {
+            let packet = buf.read_bytes(offset, len);
+            state.sent.push(packet);
+        } else {
+            let packet = state.incoming.pop_front().unwrap_or_default();
+            let n = packet.len().min(len);
+            buf.write_bytes(offset, &packet[..n]);
+            ctx.regs().write32(base + DMA_LENGTH, n as u32);
+        }
+    }
+
+    fn tail_written(&self, ctx: &SimContext, base: usize) {
+        let regs = ctx.regs();
+        {
+            let mut state = lock(&self.state);
+            let channel = usize::from(base != 0);
+            state.tail_bd[channel] = Some(regs.read_addr(base + DMA_TAILDESC));
+            if state.next_bd[channel].is_none() {
+                // Continue behind the last processed descriptor.
+                let cur = regs.read_addr(base + DMA_CURDESC);
+                state.next_bd[channel] = ctx
+                    .buffer_at(cur)
+                    .map(|(ring, off)| ring.read_addr(off + BD_NXTDESC));
+            }
+        }
+        self.process_descriptors(ctx, base);
+    }
+
+    fn process_descriptors(&self, ctx: &SimContext, base: usize) {
+        let mut state = lock(&self.state);
+        let channel = usize::from(base != 0);
+        let tail = match state.tail_bd[channel] {
+            Some(tail) => tail,
+            None => return,
+        };
+        let regs = ctx.regs();
+        while let Some(bd) = state.next_bd[channel] {
+            let (ring, bd_off) = match ctx.buffer_at(bd) {
+                Some(found) => found,
+                None => break,
+            };
+            let addr = ring.read_addr(bd_off + BD_BUFFER_ADDRESS);
+            let control = ring.read32(bd_off + BD_CONTROL);
+            let len = (control & BD_LEN_MASK) as usize;
+            let status = if base == 0 {
+                if let Some((buf, offset)) = ctx.buffer_at(addr) {
+                    state.partial.extend(buf.read_bytes(offset, len));
+                }
+                if control & BD_CONTROL_EOF != 0 {
+                    let packet = std::mem::take(&mut state.partial);
+                    state.sent.push(packet);
+                }
+                len as u32
+            } else {
+                let packet = match state.incoming.front() {
+                    Some(packet) => packet,
+                    None => break,
+                };
+                let start = state.received;
+                let n = (packet.len() - start).min(len);
+                if let Some((buf, offset)) = ctx.buffer_at(addr) {
+                    buf.write_bytes(offset, &packet[start..start + n]);
+                }
+                let mut status = n as u32;
+                if start == 0 {
+                    status |= BD_STATUS_SOF;
+                }
+                if start + n == packet.len() {
+                    status |= BD_STATUS_EOF;
+                    state.incoming.pop_front();
+                    state.received = 0;
+                } else {
+                    state.received += n;
+                }
+                status
+            };
+            ring.write32(bd_off + BD_STATUS, BD_STATUS_CMPLT | status);
+            regs.write32(base + DMA_CURDESC, bd as u32);
+            if self.ext_addr {
+                regs.write32(base + DMA_CURDESC + 4, (bd as u64 >> 32) as u32);
+            }
+            let threshold = (regs.read32(base + DMA_DMACR) >> 16 & 0xFF).max(1);
+            state.ioc_count[channel] += 1;
+            if state.ioc_count[channel] >= threshold {
+                state.ioc_count[channel] = 0;
+                interrupt(ctx, base, DMA_IRQ_IOC);
+            }
+            state.next_bd[channel] = (bd != tail).then(|| ring.read_addr(bd_off + BD_NXTDESC));
+        }
+        let delay = regs.read32(base + DMA_DMACR) >> 24;
+        if state.ioc_count[channel] > 0 && delay > 0 {
+            state.ioc_count[channel] = 0;
+            interrupt(ctx, base, DMA_IRQ_DELAY);
+        }
+        let done = state.next_bd[channel].is_none();
+        let dmasr = regs.read32(base + DMA_DMASR) & !DMASR_IDLE;
+        regs.write32(base + DMA_DMASR, dmasr | if done { DMASR_IDLE } else { 0 });
+    }
+}
+
+impl SimModel for AxiDmaModel {
+    /// Also the soft reset (DMACR.Reset), which covers both channels.
+    fn reset(&mut self, ctx: &SimContext) {
+        let sg = if self.scatter_gather {
+            DMASR_SG_INCLD
+        } else {
+            0
+        };
+        for base in [0, DMA_S2MM_OFFSET] {
+            ctx.regs().write32(base + DMA_DMACR, DMACR_DEFAULT);
+            ctx.regs().write32(base + DMA_DMASR, sg | DMASR_HALTED);
+        }
+        let mut state = lock(&self.state);
+        state.partial.clear();
+        state.received = 0;
+        state.next_bd = [None; 2];
+        state.tail_bd = [None; 2];
+        state.ioc_count = [0; 2];
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        let regs = ctx.regs();
+        let (base, reg) = if offset >= DMA_S2MM_OFFSET {
+            (DMA_S2MM_OFFSET, offset - DMA_S2MM_OFFSET)
+        } else {
+            (0, offset)
+        };
+        let dmasr = regs.read32(base + DMA_DMASR);
+        if reg == DMA_DMASR {
+            // Interrupt bits are write-1-to-clear, the rest is read-only.
+            regs.write32(offset, dmasr & !(data & DMA_IRQ_ALL));
+            return;
+        }
+        if reg == DMA_DMACR && data & DMACR_RESET != 0 {
+            self.reset(ctx);
+            return;
+        }
+        regs.write32(offset, data);
+        let halted = dmasr & DMASR_HALTED != 0;
+        let dmasr = dmasr & (DMASR_SG_INCLD | DMA_IRQ_ALL);
+        match reg {
+            DMA_DMACR if data & 1 == 0 => regs.write32(base + DMA_DMASR, dmasr | DMASR_HALTED),
+            // Only starting a halted channel changes its state.
+            DMA_DMACR if halted => regs.write32(base + DMA_DMASR, dmasr | DMASR_IDLE),
+            DMA_CURDESC | DMA_CURDESC_MSB if self.scatter_gather => {
+                let mut state = lock(&self.state);
+                state.next_bd[usize::from(base != 0)] = Some(regs.read_addr(base + DMA_CURDESC));
+                state.tail_bd[usize::from(base != 0)] = None;
+            }
+            DMA_TAILDESC if self.scatter_gather && !self.ext_addr => {
+                self.tail_written(ctx, base);
+            }
+            DMA_TAILDESC_MSB if self.scatter_gather && self.ext_addr => {
+                self.tail_written(ctx, base);
+            }
+            DMA_LENGTH if !self.scatter_gather => {
+                regs.write32(base + DMA_DMASR, dmasr);
+                self.simple_transfer(ctx, base, data as usize);
+                regs.write32(base + DMA_DMASR, dmasr | DMASR_IDLE);
+                interrupt(ctx, base, DMA_IRQ_IOC);
+            }
+            _ => {}
+        }
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        let waiting = {
+            let state = lock(&self.state);
+            state.next_bd[1].is_some() && state.tail_bd[1].is_some()
+        };
+        if offset == DMA_S2MM_OFFSET + DMA_DMASR && self.scatter_gather && waiting {
+            self.process_descriptors(ctx, DMA_S2MM_OFFSET);
+        }
+        ctx.regs().read32(offset)
+    }
+}
+
+/// Sets `bits` in DMASR and raises the interrupt if DMACR enables them.
+fn interrupt(ctx: &SimContext, base: usize, bits: u32) {
+    let regs = ctx.regs();
+    regs.write32(base + DMA_DMASR, regs.read32(base + DMA_DMASR) | bits);
+    if regs.read32(base + DMA_DMACR) & bits != 0 {
+        ctx.raise_irq();
+    }
+}
+
+const VDMA_MM2S_DMACR: usize = 0x00;
+const VDMA_MM2S_DMASR: usize = 0x04;
+const VDMA_MM2S_FRMSTORE: usize = 0x18;
+const VDMA_PARK_PTR: usize = 0x28;
+const VDMA_MM2S_VSIZE: usize = 0x50;
+const VDMA_MM2S_HSIZE: usize = 0x54;
+const VDMA_MM2S_FRMDLY_STRIDE: usize = 0x58;
+const VDMA_MM2S_START_ADDRESS1: usize = 0x5C;
+const VDMA_S2MM_DMACR: usize = 0x30;
+const VDMA_S2MM_DMASR: usize = 0x34;
+const VDMA_S2MM_FRMSTORE: usize = 0x48;
+const VDMA_S2MM_VSIZE: usize = 0xA0;
+const VDMA_S2MM_HSIZE: usize = 0xA4;
+const VDMA_S2MM_FRMDLY_STRIDE: usize = 0xA8;
+const VDMA_S2MM_START_ADDRESS1: usize = 0xAC;
+
+const VDMACR_RS: u32 = 1 << 0;
+const VDMACR_CIRCULAR_PARK: u32 = 1 << 1;
+const VDMACR_RESET: u32 = 1 << 2;
+const VDMASR_HALTED: u32 = 1 << 0;
+const VDMASR_SOF_EARLY_ERR: u32 = 1 << 7;
+const VDMASR_SOF_LATE_ERR: u32 = 1 << 11;
+/// Frame size errors and interrupt bits, the write-1-to-clear part of VDMASR.
+const VDMASR_W1C: u32 = 0xF980 | VDMA_IRQ_ALL;
+const VDMA_IRQ_FRAME_COUNT: u32 = 1 << 12;
+const VDMA_IRQ_ERR: u32 = 1 << 14;
+const VDMA_IRQ_ALL: u32 = 0x7000;
+
+#[derive(Default)]
+struct AxiVdmaS2MMState {
+    /// Queued frames, and whether each follows the previous one at once.
+    incoming: VecDeque<(Vec<u8>, bool)>,
+    /// Frame store of a frame that has started but is not written yet.
+    started: Option<usize>,
+    next_store: usize,
+    frame_count: u32,
+    error: u32,
+}
+
+/// axi_vdma S2MM channel: a frame queued with `push_frame` is written to
+/// memory when the driver next reads `S2MM_DMASR` with the channel running
+/// and no frame-count event pending.
+///
+/// Circular mode fills the frame stores in turn, park mode only the one
+/// `PARK_PTR_REG.WrFrmPtrRef` selects. A frame whose length is not
+/// `HSIZE * VSIZE` is written as far as it fits and flags SOFEarlyErr
+/// (short) or SOFLateErr (long).
+///
+/// A frame picks its frame store when it starts, which is when it is
+/// written, or right at the end of the previous one for frames queued with
+/// `push_frame_back_to_back`.
+#[derive(Clone, Default)]
+pub struct AxiVdmaS2MMModel {
+    ext_addr: bool,
+    state: Arc<Mutex<AxiVdmaS2MMState>>,
+}
+
+impl AxiVdmaS2MMModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_addr_width(mut self, addr_width: u32) -> Self {
+        self.ext_addr = addr_width > 32;
+        self
+    }
+
+    /// Queues a frame, rows tightly packed, on the S2MM stream.
+    pub fn push_frame(&self, frame: Vec<u8>) {
+        lock(&self.state).incoming.push_back((frame, false));
+    }
+
+    /// Queues a frame that starts as soon as the previous one is written,
+    /// before the driver can move the park pointer.
+    pub fn push_frame_back_to_back(&self, frame: Vec<u8>) {
+        lock(&self.state).incoming.push_back((frame, true));
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        lock(&self.state).frame_count
+    }
+
+    /// Sets `bits` in `S2MM_DMASR`, as the core would on a failed write,
+    /// when the driver next reads it.
+    pub fn inject_error(&self, bits: u32) {
+        lock(&self.state).error |= bits;
+    }
+
+    fn capture(&self, ctx: &SimContext) {
+        let regs = ctx.regs();
+        let mut state = lock(&self.state);
+        if state.error != 0 {
+            let status = regs.read32(VDMA_S2MM_DMASR) | state.error | VDMA_IRQ_ERR;
+            regs.write32(VDMA_S2MM_DMASR, status);
+            state.error = 0;
+            if regs.read32(VDMA_S2MM_DMACR) & VDMA_IRQ_ERR != 0 {
+                ctx.raise_irq();
+            }
+        }
+        let vsize = regs.read32(VDMA_S2MM_VSIZE) as usize;
+        let running = regs.read32(VDMA_S2MM_DMACR) & VDMACR_RS != 0;
+        let pending = regs.read32(VDMA_S2MM_DMASR) & VDMA_IRQ_FRAME_COUNT != 0;
+        if !running || pending || vsize == 0 {
+            return;
+        }
+        let frame = match state.incoming.pop_front() {
+            Some((frame, _)) => frame,
+            None => return,
+        };
+        let stores = (regs.read32(VDMA_S2MM_FRMSTORE) as usize).max(1);
+        let store = match state.started.take() {
+            Some(store) => store,
+            None => Self::start_store(ctx, &mut state, stores),
+        };
+        let addr = vdma_start_address(ctx, VDMA_S2MM_START_ADDRESS1, store, self.ext_addr);
+        let hsize = regs.read32(VDMA_S2MM_HSIZE) as usize;
+        let stride = (regs.read32(VDMA_S2MM_FRMDLY_STRIDE) & 0xFFFF) as usize;
+        if let Some((buf, offset)) = ctx.buffer_at(addr) {
+            for (y, row) in frame.chunks(hsize).take(vsize).enumerate() {
+                buf.write_bytes(offset + y * stride, row);
+            }
+        }
+        let mut status = regs.read32(VDMA_S2MM_DMASR) | VDMA_IRQ_FRAME_COUNT;
+        if frame.len() < hsize * vsize {
+            status |= VDMASR_SOF_EARLY_ERR | VDMA_IRQ_ERR;
+        } else if frame.len() > hsize * vsize {
+            status |= VDMASR_SOF_LATE_ERR | VDMA_IRQ_ERR;
+        }
+        regs.write32(VDMA_S2MM_DMASR, status);
+        // The store being written next.
+        let current = if matches!(state.incoming.front(), Some((_, true))) {
+            let next = Self::start_store(ctx, &mut state, stores);
+            state.started = Some(next);
+            next
+        } else if regs.read32(VDMA_S2MM_DMACR) & VDMACR_CIRCULAR_PARK != 0 {
+            state.next_store % stores
+        } else {
+            store
+        };
+        let park_ptr = regs.read32(VDMA_PARK_PTR);
+        regs.write32(
+            VDMA_PARK_PTR,
+            (park_ptr & !(0x1F << 24)) | (current as u32) << 24,
+        );
+        state.frame_count += 1;
+        if regs.read32(VDMA_S2MM_DMACR) & status & VDMA_IRQ_ALL != 0 {
+            ctx.raise_irq();
+        }
+    }
+
+    /// Frame store of a frame starting now.
+    fn start_store(ctx: &SimContext, state: &mut AxiVdmaS2MMState, stores: usize) -> usize {
+        let regs = ctx.regs();
+        if regs.read32(VDMA_S2MM_DMACR) & VDMACR_CIRCULAR_PARK != 0 {
+            let store = state.next_store % stores;
+            state.next_store = store + 1;
+            store
+        } else {
+            ((regs.read32(VDMA_PARK_PTR) >> 8) & 0x1F) as usize
+        }
+    }
+}
+
+impl SimModel for AxiVdmaS2MMModel {
+    fn reset(&mut self, ctx: &SimContext) {
+        ctx.regs().write32(VDMA_S2MM_DMACR, 0x0001_0000);
+        ctx.regs().write32(VDMA_S2MM_DMASR, VDMASR_HALTED);
+        let mut state = lock(&self.state);
+        state.next_store = 0;
+        state.started = None;
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        let regs = ctx.regs();
+        match offset {
+            VDMA_S2MM_DMACR if data & VDMACR_RESET != 0 => self.reset(ctx),
+            VDMA_S2MM_DMACR | VDMA_S2MM_DMASR => {
+                vdma_channel_write(ctx, VDMA_S2MM_DMACR, offset, data)
+            }
+            _ => regs.write32(offset, data),
+        }
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        if offset == VDMA_S2MM_DMASR {
+            self.capture(ctx);
+        }
+        ctx.regs().read32(offset)
+    }
+}
+
+/// Frame store `store` of the start address bank at `first`.
+fn vdma_start_address(ctx: &SimContext, first: usize, store: usize, ext_addr: bool) -> usize {
+    if ext_addr {
+        ctx.regs().read_addr(first + 8 * store)
+    } else {
+        ctx.regs().read32(first + 4 * store) as usize
+    }
+}
+
+/// Writes to the DMACR (without Reset) or DMASR of the channel at `dmacr`.
+fn vdma_channel_write(ctx: &SimContext, dmacr: usize, offset: usize, data: u32) {
+    let regs = ctx.regs();
+    let dmasr = regs.read32(dmacr + 4);
+    if offset == dmacr {
+        regs.write32(offset, data);
+        let halted = if data & VDMACR_RS == 0 {
+            VDMASR_HALTED
+        } else {
+            0
+        };
+        regs.write32(dmacr + 4, dmasr & !VDMASR_HALTED | halted);
+    } else {
+        regs.write32(offset, dmasr & !(data & VDMASR_W1C));
+    }
+}
+
+#[derive(Default)]
+struct AxiVdmaMM2SState {
+    periods: u32,
+    next_store: usize,
+    shown: Vec<usize>,
+    last_frame: Option<Vec<u8>>,
+}
+
+/// axi_vdma MM2S channel: `advance` lets frame periods pass, which happens
+/// when the driver next reads `MM2S_DMASR` or `PARK_PTR_REG`.
+///
+/// Every period reads one frame store, the one `PARK_PTR_REG.RdFrmPtrRef`
+/// selects in park mode or the next one in circular mode, reports it in
+/// `RdFrmStore` and sets FrmCnt_Irq.
+#[derive(Clone, Default)]
+pub struct AxiVdmaMM2SModel {
+    ext_addr: bool,
+    state: Arc<Mutex<AxiVdmaMM2SState>>,
+}
+
+impl AxiVdmaMM2SModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_addr_width(mut self, addr_width: u32) -> Self {
+        self.ext_addr = addr_width > 32;
+        self
+    }
+
+    pub fn advance(&self, periods: u32) {
+        lock(&self.state).periods += periods;
+    }
+
+    /// Frame store read in every period so far.
+    pub fn shown(&self) -> Vec<usize> {
+        lock(&self.state).shown.clone()
+    }
+
+    /// The last frame streamed out, with rows tightly packed.
+    pub fn last_frame(&self) -> Option<Vec<u8>> {
+        lock(&self.state).last_frame.clone()
+    }
+
+    fn stream_out(&self, ctx: &SimContext) {
+        let regs = ctx.regs();
+        let mut state = lock(&self.state);
+        let running = regs.read32(VDMA_MM2S_DMACR) & VDMACR_RS != 0;
+        let vsize = regs.read32(VDMA_MM2S_VSIZE) as usize;
+        if !running || vsize == 0 {
+            return;
+        }
+        let stores = (regs.read32(VDMA_MM2S_FRMSTORE) as usize).max(1);
+        let hsize = regs.read32(VDMA_MM2S_HSIZE) as usize;
+        let stride = (regs.read32(VDMA_MM2S_FRMDLY_STRIDE) & 0xFFFF) as usize;
+        while state.periods > 0 {
+            state.periods -= 1;
+            let park_ptr = regs.read32(VDMA_PARK_PTR);
+            let store = if regs.read32(VDMA_MM2S_DMACR) & VDMACR_CIRCULAR_PARK != 0 {
+                let store = state.next_store % stores;
+                state.next_store = store + 1;
+                store
+            } else {
+                (park_ptr & 0x1F) as usize
+            };
+            let addr = vdma_start_address(ctx, VDMA_MM2S_START_ADDRESS1, store, self.ext_addr);
+            if let Some((buf, offset)) = ctx.buffer_at(addr) {
+                let mut frame = Vec::with_capacity(hsize * vsize);
+                for y in 0..vsize {
+                    frame.extend(buf.read_bytes(offset + y * stride, hsize));
+                }
+                state.last_frame = Some(frame);
+            }
+            state.shown.push(store);
+            regs.write32(
+                VDMA_PARK_PTR,
+                (park_ptr & !(0x1F << 16)) | (store as u32) << 16,
+            );
+            let dmasr = regs.read32(VDMA_MM2S_DMASR);
+            regs.write32(VDMA_MM2S_DMASR, dmasr | VDMA_IRQ_FRAME_COUNT);
+            if regs.read32(VDMA_MM2S_DMACR) & VDMA_IRQ_FRAME_COUNT != 0 {
+                ctx.raise_irq();
+            }
+        }
+    }
+}
+
+impl SimModel for AxiVdmaMM2SModel {
+    fn reset(&mut self, ctx: &SimContext) {
+        ctx.regs().write32(VDMA_MM2S_DMACR, 0x0001_0000);
+        ctx.regs().write32(VDMA_MM2S_DMASR, VDMASR_HALTED);
+        lock(&self.state).next_store = 0;
+    }
+    fn write(&mut self, ctx: &SimContext, offset: usize, data: u32) {
+        match offset {
+            VDMA_MM2S_DMACR if data & VDMACR_RESET != 0 => self.reset(ctx),
+            VDMA_MM2S_DMACR | VDMA_MM2S_DMASR => {
+                vdma_channel_write(ctx, VDMA_MM2S_DMACR, offset, data)
+            }
+            _ => ctx.regs().write32(offset, data),
+        }
+    }
+    fn read(&mut self, ctx: &SimContext, offset: usize) -> u32 {
+        if offset == VDMA_MM2S_DMASR || offset == VDMA_PARK_PTR {
+            self.stream_out(ctx);
+        }
+        ctx.regs().read32(offset)
+    }
+}
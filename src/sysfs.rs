@@ -0,0 +1,171 @@
+//! UIO and u-dma-buf devices as listed in sysfs.
+//!
+//! The root is configurable so that discovery can run against a fake tree.
+
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, XipError};
+
+/// `/sys/class/uio/uioN`, with the first memory map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UioDevice {
+    /// Node name, e.g. `uio3`.
+    pub node: String,
+    /// Contents of `name`, which is what `UioAccessor::new_with_name` matches.
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
+
+/// `/sys/class/u-dma-buf/<name>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdmabufDevice {
+    pub name: String,
+    pub phys_addr: u64,
+    pub size: u64,
+}
+
+/// `sync_direction` values, as in the kernel's `enum dma_data_direction`.
+pub const DMA_BIDIRECTIONAL: u32 = 0;
+pub const DMA_TO_DEVICE: u32 = 1;
+pub const DMA_FROM_DEVICE: u32 = 2;
+
+/// Cache maintenance controls of a u-dma-buf device mapped cached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdmabufSync {
+    dir: PathBuf,
+}
+
+impl UdmabufSync {
+    /// Invalidates `offset..offset + len` so the CPU sees what the PL wrote.
+    pub fn sync_for_cpu(&self, offset: usize, len: usize) -> Result<()> {
+        self.sync("sync_for_cpu", DMA_FROM_DEVICE, offset, len)
+    }
+
+    /// Writes `offset..offset + len` back so the PL sees what the CPU wrote.
+    pub fn sync_for_device(&self, offset: usize, len: usize) -> Result<()> {
+        self.sync("sync_for_device", DMA_TO_DEVICE, offset, len)
+    }
+
+    fn sync(&self, control: &str, direction: u32, offset: usize, len: usize) -> Result<()> {
+        write_value(&self.dir.join("sync_offset"), offset)?;
+        write_value(&self.dir.join("sync_size"), len)?;
+        write_value(&self.dir.join("sync_direction"), direction)?;
+        write_value(&self.dir.join(control), 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sysfs {
+    root: PathBuf,
+}
+
+impl Default for Sysfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sysfs {
+    pub fn new() -> Self {
+        Self::with_root("/sys")
+    }
+
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Sysfs { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Every UIO node with a memory map, sorted by node number. Nodes
+    /// without `maps/map0`, such as interrupt-only devices, are skipped. A
+    /// missing class directory (no UIO driver loaded) yields an empty list.
+    pub fn uio_devices(&self) -> Result<Vec<UioDevice>> {
+        let mut devices = Vec::new();
+        for dir in list_dir(&self.root.join("class/uio"))? {
+            let node = file_name(&dir);
+            let number = match node
+                .strip_prefix("uio")
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                Some(number) => number,
+                None => continue,
+            };
+            let map0 = dir.join("maps/map0");
+            if !map0.is_dir() {
+                continue;
+            }
+            let device = UioDevice {
+                name: read_string(&dir.join("name"))?,
+                addr: read_u64(&map0.join("addr"))?,
+                size: read_u64(&map0.join("size"))?,
+                node,
+            };
+            devices.push((number, device));
+        }
+        devices.sort_by_key(|(number, _)| *number);
+        Ok(devices.into_iter().map(|(_, device)| device).collect())
+    }
+
+    pub fn udmabuf_devices(&self) -> Result<Vec<UdmabufDevice>> {
+        let mut devices = Vec::new();
+        for dir in list_dir(&self.root.join("class/u-dma-buf"))? {
+            devices.push(UdmabufDevice {
+                name: file_name(&dir),
+                phys_addr: read_u64(&dir.join("phys_addr"))?,
+                size: read_u64(&dir.join("size"))?,
+            });
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    pub fn udmabuf_sync(&self, name: &str) -> UdmabufSync {
+        UdmabufSync {
+            dir: self.root.join("class/u-dma-buf").join(name),
+        }
+    }
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(dir).map_err(|e| XipError::io("read", dir, e))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        paths.push(entry.map_err(|e| XipError::io("read", dir, e))?.path());
+    }
+    Ok(paths)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn read_string(path: &Path) -> Result<String> {
+    let value = fs::read_to_string(path).map_err(|e| XipError::io("read", path, e))?;
+    Ok(value.trim().to_string())
+}
+
+fn write_value(path: &Path, value: impl Display) -> Result<()> {
+    fs::write(path, value.to_string()).map_err(|e| XipError::io("write", path, e))
+}
+
+fn read_u64(path: &Path) -> Result<u64> {
+    let value = read_string(path)?;
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| XipError::Mapping(format!("{}: {:?} is not a number", path.display(), value)))
+}
@@ -0,0 +1,91 @@
+//! Bounded waits on status registers and interrupts.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::backend::RegIo;
+use crate::error::Result;
+
+/// Timeout of the blocking driver operations unless changed with the
+/// driver's `set_timeout`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A status register did not reach the expected state in time.
+///
+/// Returned as `XipError::Timeout`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeoutError {
+    pub operation: &'static str,
+    pub timeout: Duration,
+    /// Last value of the status register that was polled.
+    pub status: u32,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: timed out after {:?} (status = 0x{:08X})",
+            self.operation, self.timeout, self.status
+        )
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Calls `poll` until it returns `true`, an error, or `timeout` passes.
+/// `status` reads the register to report on timeout.
+pub fn wait_until(
+    operation: &'static str,
+    timeout: Duration,
+    mut poll: impl FnMut() -> Result<bool>,
+    status: impl FnOnce() -> u32,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if poll()? {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(TimeoutError {
+                operation,
+                timeout,
+                status: status(),
+            }
+            .into());
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// Deadline of a wait that sleeps on the UIO interrupt.
+pub struct IrqDeadline {
+    operation: &'static str,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+impl IrqDeadline {
+    pub fn new(operation: &'static str, timeout: Duration) -> Self {
+        IrqDeadline {
+            operation,
+            timeout,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// Sleeps until the next interrupt of `regs`. Fails with `Timeout`,
+    /// reporting the register at `status_offset`, if none comes in time.
+    pub fn wait_irq(&self, regs: &mut dyn RegIo, status_offset: usize) -> Result<()> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if regs.wait_irq_timeout(left)? {
+            return Ok(());
+        }
+        Err(TimeoutError {
+            operation: self.operation,
+            timeout: self.timeout,
+            status: unsafe { regs.read_mem32(status_offset) },
+        }
+        .into())
+    }
+}
@@ -1,10 +1,11 @@
-use anyhow::{ensure, Result, Context, bail};
+use std::time::Duration;
 
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
-use crate::json_as_u32;
+use crate::backend::{Backend, BufIo, DeviceBackend, RegIo};
+use crate::dma_buffer::split_phys_addr;
+use crate::error::{bail, ensure, Result};
+use crate::frame_pool::FramePool;
+use crate::hwinfo::IpDescriptor;
+use crate::timeout::{wait_until, DEFAULT_TIMEOUT};
 
 const FINDLINES_STATUS:usize          = 0x00;
 const FINDLINES_START:usize           = 0x04;
@@ -37,6 +38,16 @@ pub struct LanePoint {
     pub y: u32,
 }
 
+impl LanePoint {
+    fn from_word(data: u32) -> Self {
+        LanePoint {
+            direction: (data >> 28) & 0xf,
+            x: (data >> 14) & 0x3fff,
+            y: data & 0x3fff,
+        }
+    }
+}
+
 impl std::fmt::Display for LanePoint {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "({}, {}): {}", self.x, self.y, self.direction)
@@ -45,8 +56,8 @@ impl std::fmt::Display for LanePoint {
 
 
 pub struct UmvLaneDetector {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: UdmabufAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Box<dyn BufIo>,
     image_width: u32,
     image_height: u32,
     max_detect_interval: u32,
@@ -68,45 +79,34 @@ pub struct UmvLaneDetector {
     pub fl_hline_width_detect_min: u32,
     pub findlines_horizon: u32,
     pub fl_sequence_range: u32,
+    pool: Option<FramePool>,
+    /// Pool buffer the running detection writes to.
+    pool_writing: Option<usize>,
+    timeout: Duration,
 }
 
 impl UmvLaneDetector {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let udmabuf_name = json_as_str!(hw_object["udmabuf"][0]);
-        let image_width = json_as_u32!(hw_params["IMAGE_WIDTH"]);
-        let image_height = json_as_u32!(hw_params["IMAGE_HEIGHT"]);
-        let max_detect_lines = json_as_u32!(hw_params["MAX_DETECT_LINES"]);
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("slab", &["umv_project"], "umv_lane_detector", "UmvLaneDetector::new()")?;
+        let image_width = desc.param_u32("IMAGE_WIDTH")?;
+        let image_height = desc.param_u32("IMAGE_HEIGHT")?;
+        let max_detect_lines = desc.param_u32("MAX_DETECT_LINES")?;
         let max_detect_interval = (max_detect_lines as f32).log2() as u32;
-        let filter_type = json_as_u32!(hw_params["FILTER_TYPE_DEFAULT"]);
-        ensure!(
-            vendor == "slab" &&
-            library == "umv_project" &&
-            name == "umv_lane_detector",
-            "UmvLaneDetector::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
-        let udmabuf = match UdmabufAccessor::new(udmabuf_name, false) {
-            Ok(udmabuf_acc) => {
-                udmabuf_acc
-            },
-            Err(e) => {
-                bail!("UdmabufAccessor: {}", e)
-            }
-        };
+        let filter_type = desc.param_u32("FILTER_TYPE_DEFAULT")?;
+        let uio = backend.open_uio(desc.uio()?)?;
+        let udmabuf = backend.open_udmabuf(desc.udmabuf(0)?, false)?;
         Ok(UmvLaneDetector {
             uio_acc: uio,
             udmabuf_acc: udmabuf,
@@ -131,6 +131,9 @@ impl UmvLaneDetector {
             fl_hline_width_detect_min: 10,
             findlines_horizon: 0,
             fl_sequence_range: 5,
+            pool: None,
+            pool_writing: None,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
     pub fn get_status(&self) -> u32 {
@@ -155,35 +158,95 @@ impl UmvLaneDetector {
         }
         Ok(())
     }
-    pub fn stop(&self) {
+    /// Idle (0) or done (3); anything else is mid-detection.
+    pub fn poll_stopped(&self) -> bool {
+        self.get_status().is_multiple_of(3)
+    }
+    pub fn stop(&self) -> Result<()> {
         unsafe {
             self.uio_acc.write_mem32(FINDLINES_START, 0x00);
         }
-        while self.get_status() % 3 != 0 { }
-    }
-    pub fn write_framebuf_addr(&self) {
+        wait_until(
+            "UmvLaneDetector::stop",
+            self.timeout,
+            || Ok(self.poll_stopped()),
+            || self.get_status(),
+        )
+    }
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// The core only has a 32-bit base address register.
+    pub fn write_framebuf_addr(&self) -> Result<()> {
+        let (addr, size) = match (&self.pool, self.pool_writing) {
+            (Some(pool), Some(index)) => (pool.phys_addr(index)?, pool.spec().size()),
+            _ => (self.udmabuf_acc.phys_addr(), self.udmabuf_acc.size()),
+        };
+        let (mem_base_addr, _) = split_phys_addr(addr, 32)?;
         unsafe {
-            self.uio_acc
-                .write_mem32(MEM_BASE_ADDR, self.udmabuf_acc.phys_addr() as u32);
-            self.uio_acc
-                .write_mem32(MEM_SIZE, (self.udmabuf_acc.size() / 4) as u32);
+            self.uio_acc.write_mem32(MEM_BASE_ADDR, mem_base_addr);
+            self.uio_acc.write_mem32(MEM_SIZE, (size / 4) as u32);
         }
+        Ok(())
     }
-    pub fn read_data(&self) -> Vec<LanePoint> {
-        self.stop();
+    pub fn read_data(&self) -> Result<Vec<LanePoint>> {
+        self.stop()?;
         let detect_cnt = unsafe { self.uio_acc.read_mem32(FINDLINES_DETECT_COUNT) } as usize;
         let data_num = detect_cnt.min(self.udmabuf_acc.size() / 4);
         let mut buf = Vec::with_capacity(data_num);
         for i in 0..data_num {
-            let data = unsafe { self.udmabuf_acc.read_mem32(0x00 + 4 * i) };
-            let point = LanePoint {
-                direction: (data >> 28) & 0xf,
-                x: (data >> 14) & 0x3fff,
-                y: data & 0x3fff,
-            };
-            buf.push(point);
+            let data = unsafe { self.udmabuf_acc.read_mem32(4 * i) };
+            buf.push(LanePoint::from_word(data));
         }
-        buf
+        Ok(buf)
+    }
+    /// Writes detection results to the buffers of `pool` instead of the
+    /// udmabuf, one buffer per detection. Only the size of the pool's
+    /// frames matters.
+    pub fn attach_pool(&mut self, pool: &FramePool) -> Result<()> {
+        let size = pool.spec().size();
+        ensure!(size >= 4, Format, "UmvLaneDetector: pool frames of {} bytes hold no point", size);
+        self.pool = Some(pool.clone());
+        self.pool_writing = None;
+        Ok(())
+    }
+    pub fn detach_pool(&mut self) -> Result<()> {
+        if let (Some(pool), Some(index)) = (&self.pool, self.pool_writing.take()) {
+            pool.release_write(index)?;
+        }
+        self.pool = None;
+        Ok(())
+    }
+    /// Like `start`, detecting into a pool buffer no reader holds.
+    pub fn start_into_pool(&mut self) -> Result<()> {
+        let Some(pool) = &self.pool else {
+            bail!(InvalidState, "UmvLaneDetector: no FramePool attached");
+        };
+        if self.pool_writing.is_none() {
+            self.pool_writing = Some(pool.acquire_write()?);
+        }
+        self.start()
+    }
+    /// Stops the detection started with `start_into_pool`, publishes its
+    /// buffer and returns the sequence number and the points.
+    pub fn read_pool_data(&mut self) -> Result<(u64, Vec<LanePoint>)> {
+        let (Some(pool), Some(index)) = (&self.pool, self.pool_writing) else {
+            bail!(InvalidState, "UmvLaneDetector: start_into_pool was not called");
+        };
+        self.stop()?;
+        let detect_cnt = unsafe { self.uio_acc.read_mem32(FINDLINES_DETECT_COUNT) } as usize;
+        let data = pool.read_frame(index)?;
+        let points = data
+            .chunks_exact(4)
+            .take(detect_cnt)
+            .map(|w| LanePoint::from_word(u32::from_le_bytes([w[0], w[1], w[2], w[3]])))
+            .collect();
+        self.pool_writing = None;
+        let sequence = pool.publish(index)?;
+        Ok((sequence, points))
     }
     pub fn configure_all(&self) -> Result<()> {
         self.write_filter_type();
@@ -198,7 +261,7 @@ impl UmvLaneDetector {
         self.write_vid_mode();
         self.write_fl_hline_din_mask();
         self.write_fl_vline_din_mask();
-        self.write_framebuf_addr();
+        self.write_framebuf_addr()?;
         self.write_fl_vline_width_detect_min();
         self.write_fl_hline_width_detect_min();
         self.write_findlines_horizon()?;
@@ -236,8 +299,8 @@ impl UmvLaneDetector {
         }
     }
     pub fn write_fl_vline_width(&self) -> Result<()> {
-        ensure!(self.findlines_horizon <= self.image_height, "image_height < findlines_horizon");
-        ensure!(self.fl_vline_width_min <= self.fl_vline_width_max, "fl_width_max < fl_width_min");
+        ensure!(self.findlines_horizon <= self.image_height, InvalidArgument, "image_height < findlines_horizon");
+        ensure!(self.fl_vline_width_min <= self.fl_vline_width_max, InvalidArgument, "fl_width_max < fl_width_min");
         let interval = (self.image_height - self.findlines_horizon) / (self.fl_vline_width_max - self.fl_vline_width_min + 1);
         self.write_fl_vline_width_inc_interval(interval);
         self.write_fl_vline_width_min();
@@ -260,8 +323,8 @@ impl UmvLaneDetector {
         }
     }
     pub fn write_fl_hline_width(&self) -> Result<()> {
-        ensure!(self.findlines_horizon <= self.image_height, "image_height < findlines_horizon");
-        ensure!(self.fl_hline_width_min <= self.fl_hline_width_max, "fl_width_max < fl_width_min");
+        ensure!(self.findlines_horizon <= self.image_height, InvalidArgument, "image_height < findlines_horizon");
+        ensure!(self.fl_hline_width_min <= self.fl_hline_width_max, InvalidArgument, "fl_width_max < fl_width_min");
         let interval = (self.image_height - self.findlines_horizon) / (self.fl_hline_width_max - self.fl_hline_width_min + 1);
         self.write_fl_hline_width_inc_interval(interval);
         self.write_fl_hline_width_min();
@@ -306,7 +369,7 @@ impl UmvLaneDetector {
         }
     }
     pub fn write_findlines_horizon(&self) -> Result<()> {
-        ensure!(self.findlines_horizon <= self.image_height, "image_height < findlines_horizon");
+        ensure!(self.findlines_horizon <= self.image_height, InvalidArgument, "image_height < findlines_horizon");
         unsafe {
             self.uio_acc.write_mem32(FINDLINES_HORIZON, self.findlines_horizon);
         }
@@ -1,11 +1,6 @@
-use anyhow::{ensure, Result, Context, bail};
-
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
-use crate::json_as_i32;
-use crate::json_as_f32;
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::error::{ensure, Result};
+use crate::hwinfo::IpDescriptor;
 
 const BRAKE            :usize = 0x00;
 const ACCEL_R          :usize = 0x04;
@@ -28,36 +23,29 @@ const FIXED_DECIMAL_BITW: i32 = 16;
 
 
 pub struct UmvMotorController {
-    uio_acc: UioAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
     accel_max: i32,
     fb_edge_period: f32,
 }
 
 impl UmvMotorController {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let accel_max = json_as_i32!(hw_params["ACCEL_MAX"]);
-        let fb_edge_period = json_as_f32!(hw_params["FB_EDGE_PERIOD"]);
-        ensure!(
-            vendor == "slab" &&
-            library == "umv_project" &&
-            name == "umv_motor_controller",
-            "UmvMotorController::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio_acc = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("slab", &["umv_project"], "umv_motor_controller", "UmvMotorController::new()")?;
+        let accel_max = desc.param_i32("ACCEL_MAX")?;
+        let fb_edge_period = desc.param_f32("FB_EDGE_PERIOD")?;
+        let uio_acc = backend.open_uio(desc.uio()?)?;
         Ok(UmvMotorController {
             uio_acc,
             accel_max,
@@ -69,12 +57,12 @@ impl UmvMotorController {
         unsafe { self.uio_acc.write_mem32(BRAKE, val_u32); }
     }
     pub fn write_accel_right(&self, val: i32) -> Result<()> {
-        ensure!(val <= self.accel_max, "accel_right must be less than {}", self.accel_max);
+        ensure!(val <= self.accel_max, InvalidArgument, "accel_right must be less than {}", self.accel_max);
         unsafe { self.uio_acc.write_memi32(ACCEL_R, val); }
         Ok(())
     }
     pub fn write_accel_left(&self, val: i32) -> Result<()> {
-        ensure!(val <= self.accel_max, "accel_left must be less than {}", self.accel_max);
+        ensure!(val <= self.accel_max, InvalidArgument, "accel_left must be less than {}", self.accel_max);
         unsafe { self.uio_acc.write_memi32(ACCEL_L, val); }
         Ok(())
     }
@@ -95,57 +83,57 @@ impl UmvMotorController {
         self.set_accel_rpm_right(right_val)
     }
     pub fn write_kp_right(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Kp_right must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Kp_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Kp_right must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Kp_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KP_R, fixed_val); }
         Ok(())
     }
     pub fn write_ki_right(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Ki_right must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Ki_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Ki_right must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Ki_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KI_R, fixed_val); }
         Ok(())
     }
     pub fn write_kd_right(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Kd_right must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Kd_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Kd_right must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Kd_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KD_R, fixed_val); }
         Ok(())
     }
     pub fn write_bias_right(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Bias_right must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Bias_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Bias_right must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Bias_right must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(BIAS_R, fixed_val); }
         Ok(())
     }
     pub fn write_kp_left(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Kp_left must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Kp_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Kp_left must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Kp_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KP_L, fixed_val); }
         Ok(())
     }
     pub fn write_ki_left(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Ki_left must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Ki_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Ki_left must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Ki_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KI_L, fixed_val); }
         Ok(())
     }
     pub fn write_kd_left(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Kd_left must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Kd_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Kd_left must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Kd_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(KD_L, fixed_val); }
         Ok(())
     }
     pub fn write_bias_left(&self, val: f32) -> Result<()> {
-        ensure!(val >= 0., "Bias_left must be a positive number");
-        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), "Bias_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
+        ensure!(val >= 0., InvalidArgument, "Bias_left must be a positive number");
+        ensure!(val < (2.0_f32).powi(FIXED_DECIMAL_BITW), InvalidArgument, "Bias_left must be less than {}", (2.0_f32).powi(FIXED_DECIMAL_BITW));
         let fixed_val = (val * (2.0_f32).powi(FIXED_DECIMAL_BITW)).floor() as u32;
         unsafe { self.uio_acc.write_mem32(BIAS_L, fixed_val); }
         Ok(())
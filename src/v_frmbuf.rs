@@ -1,465 +1,1180 @@
-use anyhow::{ensure, Result, Context, bail};
+use std::fmt;
+use std::time::Duration;
 
-use jelly_mem_access::*;
+use image::{DynamicImage, GenericImageView};
 
-use crate::json_as_map;
-use crate::json_as_str;
-use crate::json_as_u32;
+use crate::ap_ctrl::{ApCtrl, AP_INT_DONE, AP_ISR};
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::dma_buffer::{split_phys_addr, DmaBuffer};
+use crate::error::{bail, ensure, Result, XipError};
+use crate::frame_image::{image_to_frame, FrameImage};
+use crate::frame_pool::{FramePool, FrameSpec};
+use crate::hwinfo::IpDescriptor;
+use crate::timeout::{IrqDeadline, DEFAULT_TIMEOUT};
 
-#[derive(Clone, Copy, Debug)]
+/// Video memory format, the value written to the `video_format` register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColorFormat {
     /* Video in memory formats */
-    Rgbx8 = 10,   // [31:0] x:B:G:R 8:8:8:8
-    Yuvx8,        // [31:0] x:V:U:Y 8:8:8:8
-    Yuyv8,        // [31:0] V:Y:U:Y 8:8:8:8
-    Rgba8,        // [31:0] A:B:G:R 8:8:8:8
-    Yuva8,        // [31:0] A:V:U:Y 8:8:8:8
-    Rgbx10,       // [31:0] x:B:G:R 2:10:10:10
-    Yuvx10,       // [31:0] x:V:U:Y 2:10:10:10
-
-
-    Rgb565,       // [15:0] B:G:R 5:6:5
-    YUv8,         // [15:0] Y:Y 8:8, [15:0] V:U 8:8
-    YUv8420,      // [15:0] Y:Y 8:8, [15:0] V:U 8:8
-    Rgb8,         // [23:0] B:G:R 8:8:8
-    Yuv8,         // [24:0] V:U:Y 8:8:8
-    YUv10,        // [31:0] x:Y:Y:Y 2:10:10:10 [31:0] x:U:V:U 2:10:10:10
-    YUv10_420,    // [31:0] x:Y:Y:Y 2:10:10:10 [31:0] x:U:V:U 2:10:10:10
-    Y8,           // [31:0] Y:Y:Y:Y 8:8:8:8
-    Y10,          // [31:0] x:Y:Y:Y 2:10:10:10
-    Bgra8,        // [31:0] A:R:G:B 8:8:8:8
-    Bgrx8,        // [31:0] X:R:G:B 8:8:8:8
-    Uyvy8,        // [31:0] Y:V:Y:U 8:8:8:8
-    Bgr8,         // [23:0] R:G:B 8:8:8
-    Rgbx12,       // [39:0] x:R:G:B 4:12:12:12
-    Yuvx12,       // [39:0] x:V:U:Y 4:12:12:12
-    YUv12,        // [23:0] Y:Y 12:12, [23:0] V:U 12:12
-    YUv12_420,     // [23:0] Y:Y 12:12, [23:0] V:U 12:12
-    Y12,          // [39:0] x:Y2:Y1:Y0 4:12:12:12
-    Rgb16,        // [47:0] R:G:B 16:16:16
-    Yuv16,        // [47:0] V:U:Y 16:16:16
-    YUv16,        // [31:0] Y:Y 16:16, [31:0] V:U 16:16
-    Yuv16_420,     // [31:0] Y:Y 16:16, [31:0] V:U 16:16
-    Y16,          // [47:0] Y2:Y1:Y0 16:16:16
-    RGb8,         // [7:0] R:8, [7:0] G:8, [7:0] B:8
-    YUV8_420,      // [15:0] Y:Y 8:8, [7:0] U:8, [7:0] V:8
-    YUV8,         // [7:0] Y:8, [7:0] U:8, [7:0] V:8
-    YUV10,       // [9:0] Y:10, [9:0] U:10, [9:0] V:10
+    Rgbx8 = 10, // [31:0] x:B:G:R 8:8:8:8
+    Yuvx8,      // [31:0] x:V:U:Y 8:8:8:8
+    Yuyv8,      // [31:0] V:Y:U:Y 8:8:8:8
+    Rgba8,      // [31:0] A:B:G:R 8:8:8:8
+    Yuva8,      // [31:0] A:V:U:Y 8:8:8:8
+    Rgbx10,     // [31:0] x:B:G:R 2:10:10:10
+    Yuvx10,     // [31:0] x:V:U:Y 2:10:10:10
+
+    Rgb565,    // [15:0] B:G:R 5:6:5
+    YUv8,      // [15:0] Y:Y 8:8, [15:0] V:U 8:8
+    YUv8420,   // [15:0] Y:Y 8:8, [15:0] V:U 8:8
+    Rgb8,      // [23:0] B:G:R 8:8:8
+    Yuv8,      // [24:0] V:U:Y 8:8:8
+    YUv10,     // [31:0] x:Y:Y:Y 2:10:10:10 [31:0] x:U:V:U 2:10:10:10
+    YUv10_420, // [31:0] x:Y:Y:Y 2:10:10:10 [31:0] x:U:V:U 2:10:10:10
+    Y8,        // [31:0] Y:Y:Y:Y 8:8:8:8
+    Y10,       // [31:0] x:Y:Y:Y 2:10:10:10
+    Bgra8,     // [31:0] A:R:G:B 8:8:8:8
+    Bgrx8,     // [31:0] X:R:G:B 8:8:8:8
+    Uyvy8,     // [31:0] Y:V:Y:U 8:8:8:8
+    Bgr8,      // [23:0] R:G:B 8:8:8
+    Rgbx12,    // [39:0] x:R:G:B 4:12:12:12
+    Yuvx12,    // [39:0] x:V:U:Y 4:12:12:12
+    YUv12,     // [23:0] Y:Y 12:12, [23:0] V:U 12:12
+    YUv12_420, // [23:0] Y:Y 12:12, [23:0] V:U 12:12
+    Y12,       // [39:0] x:Y2:Y1:Y0 4:12:12:12
+    Rgb16,     // [47:0] R:G:B 16:16:16
+    Yuv16,     // [47:0] V:U:Y 16:16:16
+    YUv16,     // [31:0] Y:Y 16:16, [31:0] V:U 16:16
+    Yuv16_420, // [31:0] Y:Y 16:16, [31:0] V:U 16:16
+    Y16,       // [47:0] Y2:Y1:Y0 16:16:16
+    RGb8,      // [7:0] R:8, [7:0] G:8, [7:0] B:8
+    YUV8_420,  // [15:0] Y:Y 8:8, [7:0] U:8, [7:0] V:8
+    YUV8,      // [7:0] Y:8, [7:0] U:8, [7:0] V:8
+    YUV10,     // [9:0] Y:10, [9:0] U:10, [9:0] V:10
+}
+
+impl ColorFormat {
+    pub const ALL: [ColorFormat; 34] = [
+        ColorFormat::Rgbx8,
+        ColorFormat::Yuvx8,
+        ColorFormat::Yuyv8,
+        ColorFormat::Rgba8,
+        ColorFormat::Yuva8,
+        ColorFormat::Rgbx10,
+        ColorFormat::Yuvx10,
+        ColorFormat::Rgb565,
+        ColorFormat::YUv8,
+        ColorFormat::YUv8420,
+        ColorFormat::Rgb8,
+        ColorFormat::Yuv8,
+        ColorFormat::YUv10,
+        ColorFormat::YUv10_420,
+        ColorFormat::Y8,
+        ColorFormat::Y10,
+        ColorFormat::Bgra8,
+        ColorFormat::Bgrx8,
+        ColorFormat::Uyvy8,
+        ColorFormat::Bgr8,
+        ColorFormat::Rgbx12,
+        ColorFormat::Yuvx12,
+        ColorFormat::YUv12,
+        ColorFormat::YUv12_420,
+        ColorFormat::Y12,
+        ColorFormat::Rgb16,
+        ColorFormat::Yuv16,
+        ColorFormat::YUv16,
+        ColorFormat::Yuv16_420,
+        ColorFormat::Y16,
+        ColorFormat::RGb8,
+        ColorFormat::YUV8_420,
+        ColorFormat::YUV8,
+        ColorFormat::YUV10,
+    ];
+
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|fmt| fmt.id() == id)
+    }
+
+    /// Name used by the IP, as in its `HAS_<name>` parameter.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorFormat::Rgbx8 => "RGBX8",
+            ColorFormat::Yuvx8 => "YUVX8",
+            ColorFormat::Yuyv8 => "YUYV8",
+            ColorFormat::Rgba8 => "RGBA8",
+            ColorFormat::Yuva8 => "YUVA8",
+            ColorFormat::Rgbx10 => "RGBX10",
+            ColorFormat::Yuvx10 => "YUVX10",
+            ColorFormat::Rgb565 => "RGB565",
+            ColorFormat::YUv8 => "Y_UV8",
+            ColorFormat::YUv8420 => "Y_UV8_420",
+            ColorFormat::Rgb8 => "RGB8",
+            ColorFormat::Yuv8 => "YUV8",
+            ColorFormat::YUv10 => "Y_UV10",
+            ColorFormat::YUv10_420 => "Y_UV10_420",
+            ColorFormat::Y8 => "Y8",
+            ColorFormat::Y10 => "Y10",
+            ColorFormat::Bgra8 => "BGRA8",
+            ColorFormat::Bgrx8 => "BGRX8",
+            ColorFormat::Uyvy8 => "UYVY8",
+            ColorFormat::Bgr8 => "BGR8",
+            ColorFormat::Rgbx12 => "RGBX12",
+            ColorFormat::Yuvx12 => "YUVX12",
+            ColorFormat::YUv12 => "Y_UV12",
+            ColorFormat::YUv12_420 => "Y_UV12_420",
+            ColorFormat::Y12 => "Y12",
+            ColorFormat::Rgb16 => "RGB16",
+            ColorFormat::Yuv16 => "YUV16",
+            ColorFormat::YUv16 => "Y_UV16",
+            ColorFormat::Yuv16_420 => "Y_UV16_420",
+            ColorFormat::Y16 => "Y16",
+            ColorFormat::RGb8 => "R_G_B8",
+            ColorFormat::YUV8_420 => "Y_U_V8_420",
+            ColorFormat::YUV8 => "Y_U_V8",
+            ColorFormat::YUV10 => "Y_U_V10",
+        }
+    }
+
+    /// Parses `name()`; "YUYV" is accepted for YUYV8.
+    pub fn parse(name: &str) -> Result<Self> {
+        if name == "YUYV" {
+            return Ok(ColorFormat::Yuyv8);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|fmt| fmt.name() == name)
+            .ok_or_else(|| XipError::Format(format!("unknown color format: {}", name)))
+    }
+
+    /// Bytes and pixels of the smallest group that fills whole bytes in the
+    /// first plane.
+    fn pixel_group(self) -> (u32, u32) {
+        match self {
+            ColorFormat::Yuyv8 | ColorFormat::Uyvy8 => (4, 2),
+            ColorFormat::Rgb565 => (2, 1),
+            ColorFormat::YUv8 | ColorFormat::YUv8420 | ColorFormat::Y8 => (1, 1),
+            ColorFormat::RGb8 | ColorFormat::YUV8_420 | ColorFormat::YUV8 => (1, 1),
+            ColorFormat::Rgb8 | ColorFormat::Yuv8 | ColorFormat::Bgr8 => (3, 1),
+            ColorFormat::YUv10 | ColorFormat::YUv10_420 | ColorFormat::Y10 => (4, 3),
+            ColorFormat::YUV10 => (4, 3),
+            ColorFormat::Rgbx12 | ColorFormat::Yuvx12 => (5, 1),
+            ColorFormat::YUv12 | ColorFormat::YUv12_420 => (3, 2),
+            ColorFormat::Y12 => (5, 3),
+            ColorFormat::Rgb16 | ColorFormat::Yuv16 => (6, 1),
+            ColorFormat::YUv16 | ColorFormat::Yuv16_420 | ColorFormat::Y16 => (2, 1),
+            _ => (4, 1),
+        }
+    }
+
+    /// Bytes of one line of `width` pixels in the first plane, before the
+    /// stride alignment. The other planes, if any, have lines of the same
+    /// size and share the stride.
+    pub fn line_bytes(self, width: u32) -> u32 {
+        let (bytes, pixels) = self.pixel_group();
+        width.div_ceil(pixels) * bytes
+    }
+
+    /// Memory planes: 2 for the semi-planar `Y_UV*` formats (luma, then
+    /// interleaved chroma) and 3 for the planar ones.
+    pub fn planes(self) -> usize {
+        match self {
+            ColorFormat::YUv8
+            | ColorFormat::YUv8420
+            | ColorFormat::YUv10
+            | ColorFormat::YUv10_420
+            | ColorFormat::YUv12
+            | ColorFormat::YUv12_420
+            | ColorFormat::YUv16
+            | ColorFormat::Yuv16_420 => 2,
+            ColorFormat::RGb8 | ColorFormat::YUV8_420 | ColorFormat::YUV8 | ColorFormat::YUV10 => 3,
+            _ => 1,
+        }
+    }
+
+    /// Lines of `plane` in a frame of `height` lines; the chroma planes of
+    /// 4:2:0 formats have half as many.
+    pub fn plane_height(self, plane: usize, height: u32) -> u32 {
+        let subsampled = matches!(
+            self,
+            ColorFormat::YUv8420
+                | ColorFormat::YUv10_420
+                | ColorFormat::YUv12_420
+                | ColorFormat::Yuv16_420
+                | ColorFormat::YUV8_420
+        );
+        if plane > 0 && subsampled {
+            height.div_ceil(2)
+        } else {
+            height
+        }
+    }
+}
+
+impl fmt::Display for ColorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Formats the IP was synthesized with, from its `HAS_*` parameters.
+fn read_formats(desc: &IpDescriptor) -> Result<Vec<ColorFormat>> {
+    let mut formats = Vec::new();
+    for fmt in ColorFormat::ALL {
+        if desc.param_bool_or(&format!("HAS_{}", fmt.name()), false)? {
+            formats.push(fmt);
+        }
+    }
+    Ok(formats)
+}
+
+fn check_format(formats: &[ColorFormat], fmt: ColorFormat) -> Result<()> {
+    ensure!(formats.contains(&fmt), Format, "{} is not enabled", fmt);
+    Ok(())
+}
+
+/// Address registers of the luma, chroma (or U) and V planes. 0x48 is the
+/// field ID, so the V plane register differs between the two directions.
+const RD_PLANE_ADDR_REGS: [usize; 3] = [0x30, 0x3C, 0x74];
+const WR_PLANE_ADDR_REGS: [usize; 3] = [0x30, 0x3C, 0x5C];
+
+/// Where one plane of a frame lives.
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    buffer: usize,
+    offset: usize,
+    lines: usize,
+    line_bytes: usize,
+    stride: usize,
+}
+
+impl Plane {
+    /// Bytes with lines tightly packed.
+    fn size(&self) -> usize {
+        self.line_bytes * self.lines
+    }
+
+    /// Bytes the plane takes in the udmabuf, lines `stride` apart.
+    fn span(&self) -> usize {
+        self.stride * self.lines
+    }
+}
+
+/// Places the planes of `fmt`: plane `i` in `udmabuf[i]` if the IP has that
+/// many udmabufs, otherwise right after the previous plane.
+fn plane_layout(
+    fmt: ColorFormat,
+    width: u32,
+    height: u32,
+    stride: u32,
+    buffers: &[DmaBuffer],
+) -> Result<Vec<Plane>> {
+    let mut planes = Vec::new();
+    let mut next = (0, 0);
+    for i in 0..fmt.planes() {
+        let lines = fmt.plane_height(i, height) as usize;
+        let (buffer, offset) = if i < buffers.len() { (i, 0) } else { next };
+        let end = offset + stride as usize * lines;
+        let capacity = buffers[buffer].size();
+        if end > capacity {
+            return Err(XipError::BufferTooLarge {
+                size: end,
+                capacity,
+            });
+        }
+        planes.push(Plane {
+            buffer,
+            offset,
+            lines,
+            line_bytes: fmt.line_bytes(width) as usize,
+            stride: stride as usize,
+        });
+        next = (buffer, end);
+    }
+    Ok(planes)
+}
+
+/// Copies `lines` lines of `line_bytes` bytes between buffers whose lines are
+/// `dst_stride` and `src_stride` bytes apart.
+fn copy_lines(
+    dst: &mut [u8],
+    dst_stride: usize,
+    src: &[u8],
+    src_stride: usize,
+    line_bytes: usize,
+    lines: usize,
+) {
+    for y in 0..lines {
+        dst[y * dst_stride..][..line_bytes].copy_from_slice(&src[y * src_stride..][..line_bytes]);
+    }
+}
+
+/// Checks a user frame of `len` bytes holding the planes of `layout` one after
+/// the other, lines `stride` bytes apart.
+fn check_user_frame(layout: &[Plane], len: usize, stride: usize) -> Result<()> {
+    let line_bytes = layout[0].line_bytes;
+    ensure!(
+        stride >= line_bytes,
+        InvalidArgument,
+        "stride {} is shorter than a line of {} bytes",
+        stride,
+        line_bytes
+    );
+    let lines: usize = layout.iter().map(|p| p.lines).sum();
+    ensure!(lines > 0, InvalidArgument, "the frame has no lines");
+    let needed = stride * (lines - 1) + line_bytes;
+    ensure!(
+        len >= needed,
+        InvalidArgument,
+        "frame is {} bytes, {} needed",
+        len,
+        needed
+    );
+    Ok(())
+}
+
+fn open_udmabufs(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Vec<DmaBuffer>> {
+    desc.udmabuf(0)?;
+    let mut buffers = Vec::new();
+    for name in &desc.udmabuf {
+        buffers.push(DmaBuffer::from_io(
+            backend.open_udmabuf(name, desc.udmabuf_cached)?,
+        ));
+    }
+    Ok(buffers)
+}
+
+fn write_plane_addrs(
+    uio_acc: &dyn RegIo,
+    regs: &[usize; 3],
+    addrs: &[usize],
+    addr_width: u32,
+) -> Result<()> {
+    for (&addr, &offset) in addrs.iter().zip(regs.iter()) {
+        write_addr(uio_acc, offset, addr, addr_width)?;
+    }
+    Ok(())
+}
+
+/// Frame settings and udmabufs of a driver, what both directions derive
+/// their memory layout from.
+struct FrameGeometry<'a> {
+    format: Option<ColorFormat>,
+    width: u32,
+    height: u32,
+    max_width: u32,
+    max_height: u32,
+    pix_per_clk: u32,
+    buffers: &'a [DmaBuffer],
 }
 
+impl FrameGeometry<'_> {
+    fn format(&self) -> Result<ColorFormat> {
+        match self.format {
+            Some(fmt) => Ok(fmt),
+            None => bail!(Format, "Format is not set"),
+        }
+    }
+
+    fn line_bytes(&self) -> Result<u32> {
+        Ok(self.format()?.line_bytes(self.width))
+    }
+
+    /// Bytes between the starts of two lines in memory.
+    fn stride(&self) -> Result<u32> {
+        let mmap_width_bytes = self.pix_per_clk * 8;
+        Ok(self.line_bytes()?.next_multiple_of(mmap_width_bytes))
+    }
+
+    fn check_size(&self) -> Result<()> {
+        ensure!(
+            self.width <= self.max_width,
+            Format,
+            "FRAME_WIDTH too large"
+        );
+        ensure!(
+            self.height <= self.max_height,
+            Format,
+            "FRAME_HEIGHT too large"
+        );
+        Ok(())
+    }
+
+    fn layout(&self) -> Result<Vec<Plane>> {
+        self.check_size()?;
+        plane_layout(
+            self.format()?,
+            self.width,
+            self.height,
+            self.stride()?,
+            self.buffers,
+        )
+    }
+
+    fn plane(&self, plane: usize) -> Result<Plane> {
+        let layout = self.layout()?;
+        match layout.get(plane) {
+            Some(p) => Ok(*p),
+            None => bail!(
+                InvalidArgument,
+                "plane {} out of range, {} has {}",
+                plane,
+                self.format()?,
+                layout.len()
+            ),
+        }
+    }
+
+    /// Physical addresses of the planes, or of the first udmabuf while no
+    /// format is set.
+    fn plane_addrs(&self) -> Result<Vec<usize>> {
+        if self.format.is_none() {
+            return Ok(vec![self.buffers[0].phys_addr()]);
+        }
+        Ok(self
+            .layout()?
+            .iter()
+            .map(|p| self.buffers[p.buffer].phys_addr() + p.offset)
+            .collect())
+    }
+
+    fn frame_spec(&self) -> Result<FrameSpec> {
+        Ok(FrameSpec::new(self.width, self.height, self.format()?).with_stride(self.stride()?))
+    }
+
+    /// Frame size, stride and `video_format`.
+    fn write_format(&self, uio_acc: &dyn RegIo) -> Result<()> {
+        self.check_size()?;
+        let fmt = self.format()?;
+        let stride = self.stride()?;
+        unsafe {
+            uio_acc.write_mem32(0x10, self.width);
+            uio_acc.write_mem32(0x18, self.height);
+            uio_acc.write_mem32(0x20, stride);
+            uio_acc.write_mem32(0x28, fmt.id());
+        }
+        Ok(())
+    }
+}
 
 pub struct VideoFrameBufRead {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: UdmabufAccessor<usize>,
-    fmt_id: u32,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Vec<DmaBuffer>,
+    format: Option<ColorFormat>,
+    formats: Vec<ColorFormat>,
     max_width: u32,
     max_height: u32,
-    has_rgb8: bool,
-    has_yuyv8: bool,
     pub frame_width: u32,
     pub frame_height: u32,
     pix_per_clk: u32,
-    bytes_per_pix: u32,
     tie_en: bool,
-    tie_addr: usize,
+    tie_addrs: Vec<usize>,
+    pool: Option<FramePool>,
+    addr_width: u32,
+    timeout: Duration,
 }
 
 impl VideoFrameBufRead {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let udmabuf_name = json_as_str!(hw_object["udmabuf"][0]);
-        let max_width: u32 = json_as_u32!(hw_params["MAX_COLS"]);
-        let max_height: u32 = json_as_u32!(hw_params["MAX_ROWS"]);
-        let has_rgb8 = json_as_u32!(hw_params["HAS_RGB8"]) == 1;
-        let has_yuyv8 = json_as_u32!(hw_params["HAS_YUYV8"]) == 1;
-        let pix_per_clk = json_as_u32!(hw_params["SAMPLES_PER_CLOCK"]);
-        ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "v_frmbuf_rd",
-            "VideoFrameBufRead::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
-        let udmabuf = match UdmabufAccessor::new(udmabuf_name, false) {
-            Ok(udmabuf_acc) => {
-                udmabuf_acc
-            },
-            Err(e) => {
-                bail!("UdmabufAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip(
+            "xilinx.com",
+            &["ip"],
+            "v_frmbuf_rd",
+            "VideoFrameBufRead::new()",
+        )?;
+        let max_width = desc.param_u32("MAX_COLS")?;
+        let max_height = desc.param_u32("MAX_ROWS")?;
+        let formats = read_formats(desc)?;
+        let pix_per_clk = desc.param_u32("SAMPLES_PER_CLOCK")?;
+        let addr_width = desc.addr_width("AXIMM_ADDR_WIDTH")?;
+        let uio = backend.open_uio(desc.uio()?)?;
+        let udmabuf = open_udmabufs(desc, backend)?;
         Ok(VideoFrameBufRead {
             uio_acc: uio,
             udmabuf_acc: udmabuf,
-            fmt_id: 0,
+            format: None,
+            formats,
             max_width,
             max_height,
-            has_rgb8,
-            has_yuyv8,
             frame_height: max_height,
             frame_width: max_width,
             pix_per_clk,
-            bytes_per_pix: 0,
             tie_en: false,
-            tie_addr: 0,
+            tie_addrs: Vec::new(),
+            pool: None,
+            addr_width,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
 
-    pub fn is_running(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 1 }
-    }
-    pub fn is_done(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 2 == 2 }
-    }
-    pub fn is_idle(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 4 == 4 }
-    }
-    pub fn is_ready(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 0 }
-    }
-    pub fn get_auto_restart_enable(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 0x80 == 0x80 }
-    }
-    pub fn set_auto_restart_enable(&self, en: bool) {
-        let reg = if en { 0x80 } else { 0 };
-        unsafe {
-            self.uio_acc.write_mem32(0x00, reg);
-        }
-    }
     pub fn start(&mut self) -> Result<()> {
         self.configure()?;
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x81);
-        }
+        self.ap_start_auto_restart();
         Ok(())
     }
     pub fn start_once(&mut self) -> Result<()> {
         self.configure()?;
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x01);
-        }
+        self.ap_start();
         Ok(())
     }
     pub fn configure(&mut self) -> Result<()> {
         self.write_format()?;
-        self.write_framebuf_addr();
-        Ok(())
+        self.write_framebuf_addr()
     }
-    pub fn stop(&self) {
+    /// Disables auto-restart and waits for the current frame to finish.
+    pub fn stop(&self) -> Result<()> {
         self.set_auto_restart_enable(false);
-        while !self.is_ready() { }
+        self.wait_idle(self.timeout)
     }
-    pub fn wait_done_interrupt(&mut self) {
-        self.uio_acc.set_irq_enable(true).unwrap();
-        unsafe {
-            self.uio_acc.write_mem32(0x04, 0x01);
-            self.uio_acc.write_mem32(0x08, 0x01);
-        }
-        if !self.is_idle() {
-            self.uio_acc.wait_irq().unwrap();
-        }
-        unsafe {
-            self.uio_acc.write_mem32(0x0c, 0x01);
-            self.uio_acc.write_mem32(0x04, 0x00);
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    pub fn write_framebuf_addr(&self) -> Result<()> {
+        if self.tie_en {
+            write_plane_addrs(
+                &*self.uio_acc,
+                &RD_PLANE_ADDR_REGS,
+                &self.tie_addrs,
+                self.addr_width,
+            )
+        } else {
+            write_plane_addrs(
+                &*self.uio_acc,
+                &RD_PLANE_ADDR_REGS,
+                &self.geometry().plane_addrs()?,
+                self.addr_width,
+            )
         }
     }
-    pub fn write_framebuf_addr(&self) {
-        unsafe {
-            if self.tie_en {
-                self.uio_acc.write_mem32(0x30, self.tie_addr as u32);
-            }
-            else {
-                self.uio_acc.write_mem32(0x30, self.udmabuf_acc.phys_addr() as u32);
-            }
+    /// Copies a frame with its planes one after the other, lines tightly
+    /// packed, and starts reading it.
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let line_bytes = self.geometry().line_bytes()? as usize;
+        self.write_frame_from(frame, line_bytes)
+    }
+    /// Copies a frame whose lines are `stride` bytes apart, planes one after
+    /// the other, and starts reading it.
+    pub fn write_frame_from(&mut self, frame: &[u8], stride: usize) -> Result<()> {
+        let layout = self.geometry().layout()?;
+        check_user_frame(&layout, frame.len(), stride)?;
+        let mut src = 0;
+        for p in layout {
+            let dst = self.udmabuf_acc[p.buffer].slice_mut(p.offset, p.span())?;
+            copy_lines(dst, p.stride, &frame[src..], stride, p.line_bytes, p.lines);
+            src += stride * p.lines;
         }
+        self.write_frame_in_place()
     }
-    pub fn write_frame<V>(&mut self, frame: *const V) -> Result<()> {
-        ensure!(self.frame_width <= self.max_width, "FRAME_WIDTH too large");
-        ensure!(self.frame_height <= self.max_height, "FRAME_HEIGHT too large");
-        let size_of_v = core::mem::size_of::<V>();
-        let count = (self.frame_width * self.frame_height * self.bytes_per_pix) as usize;
-        ensure!(size_of_v * count < self.udmabuf_acc.phys_addr(), "Array size too large");
-        ensure!(size_of_v == 1, "Unsupported data format: {}", size_of_v);
-        // self.stop();
-        unsafe {
-            self.udmabuf_acc.copy_from(frame, 0x00, count);
+    /// Converts `img` to the configured format and starts reading it. The
+    /// image must be the size of the frame.
+    pub fn write_image(&mut self, img: &DynamicImage) -> Result<()> {
+        let fmt = self.geometry().format()?;
+        ensure!(
+            img.dimensions() == (self.frame_width, self.frame_height),
+            InvalidArgument,
+            "image is {}x{}, the frame is {}x{}",
+            img.width(),
+            img.height(),
+            self.frame_width,
+            self.frame_height
+        );
+        let frame = image_to_frame(fmt, img)?;
+        self.write_frame_from(&frame, fmt.line_bytes(self.frame_width) as usize)
+    }
+    /// Copies `data` into `plane` (0 for luma), lines tightly packed.
+    pub fn write_plane(&mut self, plane: usize, data: &[u8]) -> Result<()> {
+        let p = self.geometry().plane(plane)?;
+        ensure!(
+            data.len() == p.size(),
+            InvalidArgument,
+            "plane {} takes {} bytes, got {}",
+            plane,
+            p.size(),
+            data.len()
+        );
+        let dst = self.udmabuf_acc[p.buffer].slice_mut(p.offset, p.span())?;
+        copy_lines(dst, p.stride, data, p.line_bytes, p.line_bytes, p.lines);
+        Ok(())
+    }
+    /// Starts reading the frame already rendered into `buffer_mut()` or
+    /// `plane_mut()`.
+    pub fn write_frame_in_place(&mut self) -> Result<()> {
+        for plane in self.geometry().layout()? {
+            self.udmabuf_acc[plane.buffer].sync_for_device(plane.offset, plane.span())?;
         }
         self.start()
     }
-    pub fn set_format(&mut self, fmt: &str) -> Result<()> {
-        match fmt {
-            "YUYV" => {
-                ensure!(self.has_yuyv8, "YUYV8 is not enabled");
-                self.fmt_id = 12;
-                self.bytes_per_pix = 2;
-            }
-            "RGB8" => {
-                ensure!(self.has_rgb8, "RGB8 is not enabled");
-                self.fmt_id = 20;
-                self.bytes_per_pix = 3;
-            }
-            _ => {
-                bail!("{} is not enabled", fmt);
-            }
+    pub fn buffer(&self) -> &DmaBuffer {
+        &self.udmabuf_acc[0]
+    }
+    pub fn buffer_mut(&mut self) -> &mut DmaBuffer {
+        &mut self.udmabuf_acc[0]
+    }
+    /// Plane `plane` of the frame in memory, for rendering in place. Lines
+    /// are `stride()` bytes apart.
+    pub fn plane_mut(&mut self, plane: usize) -> Result<&mut [u8]> {
+        let p = self.geometry().plane(plane)?;
+        self.udmabuf_acc[p.buffer].slice_mut(p.offset, p.span())
+    }
+    /// Bytes of `plane` with lines tightly packed.
+    pub fn plane_size(&self, plane: usize) -> Result<usize> {
+        Ok(self.geometry().plane(plane)?.size())
+    }
+    /// Bytes of all planes with lines tightly packed, what `write_frame`
+    /// takes.
+    pub fn frame_size(&self) -> Result<usize> {
+        Ok(self.geometry().layout()?.iter().map(Plane::size).sum())
+    }
+    fn geometry(&self) -> FrameGeometry<'_> {
+        FrameGeometry {
+            format: self.format,
+            width: self.frame_width,
+            height: self.frame_height,
+            max_width: self.max_width,
+            max_height: self.max_height,
+            pix_per_clk: self.pix_per_clk,
+            buffers: &self.udmabuf_acc,
         }
+    }
+    pub fn set_format(&mut self, fmt: ColorFormat) -> Result<()> {
+        check_format(&self.formats, fmt)?;
+        self.format = Some(fmt);
         Ok(())
     }
     pub fn get_format(&self) -> Result<&str> {
-        match self.fmt_id {
-             0 => Ok("Not Set"),
-            12 => Ok("YUYV"),
-            20 => Ok("RGB8"),
-            _ => {
-                bail!("unknown fmt: {}", self.fmt_id);
-            }
-        }
+        Ok(self.format.map_or("Not Set", ColorFormat::name))
+    }
+    pub fn format(&self) -> Option<ColorFormat> {
+        self.format
+    }
+    /// Formats the IP was synthesized with.
+    pub fn supported_formats(&self) -> &[ColorFormat] {
+        &self.formats
+    }
+    /// Bytes between the starts of two lines in memory.
+    pub fn stride(&self) -> Result<u32> {
+        self.geometry().stride()
     }
     pub fn write_format(&self) -> Result<()> {
-        ensure!(self.frame_width <= self.max_width, "FRAME_WIDTH too large");
-        ensure!(self.frame_height <= self.max_height, "FRAME_HEIGHT too large");
-        ensure!(self.fmt_id != 0, "Format is not set");
-        let mmap_width_bytes = self.pix_per_clk * 8;
-        let stride = ((self.frame_width * self.bytes_per_pix + mmap_width_bytes - 1)
-            / mmap_width_bytes)
-            * mmap_width_bytes;
-        unsafe {
-            self.uio_acc.write_mem32(0x10, self.frame_width);
-            self.uio_acc.write_mem32(0x18, self.frame_height);
-            self.uio_acc.write_mem32(0x20, stride);
-            self.uio_acc.write_mem32(0x28, self.fmt_id);
-        }
-        Ok(())
+        self.geometry().write_format(&*self.uio_acc)
     }
-    pub fn tie(&mut self, vfbw: &VideoFrameBufWrite) {
+    /// Reads the planes `vfbw` writes instead of our own udmabufs. Share a
+    /// `FramePool` instead to hand frames over without tearing.
+    pub fn tie(&mut self, vfbw: &VideoFrameBufWrite) -> Result<()> {
+        self.tie_addrs = vfbw.plane_addrs()?;
         self.tie_en = true;
-        self.tie_addr = vfbw.get_addr();
+        Ok(())
     }
     pub fn untie(&mut self) {
         self.tie_en = false;
     }
+    /// Layout of the frames with the current size and format.
+    pub fn frame_spec(&self) -> Result<FrameSpec> {
+        self.geometry().frame_spec()
+    }
+    /// Reads frames from `pool` with `show_from_pool`. Fails if the pool
+    /// holds frames of another size, format or stride.
+    pub fn attach_pool(&mut self, pool: &FramePool) -> Result<()> {
+        self.pool = Some(pool.attach(&self.frame_spec()?)?);
+        Ok(())
+    }
+    pub fn detach_pool(&mut self) {
+        self.pool = None;
+    }
+    /// Reads the latest frame published to the pool once and returns its
+    /// sequence number, or `None` if nothing was published yet. The frame
+    /// is held until the core is done with it.
+    pub fn show_from_pool(&mut self) -> Result<Option<u64>> {
+        let Some(pool) = &self.pool else {
+            bail!(InvalidState, "VideoFrameBufRead: no FramePool attached");
+        };
+        pool.spec().check(&self.frame_spec()?)?;
+        let Some(frame) = pool.acquire_read() else {
+            return Ok(None);
+        };
+        let result = pool
+            .plane_addrs(frame.index)
+            .and_then(|addrs| self.read_pool_frame(&addrs));
+        pool.release_read(frame.index)?;
+        result.map(|()| Some(frame.sequence))
+    }
+    fn read_pool_frame(&self, addrs: &[usize]) -> Result<()> {
+        self.stop()?;
+        self.write_format()?;
+        write_plane_addrs(&*self.uio_acc, &RD_PLANE_ADDR_REGS, addrs, self.addr_width)?;
+        self.start_and_wait(self.timeout)
+    }
     pub fn calc_stride(&self, fmt: ColorFormat) -> u32 {
         let mm_width_bytes = self.pix_per_clk * 8;
+        fmt.line_bytes(self.frame_width)
+            .next_multiple_of(mm_width_bytes)
+    }
+}
 
-        let (bpp_numerator, bpp_denominator) = match fmt {
-            ColorFormat::YUv10 | ColorFormat::YUv10_420 | ColorFormat::Y10 => {
-                (4, 3)
-            },
-            ColorFormat::YUv8 | ColorFormat::YUv8420 | ColorFormat::Y8 | ColorFormat::YUV10 => {
-                (1, 1)
-            },
-            ColorFormat::Rgb8 | ColorFormat::Yuv8 | ColorFormat::Bgr8 => {
-                (3, 1)
-            },
-            ColorFormat::Rgbx12 | ColorFormat::Yuvx12 => {
-                (5, 1)
-            },
-            ColorFormat::YUv12 | ColorFormat::YUv12_420 | ColorFormat::Y12 => {
-                (3, 2)
-            },
-            ColorFormat::Rgb16 | ColorFormat::Yuv16 => {
-                (6, 1)
-            },
-            ColorFormat::Yuyv8 | ColorFormat::Uyvy8 | ColorFormat::YUv16 | ColorFormat::Yuv16_420 | ColorFormat::Y16 => {
-                 (2, 1)
-            },
-            _ => {
-                (4, 1)
-            }
-        };
-
-        ((((self.frame_width * bpp_numerator) / bpp_denominator) + mm_width_bytes - 1) / mm_width_bytes) * mm_width_bytes
+impl ApCtrl for VideoFrameBufRead {
+    fn ap_regs(&self) -> &dyn RegIo {
+        &*self.uio_acc
+    }
+    fn ap_regs_mut(&mut self) -> &mut dyn RegIo {
+        &mut *self.uio_acc
     }
 }
 
+/// A frame taken from the capture ring with `VideoFrameBufWrite::dequeue`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapturedFrame {
+    /// Ring slot holding the frame, to hand back with `queue`.
+    pub index: usize,
+    /// Frames completed before this one since `start_streaming`. Gaps mean
+    /// frames were overwritten before being dequeued.
+    pub sequence: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Free,
+    Writing,
+    /// The most recently completed frame and its sequence number.
+    Ready(u64),
+    Dequeued,
+}
+
 pub struct VideoFrameBufWrite {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: UdmabufAccessor<usize>,
-    fmt_id: u32,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Vec<DmaBuffer>,
+    format: Option<ColorFormat>,
+    formats: Vec<ColorFormat>,
     max_width: u32,
     max_height: u32,
-    has_rgb8: bool,
-    has_yuyv8: bool,
     pub frame_width: u32,
     pub frame_height: u32,
     pub pix_per_clk: u32,
-    bytes_per_pix: u32,
+    addr_width: u32,
+    ring: Vec<Slot>,
+    sequence: u64,
+    pool: Option<FramePool>,
+    timeout: Duration,
 }
 
 impl VideoFrameBufWrite {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let udmabuf_name = json_as_str!(hw_object["udmabuf"][0]);
-        let max_width = json_as_u32!(hw_params["MAX_COLS"]);
-        let max_height = json_as_u32!(hw_params["MAX_ROWS"]);
-        let has_rgb8 = json_as_u32!(hw_params["HAS_RGB8"]) == 1;
-        let has_yuyv8 = json_as_u32!(hw_params["HAS_YUYV8"]) == 1;
-        let pix_per_clk = json_as_u32!(hw_params["SAMPLES_PER_CLOCK"]);
-        ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "v_frmbuf_wr",
-            "VideoFrameBufWrite::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
-        let udmabuf = match UdmabufAccessor::new(udmabuf_name, false) {
-            Ok(udmabuf_acc) => {
-                udmabuf_acc
-            },
-            Err(e) => {
-                bail!("UdmabufAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip(
+            "xilinx.com",
+            &["ip"],
+            "v_frmbuf_wr",
+            "VideoFrameBufWrite::new()",
+        )?;
+        let max_width = desc.param_u32("MAX_COLS")?;
+        let max_height = desc.param_u32("MAX_ROWS")?;
+        let formats = read_formats(desc)?;
+        let pix_per_clk = desc.param_u32("SAMPLES_PER_CLOCK")?;
+        let addr_width = desc.addr_width("AXIMM_ADDR_WIDTH")?;
+        let uio = backend.open_uio(desc.uio()?)?;
+        let udmabuf = open_udmabufs(desc, backend)?;
         Ok(VideoFrameBufWrite {
             uio_acc: uio,
             udmabuf_acc: udmabuf,
-            fmt_id: 0,
+            format: None,
+            formats,
             max_width,
             max_height,
-            has_rgb8,
-            has_yuyv8,
             frame_height: max_height,
             frame_width: max_width,
             pix_per_clk,
-            bytes_per_pix: 0,
+            addr_width,
+            ring: Vec::new(),
+            sequence: 0,
+            pool: None,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
 
-    pub fn is_running(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 1 }
-    }
-    pub fn is_done(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 2 == 2 }
-    }
-    pub fn is_idle(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 4 == 4 }
-    }
-    pub fn is_ready(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 0 }
-    }
-    pub fn get_auto_restart_enable(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 0x80 == 0x80 }
-    }
-    pub fn set_auto_restart_enable(&self, en: bool) {
-        let reg = if en { 0x80 } else { 0 };
-        unsafe {
-            self.uio_acc.write_mem32(0x00, reg);
-        }
-    }
     pub fn start(&self) -> Result<()> {
+        self.ensure_not_streaming()?;
         self.write_format()?;
-        self.set_framebuf_addr();
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x81);
-        }
+        self.set_framebuf_addr()?;
+        self.ap_start_auto_restart();
         Ok(())
     }
     pub fn start_once(&self) -> Result<()> {
         self.write_format()?;
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x01);
-        }
+        self.ap_start();
         Ok(())
     }
     pub fn stop(&self) {
         self.set_auto_restart_enable(false);
         // while !self.is_ready() { }
     }
-    pub fn set_framebuf_addr(&self) {
-        unsafe {
-            self.uio_acc
-                .write_mem32(0x30, self.udmabuf_acc.phys_addr() as u32);
-        }
+    pub fn set_framebuf_addr(&self) -> Result<()> {
+        write_plane_addrs(
+            &*self.uio_acc,
+            &WR_PLANE_ADDR_REGS,
+            &self.plane_addrs()?,
+            self.addr_width,
+        )
+    }
+    /// Timeout of `capture_to_pool` and `dequeue`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
-    pub fn read_frame_as_image(&self) -> Result<image::RgbImage> {
-        image::ImageBuffer::from_raw(self.frame_width, self.frame_height, self.read_frame()?).context("Can't convert to image")
+    pub fn timeout(&self) -> Duration {
+        self.timeout
     }
+    /// The last frame converted to an image; see `FrameImage::from_frame`
+    /// for the supported formats.
+    pub fn read_frame_as_image(&self) -> Result<FrameImage> {
+        let fmt = self.geometry().format()?;
+        let frame = self.read_frame()?;
+        FrameImage::from_frame(fmt, self.frame_width, self.frame_height, &frame)
+    }
+    /// The last frame with its planes one after the other, lines tightly
+    /// packed.
     pub fn read_frame(&self) -> Result<Vec<u8>> {
-        ensure!(self.frame_width <= self.max_width, "FRAME_WIDTH too large");
-        ensure!(self.frame_height <= self.max_height, "FRAME_HEIGHT too large");
-        let w = self.frame_width as usize;
-        let h = self.frame_height as usize;
-        let bpp = self.bytes_per_pix as usize;
-        let mut buf = Vec::with_capacity(w * h * bpp);
+        self.ensure_not_streaming()?;
+        let mut buf = vec![0; self.geometry().layout()?.iter().map(Plane::size).sum()];
+        self.read_frame_into(&mut buf, self.geometry().line_bytes()? as usize)?;
+        Ok(buf)
+    }
+    /// Copies the last frame into `frame` with lines `stride` bytes apart,
+    /// planes one after the other, and resumes capturing.
+    pub fn read_frame_into(&self, frame: &mut [u8], stride: usize) -> Result<()> {
+        self.ensure_not_streaming()?;
+        let layout = self.geometry().layout()?;
+        check_user_frame(&layout, frame.len(), stride)?;
+        let planes = self.read_planes_in_place()?;
+        let mut dst = 0;
+        for (p, src) in layout.iter().zip(planes) {
+            copy_lines(
+                &mut frame[dst..],
+                stride,
+                src,
+                p.stride,
+                p.line_bytes,
+                p.lines,
+            );
+            dst += stride * p.lines;
+        }
+        self.start()
+    }
+    /// Stops capturing and borrows the first plane of the last frame
+    /// straight from the udmabuf, lines `stride()` bytes apart. Call `start`
+    /// to resume capturing once done with it.
+    pub fn read_frame_in_place(&self) -> Result<&[u8]> {
+        Ok(self.read_planes_in_place()?[0])
+    }
+    /// Like `read_frame_in_place`, for every plane.
+    pub fn read_planes_in_place(&self) -> Result<Vec<&[u8]>> {
+        self.ensure_not_streaming()?;
+        let layout = self.geometry().layout()?;
+        // The frame in flight still lands in the buffer until the core idles.
         self.stop();
-        unsafe {
-            self.udmabuf_acc
-                .copy_to(0x00, buf.as_mut_ptr(), w * h * bpp);
-            buf.set_len(w * h * bpp);
+        self.wait_idle(self.timeout)?;
+        let mut planes = Vec::new();
+        for p in layout {
+            let buf = &self.udmabuf_acc[p.buffer];
+            buf.sync_for_cpu(p.offset, p.span())?;
+            planes.push(buf.slice(p.offset, p.span())?);
         }
-        self.start()?;
-        Ok(buf)
+        Ok(planes)
     }
-    pub fn set_format(&mut self, fmt: &str) -> Result<()> {
-        match fmt {
-            "YUYV" => {
-                ensure!(self.has_yuyv8, "YUYV8 is not enabled");
-                self.fmt_id = 12;
-                self.bytes_per_pix = 2;
+    pub fn buffer(&self) -> &DmaBuffer {
+        &self.udmabuf_acc[0]
+    }
+    /// Bytes of `plane` with lines tightly packed.
+    pub fn plane_size(&self, plane: usize) -> Result<usize> {
+        Ok(self.geometry().plane(plane)?.size())
+    }
+    fn geometry(&self) -> FrameGeometry<'_> {
+        FrameGeometry {
+            format: self.format,
+            width: self.frame_width,
+            height: self.frame_height,
+            max_width: self.max_width,
+            max_height: self.max_height,
+            pix_per_clk: self.pix_per_clk,
+            buffers: &self.udmabuf_acc,
+        }
+    }
+    /// Physical addresses of the planes, or of the first udmabuf while no
+    /// format is set.
+    pub fn plane_addrs(&self) -> Result<Vec<usize>> {
+        self.geometry().plane_addrs()
+    }
+    pub fn set_format(&mut self, fmt: ColorFormat) -> Result<()> {
+        check_format(&self.formats, fmt)?;
+        self.format = Some(fmt);
+        Ok(())
+    }
+    pub fn get_format(&self) -> Result<&str> {
+        Ok(self.format.map_or("Not Set", ColorFormat::name))
+    }
+    pub fn format(&self) -> Option<ColorFormat> {
+        self.format
+    }
+    /// Formats the IP was synthesized with.
+    pub fn supported_formats(&self) -> &[ColorFormat] {
+        &self.formats
+    }
+    /// Bytes between the starts of two lines in memory.
+    pub fn stride(&self) -> Result<u32> {
+        self.geometry().stride()
+    }
+    pub fn write_format(&self) -> Result<()> {
+        self.geometry().write_format(&*self.uio_acc)
+    }
+    pub fn get_addr(&self) -> usize {
+        self.udmabuf_acc[0].phys_addr()
+    }
+
+    /// Layout of the frames with the current size and format.
+    pub fn frame_spec(&self) -> Result<FrameSpec> {
+        self.geometry().frame_spec()
+    }
+    /// Captures into `pool` with `capture_to_pool`. Fails if the pool holds
+    /// frames of another size, format or stride.
+    pub fn attach_pool(&mut self, pool: &FramePool) -> Result<()> {
+        self.pool = Some(pool.attach(&self.frame_spec()?)?);
+        Ok(())
+    }
+    pub fn detach_pool(&mut self) {
+        self.pool = None;
+    }
+    /// Captures one frame into a pool buffer no reader holds, publishes it
+    /// and returns its sequence number.
+    pub fn capture_to_pool(&mut self) -> Result<u64> {
+        self.ensure_not_streaming()?;
+        let Some(pool) = &self.pool else {
+            bail!(InvalidState, "VideoFrameBufWrite: no FramePool attached");
+        };
+        pool.spec().check(&self.frame_spec()?)?;
+        let index = pool.acquire_write()?;
+        match pool
+            .plane_addrs(index)
+            .and_then(|addrs| self.write_pool_frame(&addrs))
+        {
+            Ok(()) => pool.publish(index),
+            Err(e) => {
+                pool.release_write(index)?;
+                Err(e)
             }
-            "RGB8" => {
-                ensure!(self.has_rgb8, "RGB8 is not enabled");
-                self.fmt_id = 20;
-                self.bytes_per_pix = 3;
+        }
+    }
+    fn write_pool_frame(&self, addrs: &[usize]) -> Result<()> {
+        self.stop();
+        self.write_format()?;
+        write_plane_addrs(&*self.uio_acc, &WR_PLANE_ADDR_REGS, addrs, self.addr_width)?;
+        self.start_and_wait(self.timeout)
+    }
+
+    /// Captures into a ring of `buffers` frames, one per udmabuf, switching
+    /// to the next free one on every ap_done interrupt. Take frames with
+    /// `dequeue` and hand them back with `queue`.
+    pub fn start_streaming(&mut self, buffers: usize) -> Result<()> {
+        ensure!(
+            (2..=self.udmabuf_acc.len()).contains(&buffers),
+            InvalidArgument,
+            "ring of {} buffers, the IP has {} udmabufs",
+            buffers,
+            self.udmabuf_acc.len()
+        );
+        for slot in 0..buffers {
+            self.slot_layout(slot)?;
+        }
+        self.stop_streaming()?;
+        self.stop();
+        self.write_format()?;
+        self.ring = vec![Slot::Free; buffers];
+        self.sequence = 0;
+        self.enable_interrupt(AP_INT_DONE)?;
+        self.clear_interrupt(AP_INT_DONE);
+        self.capture_into(0)
+    }
+    /// Leaves streaming mode after the frame being captured. Dequeued
+    /// frames stay valid until the next start.
+    pub fn stop_streaming(&mut self) -> Result<()> {
+        if !self.ring.is_empty() {
+            self.disable_interrupt();
+            self.clear_interrupt(AP_INT_DONE);
+            self.uio_acc.set_irq_enable(false)?;
+            self.ring.clear();
+        }
+        Ok(())
+    }
+    pub fn is_streaming(&self) -> bool {
+        !self.ring.is_empty()
+    }
+    fn ensure_not_streaming(&self) -> Result<()> {
+        ensure!(
+            self.ring.is_empty(),
+            InvalidState,
+            "VideoFrameBufWrite is streaming, use dequeue()"
+        );
+        Ok(())
+    }
+    /// Blocks until a frame completes unless one is already waiting, and
+    /// takes the most recent one. The core doesn't write to it until it is
+    /// handed back with `queue`. Gives up after `timeout`.
+    pub fn dequeue(&mut self) -> Result<CapturedFrame> {
+        ensure!(
+            !self.ring.is_empty(),
+            InvalidState,
+            "VideoFrameBufWrite is not streaming"
+        );
+        let deadline = IrqDeadline::new("VideoFrameBufWrite::dequeue", self.timeout);
+        self.service()?;
+        loop {
+            let ready = self.ring.iter().enumerate().find_map(|(i, s)| match s {
+                Slot::Ready(sequence) => Some((i, *sequence)),
+                _ => None,
+            });
+            if let Some((index, sequence)) = ready {
+                self.ring[index] = Slot::Dequeued;
+                for p in self.slot_layout(index)? {
+                    self.udmabuf_acc[p.buffer].sync_for_cpu(p.offset, p.span())?;
+                }
+                return Ok(CapturedFrame { index, sequence });
             }
-            _ => {
-                bail!("{} is not enabled", fmt);
+            ensure!(
+                self.ring.contains(&Slot::Writing),
+                InvalidState,
+                "every buffer is dequeued, queue one back first"
+            );
+            self.uio_acc.set_irq_enable(true)?;
+            if !self.service()? {
+                deadline.wait_irq(self.uio_acc.as_mut(), AP_ISR)?;
             }
         }
+    }
+    /// Hands a dequeued frame back to the ring.
+    pub fn queue(&mut self, index: usize) -> Result<()> {
+        ensure!(
+            self.ring.get(index) == Some(&Slot::Dequeued),
+            InvalidArgument,
+            "ring buffer {} is not dequeued",
+            index
+        );
+        self.ring[index] = Slot::Free;
+        if !self.ring.contains(&Slot::Writing) {
+            self.capture_into(index)?;
+        }
         Ok(())
     }
-    pub fn get_format(&self) -> Result<&str> {
-        match self.fmt_id {
-             0 => Ok("Not Set"),
-            12 => Ok("YUYV"),
-            20 => Ok("RGB8"),
-            _ => {
-                bail!("unknown fmt: {}", self.fmt_id);
-            }
+    /// Planes of ring buffer `index`, lines `stride()` bytes apart.
+    pub fn ring_planes(&self, index: usize) -> Result<Vec<&[u8]>> {
+        ensure!(
+            index < self.ring.len(),
+            InvalidArgument,
+            "ring buffer {} out of range, {} in use",
+            index,
+            self.ring.len()
+        );
+        let mut planes = Vec::new();
+        for p in self.slot_layout(index)? {
+            planes.push(self.udmabuf_acc[p.buffer].slice(p.offset, p.span())?);
         }
+        Ok(planes)
     }
-    pub fn write_format(&self) -> Result<()> {
-        ensure!(self.frame_width <= self.max_width, "FRAME_WIDTH too large");
-        ensure!(self.frame_height <= self.max_height, "FRAME_HEIGHT too large");
-        ensure!(self.fmt_id != 0, "Format is not set");
-        let mmap_width_bytes = self.pix_per_clk * 8;
-        let stride = ((self.frame_width * self.bytes_per_pix + mmap_width_bytes - 1)
-            / mmap_width_bytes)
-            * mmap_width_bytes;
-        unsafe {
-            self.uio_acc.write_mem32(0x10, self.frame_width);
-            self.uio_acc.write_mem32(0x18, self.frame_height);
-            self.uio_acc.write_mem32(0x20, stride);
-            self.uio_acc.write_mem32(0x28, self.fmt_id);
+    /// Takes in a pending ap_done: the frame being written becomes the
+    /// ready one, replacing an older one nobody dequeued, and the core
+    /// restarts on the next free buffer. False if no frame completed.
+    fn service(&mut self) -> Result<bool> {
+        if self.interrupt_status() & AP_INT_DONE == 0 {
+            return Ok(false);
+        }
+        self.clear_interrupt(AP_INT_DONE);
+        let Some(done) = self.ring.iter().position(|&s| s == Slot::Writing) else {
+            return Ok(false);
+        };
+        for slot in self.ring.iter_mut() {
+            if matches!(slot, Slot::Ready(_)) {
+                *slot = Slot::Free;
+            }
         }
+        self.ring[done] = Slot::Ready(self.sequence);
+        self.sequence += 1;
+        let n = self.ring.len();
+        // Without a free buffer the core stays idle until `queue`.
+        if let Some(next) = (1..n)
+            .map(|i| (done + i) % n)
+            .find(|&i| self.ring[i] == Slot::Free)
+        {
+            self.capture_into(next)?;
+        }
+        Ok(true)
+    }
+    fn capture_into(&mut self, index: usize) -> Result<()> {
+        let addrs: Vec<usize> = self
+            .slot_layout(index)?
+            .iter()
+            .map(|p| self.udmabuf_acc[p.buffer].phys_addr() + p.offset)
+            .collect();
+        write_plane_addrs(&*self.uio_acc, &WR_PLANE_ADDR_REGS, &addrs, self.addr_width)?;
+        self.ring[index] = Slot::Writing;
+        self.ap_start();
         Ok(())
     }
-    pub fn get_addr(&self) -> usize {
-        self.udmabuf_acc.phys_addr()
+    /// Planes of ring buffer `slot`, all in `udmabuf[slot]`.
+    fn slot_layout(&self, slot: usize) -> Result<Vec<Plane>> {
+        let geometry = FrameGeometry {
+            buffers: &self.udmabuf_acc[slot..=slot],
+            ..self.geometry()
+        };
+        let mut layout = geometry.layout()?;
+        for p in layout.iter_mut() {
+            p.buffer = slot;
+        }
+        Ok(layout)
+    }
+}
+
+impl ApCtrl for VideoFrameBufWrite {
+    fn ap_regs(&self) -> &dyn RegIo {
+        &*self.uio_acc
+    }
+    fn ap_regs_mut(&mut self) -> &mut dyn RegIo {
+        &mut *self.uio_acc
+    }
+}
+
+/// Writes `addr` to the register at `offset`, and its MSB word to the next
+/// register when the AXI-MM address is wider than 32 bits.
+fn write_addr(uio_acc: &dyn RegIo, offset: usize, addr: usize, addr_width: u32) -> Result<()> {
+    let (lsb, msb) = split_phys_addr(addr, addr_width)?;
+    unsafe {
+        uio_acc.write_mem32(offset, lsb);
+        if addr_width > 32 {
+            uio_acc.write_mem32(offset + 4, msb);
+        }
     }
+    Ok(())
 }
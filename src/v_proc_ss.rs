@@ -1,9 +1,7 @@
-use anyhow::{ensure, Result, Context, bail};
-
-use jelly_mem_access::*;
-
-use crate::json_as_map;
-use crate::json_as_str;
+use crate::ap_ctrl::ApCtrl;
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::error::{ensure, Result};
+use crate::hwinfo::IpDescriptor;
 
 macro_rules! float2sfix3_12 {
     ($float_num: expr) => {
@@ -18,7 +16,7 @@ macro_rules! sfix2f32 {
 }
 
 pub struct VideoProcSubsystemCsc {
-    uio_acc: UioAccessor<usize>,
+    uio_acc: Box<dyn RegIo>,
     pub frame_width: u32,
     pub frame_height: u32,
     fmt_in: u32,
@@ -40,27 +38,20 @@ pub struct VideoProcSubsystemCsc {
 
 impl VideoProcSubsystemCsc {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        // let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "v_proc_ss",
-            "VideoFrameBufRead::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "v_proc_ss", "VideoProcSubsystemCsc::new()")?;
+        let uio = backend.open_uio(desc.uio()?)?;
         let mut csc_mat = vec![0.0; 9];
         csc_mat[0] = 1.;
         csc_mat[4] = 1.;
@@ -87,39 +78,14 @@ impl VideoProcSubsystemCsc {
             csc_mat,
         })
     }
-    pub fn is_running(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 1 }
-    }
-    pub fn is_done(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 2 == 1 }
-    }
-    pub fn is_idle(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 4 == 1 }
-    }
-    pub fn is_ready(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 1 == 0 }
-    }
-    pub fn get_auto_restart_enable(&self) -> bool {
-        unsafe { self.uio_acc.read_mem32(0x00) & 0x80 == 1 }
-    }
-    pub fn set_auto_restart_enable(&self, en: bool) {
-        let reg = if en { 0x80 } else { 0 };
-        unsafe {
-            self.uio_acc.write_mem32(0x00, reg);
-        }
-    }
     pub fn start(&self) -> Result<()> {
         self.configure()?;
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x81);
-        }
+        self.ap_start_auto_restart();
         Ok(())
     }
     pub fn start_once(&self) -> Result<()> {
         self.configure()?;
-        unsafe {
-            self.uio_acc.write_mem32(0x00, 0x01);
-        }
+        self.ap_start();
         Ok(())
     }
     pub fn configure(&self) -> Result<()> {
@@ -232,8 +198,8 @@ impl VideoProcSubsystemCsc {
         self.clip_max = (1 << self.color_depth) - 1;
     }
     pub fn write_fmt(&self) -> Result<()> {
-        ensure!(self.fmt_in < 4, "fmt_in must be in the range 1 to 3");
-        ensure!(self.fmt_out < 4, "fmt_out must be in the range 1 to 3");
+        ensure!(self.fmt_in < 4, Format, "fmt_in must be in the range 1 to 3");
+        ensure!(self.fmt_out < 4, Format, "fmt_out must be in the range 1 to 3");
         unsafe {
             let reg_src = self.uio_acc.read_mem32(0x10) & 0xFFFFFF00;
             let reg_dst = self.uio_acc.read_mem32(0x18) & 0xFFFFFF00;
@@ -258,8 +224,8 @@ impl VideoProcSubsystemCsc {
     pub fn read_csc_matrix(&self) -> [f32; 9] {
         let mut ret = [0.; 9];
         unsafe {
-            for i in 0..9 {
-                ret[i] = sfix2f32!(self.uio_acc.read_mem32(0x50 + i * 8));
+            for (i, r) in ret.iter_mut().enumerate() {
+                *r = sfix2f32!(self.uio_acc.read_mem32(0x50 + i * 8));
             }
         }
         ret
@@ -282,3 +248,12 @@ impl VideoProcSubsystemCsc {
     //     todo!();
     // }
 }
+
+impl ApCtrl for VideoProcSubsystemCsc {
+    fn ap_regs(&self) -> &dyn RegIo {
+        &*self.uio_acc
+    }
+    fn ap_regs_mut(&mut self) -> &mut dyn RegIo {
+        &mut *self.uio_acc
+    }
+}
@@ -1,13 +1,13 @@
 #![allow(unused)]
 
-use anyhow::{ensure, Result, Context, bail};
+use std::time::Duration;
 
-use jelly_mem_access::*;
-
-
-use crate::json_as_map;
-use crate::json_as_vec;
-use crate::json_as_str;
+use crate::backend::{Backend, DeviceBackend, RegIo};
+use crate::dma_buffer::{split_phys_addr, DmaBuffer};
+use crate::error::{bail, ensure, Result, XipError};
+use crate::frame_pool::{FramePool, FrameSpec};
+use crate::hwinfo::IpDescriptor;
+use crate::timeout::{wait_until, IrqDeadline, DEFAULT_TIMEOUT};
 
 const MM2S_DMACR: usize = 0x00;
 const MM2S_DMASR: usize = 0x04;
@@ -41,63 +41,379 @@ const S2MM_HSIZE: usize = 0xA4;
 const S2MM_FRMDLY_STRIDE: usize = 0xA8;
 const S2MM_START_ADDRESS1: usize = 0xAC;
 
+const VDMACR_RS: u32 = 1 << 0;
+const VDMACR_CIRCULAR_PARK: u32 = 1 << 1;
+const VDMACR_RESET: u32 = 1 << 2;
+const VDMACR_GENLOCK_EN: u32 = 1 << 3;
+const VDMACR_FSYNC_SRC_SHIFT: u32 = 5;
+const VDMACR_GENLOCK_SRC: u32 = 1 << 7;
+const VDMACR_PNTR_NUM_SHIFT: u32 = 8;
+const VDMACR_IRQ_FRAME_COUNT_SHIFT: u32 = 16;
+const VDMACR_IRQ_DELAY_COUNT_SHIFT: u32 = 24;
+
+/// Interrupt enables in VDMACR and the matching write-1-to-clear status bits
+/// in VDMASR.
+pub const VDMA_IRQ_FRAME_COUNT: u32 = 1 << 12;
+pub const VDMA_IRQ_DELAY_COUNT: u32 = 1 << 13;
+pub const VDMA_IRQ_ERR: u32 = 1 << 14;
+
+const VDMASR_HALTED: u32 = 1 << 0;
+const VDMASR_INT_ERR: u32 = 1 << 4;
+const VDMASR_SLV_ERR: u32 = 1 << 5;
+const VDMASR_DEC_ERR: u32 = 1 << 6;
+/// The stream did not match HSIZE / VSIZE. Write 1 to clear.
+const VDMASR_SOF_EARLY_ERR: u32 = 1 << 7;
+const VDMASR_EOL_EARLY_ERR: u32 = 1 << 8;
+const VDMASR_SOF_LATE_ERR: u32 = 1 << 11;
+const VDMASR_EOL_LATE_ERR: u32 = 1 << 15;
+const VDMASR_FRAME_SIZE_ERR: u32 =
+    VDMASR_SOF_EARLY_ERR | VDMASR_EOL_EARLY_ERR | VDMASR_SOF_LATE_ERR | VDMASR_EOL_LATE_ERR;
+
+/// PARK_PTR_REG fields of both channels.
+const PARK_PTR_RD_REF_SHIFT: u32 = 0;
+const PARK_PTR_WR_REF_SHIFT: u32 = 8;
+const PARK_PTR_RD_STORE_SHIFT: u32 = 16;
+const PARK_PTR_WR_STORE_SHIFT: u32 = 24;
+
+const FRMDLY_SHIFT: u32 = 24;
+
+/// Frame stores reachable without switching the start address bank. With
+/// more than 32 address bits every start address takes two registers, so
+/// only half of them fit.
+const MAX_FRAME_STORES: usize = 16;
+const MAX_FRAME_STORES_64: usize = 8;
+
+/// `C_*_GENLOCK_MODE` of a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenlockMode {
+    Master,
+    Slave,
+    DynamicMaster,
+    DynamicSlave,
+}
+
+/// Where a genlock slave takes the master's frame pointer from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenlockSource {
+    /// The `*_frame_ptr_in` ports.
+    External,
+    /// The other channel of the same VDMA.
+    Internal,
+}
+
+/// What starts a frame (`FsyncSrcSelect`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsyncSource {
+    /// The channel's own `*_fsync` port.
+    Fsync,
+    /// The `*_fsync` port of the other channel.
+    OtherFsync,
+    /// `s_axis_s2mm_tuser(0)`, the start of frame of the S2MM stream.
+    Tuser,
+}
+
+/// The run-time part of VDMACR and the frame delay of a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VdmaConfig {
+    pub mode: VdmaMode,
+    pub genlock: bool,
+    pub genlock_source: GenlockSource,
+    /// Master a dynamic genlock slave follows (`RdPntrNum` / `WrPntrNum`).
+    pub genlock_master: u32,
+    pub fsync: FsyncSource,
+    /// Frames a genlock slave stays behind its master.
+    pub frame_delay: u32,
+    /// Frames per frame-count interrupt, 1 or more.
+    pub irq_frame_count: u8,
+    /// Delay timer interrupt in line periods after the last frame; 0 disables it.
+    pub irq_delay_count: u8,
+}
+
+impl Default for VdmaConfig {
+    fn default() -> Self {
+        VdmaConfig {
+            mode: VdmaMode::Circular,
+            genlock: false,
+            genlock_source: GenlockSource::Internal,
+            genlock_master: 0,
+            fsync: FsyncSource::Fsync,
+            frame_delay: 0,
+            irq_frame_count: 1,
+            irq_delay_count: 0,
+        }
+    }
+}
+
+impl VdmaConfig {
+    fn validate(&self, frame_buffers: usize) -> Result<()> {
+        ensure!(
+            self.irq_frame_count != 0,
+            InvalidArgument,
+            "irq_frame_count must be 1 or more"
+        );
+        ensure!(
+            self.genlock_master < 16,
+            InvalidArgument,
+            "genlock_master must be 0 to 15, found {}",
+            self.genlock_master
+        );
+        ensure!(
+            (self.frame_delay as usize) < frame_buffers,
+            InvalidArgument,
+            "frame_delay must be less than the {} frame buffers, found {}",
+            frame_buffers,
+            self.frame_delay
+        );
+        Ok(())
+    }
+
+    /// VDMACR with the interrupts in `irq_enable` and `run` (RS or 0).
+    fn control(&self, irq_enable: u32, run: u32) -> u32 {
+        let mut reg = run
+            | irq_enable
+            | (self.fsync as u32) << VDMACR_FSYNC_SRC_SHIFT
+            | self.genlock_master << VDMACR_PNTR_NUM_SHIFT
+            | u32::from(self.irq_frame_count) << VDMACR_IRQ_FRAME_COUNT_SHIFT
+            | u32::from(self.irq_delay_count) << VDMACR_IRQ_DELAY_COUNT_SHIFT;
+        if self.mode == VdmaMode::Circular {
+            reg |= VDMACR_CIRCULAR_PARK;
+        }
+        if self.genlock {
+            reg |= VDMACR_GENLOCK_EN;
+        }
+        if self.genlock_source == GenlockSource::Internal {
+            reg |= VDMACR_GENLOCK_SRC;
+        }
+        if self.irq_delay_count != 0 {
+            reg |= VDMA_IRQ_DELAY_COUNT;
+        }
+        reg
+    }
+}
+
+/// Sets the 5-bit field at `shift` of PARK_PTR_REG.
+fn write_park_ptr(uio_acc: &dyn RegIo, shift: u32, index: usize) {
+    let mut reg = unsafe { uio_acc.read_mem32(PARK_PTR_REG) };
+    reg &= !(0x1F << shift);
+    reg |= (index as u32) << shift;
+    unsafe {
+        uio_acc.write_mem32(PARK_PTR_REG, reg);
+    }
+}
+
+/// FRMDLY_STRIDE with the frame delay of `config`.
+fn frmdly_stride(config: &VdmaConfig, stride: u32) -> u32 {
+    config.frame_delay << FRMDLY_SHIFT | stride
+}
+
+/// The error flagged in the VDMASR at `dmasr`. Frame size errors are
+/// write-1-to-clear and are cleared once reported.
+fn check_vdmasr(uio_acc: &dyn RegIo, dmasr: usize) -> Result<()> {
+    let status = unsafe { uio_acc.read_mem32(dmasr) };
+    if status & VDMASR_DEC_ERR != 0 {
+        return Err(XipError::DmaDecode { status });
+    }
+    if status & VDMASR_SLV_ERR != 0 {
+        return Err(XipError::DmaSlave { status });
+    }
+    if status & VDMASR_INT_ERR != 0 {
+        return Err(XipError::DmaInternal { status });
+    }
+    if status & VDMASR_FRAME_SIZE_ERR != 0 {
+        unsafe {
+            uio_acc.write_mem32(dmasr, status & (VDMASR_FRAME_SIZE_ERR | VDMA_IRQ_ERR));
+        }
+        bail!(
+            Format,
+            "the stream does not match the frame size (VDMASR = 0x{:08X})",
+            status
+        );
+    }
+    Ok(())
+}
+
+fn check_park_frame(index: usize, frame_buffers: usize) -> Result<()> {
+    ensure!(
+        index < frame_buffers,
+        InvalidArgument,
+        "frame store {} out of range (0 to {})",
+        index,
+        frame_buffers - 1
+    );
+    Ok(())
+}
+
+/// What a channel takes from the IP parameters.
+struct ChannelParams {
+    frame_stores: usize,
+    /// Width of the memory-mapped side in bytes. Without the data realignment
+    /// engine, start addresses and the stride must be multiples of it.
+    mm_data_bytes: u32,
+    /// Bytes of one stream beat, taken as one pixel.
+    bytes_per_pix: u32,
+    genlock_mode: GenlockMode,
+    addr_width: u32,
+}
+
+impl ChannelParams {
+    /// `channel` is "MM2S" or "S2MM".
+    fn read(desc: &IpDescriptor, channel: &str) -> Result<Self> {
+        let addr_width = desc.addr_width("C_ADDR_WIDTH")?;
+        let max_frame_stores = if addr_width > 32 {
+            MAX_FRAME_STORES_64
+        } else {
+            MAX_FRAME_STORES
+        };
+        let frame_stores = desc.param_u32_or("C_NUM_FSTORES", 3)? as usize;
+        ensure!(
+            (1..=max_frame_stores).contains(&frame_stores),
+            HwDescription,
+            "C_NUM_FSTORES must be 1 to {} with C_ADDR_WIDTH = {}, found {}",
+            max_frame_stores,
+            addr_width,
+            frame_stores
+        );
+        // One udmabuf per frame store.
+        desc.udmabuf(frame_stores - 1)?;
+
+        let key = format!("C_M_AXI_{}_DATA_WIDTH", channel);
+        let mm_data_width = desc.param_u32_or(&key, 64)?;
+        ensure!(
+            mm_data_width >= 8 && mm_data_width.is_power_of_two(),
+            HwDescription,
+            "{} must be a power of two, found {}",
+            key,
+            mm_data_width
+        );
+        let key = if channel == "MM2S" {
+            "C_M_AXIS_MM2S_TDATA_WIDTH"
+        } else {
+            "C_S_AXIS_S2MM_TDATA_WIDTH"
+        };
+        let stream_width = desc.param_u32_or(key, 24)?;
+        ensure!(
+            stream_width >= 8 && stream_width % 8 == 0,
+            HwDescription,
+            "{} must be a multiple of 8, found {}",
+            key,
+            stream_width
+        );
+
+        let key = format!("C_{}_GENLOCK_MODE", channel);
+        let genlock_mode = match desc.param_u32_or(&key, 0)? {
+            0 => GenlockMode::Master,
+            1 => GenlockMode::Slave,
+            2 => GenlockMode::DynamicMaster,
+            3 => GenlockMode::DynamicSlave,
+            mode => bail!(HwDescription, "{} must be 0 to 3, found {}", key, mode),
+        };
+
+        Ok(ChannelParams {
+            frame_stores,
+            mm_data_bytes: mm_data_width / 8,
+            bytes_per_pix: stream_width / 8,
+            genlock_mode,
+            addr_width,
+        })
+    }
+}
+
 pub struct AxiVdmaMM2S {
-    uio_acc: UioAccessor<usize>,
-    udmabuf_acc: Vec<UdmabufAccessor<usize>>,
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Vec<DmaBuffer>,
+    /// The frame size has to be set before `start`.
     pub frame_width: u32,
     pub frame_height: u32,
+    /// `C_*_TDATA_WIDTH` / 8 by default: one pixel per stream beat.
     pub bytes_per_pix: u32,
     pub pix_per_clk: u32,
     desired_frame: usize,
+    /// Committed frame store the VDMA has not switched to yet.
+    pending: Option<usize>,
+    /// A committed frame started since the last frame-count event.
+    fresh: bool,
+    repeated: u32,
     frame_buffers: usize,
+    frame_stores: usize,
+    mm_data_bytes: u32,
+    genlock_mode: GenlockMode,
+    config: VdmaConfig,
+    pool: Option<FramePool>,
+    /// Pool buffers held for display: the one parked on, and the one
+    /// shown before until the VDMA has switched away from it.
+    pool_shown: Option<usize>,
+    pool_retiring: Option<usize>,
+    addr_width: u32,
+    timeout: Duration,
+}
+
+/// What happened on the display since the previous `AxiVdmaMM2S::commit`.
+///
+/// The driver only sees the frame-count status when it is called, so frame
+/// periods that pass between two calls are counted as one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitReport {
+    /// The previous commit was replaced before the VDMA read it.
+    pub dropped: bool,
+    /// Frame periods that showed an already shown frame again.
+    pub repeated: u32,
 }
 
 impl AxiVdmaMM2S {
     pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
-        let hw_object = json_as_map!(hw_info);
-        // let hw_params = json_as_map!(hw_object["params"]);
-        let vendor = json_as_str!(hw_object["vendor"]);
-        let library = json_as_str!(hw_object["library"]);
-        let name = json_as_str!(hw_object["name"]);
-        let uio_name = json_as_str!(hw_object["uio"]);
-        let udmabuf_names = json_as_vec!(hw_object["udmabuf"]);
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axi_vdma", "AxiVdmaMM2S::new()")?;
         ensure!(
-            vendor == "xilinx.com" &&
-            library == "ip" &&
-            name == "axi_vdma",
-            "AxiVdmaMM2S::new(): This IP is not supported. ({})",
-            name
-        );
-        let uio = match UioAccessor::<usize>::new_with_name(uio_name) {
-            Ok(uio_acc) => {
-                uio_acc
-            },
-            Err(e) => {
-                bail!("UioAccessor: {}", e)
-            }
-        };
+            desc.param_bool_or("C_INCLUDE_MM2S", true)?,
+            Unsupported,
+            "AxiVdmaMM2S::new(): The MM2S channel is not included in this IP."
+        );
+        let params =
+            ChannelParams::read(desc, "MM2S").map_err(|e| e.context("AxiVdmaMM2S::new()"))?;
+        let uio = backend.open_uio(desc.uio()?)?;
         let mut udmabuf = Vec::new();
-        for name in udmabuf_names.iter() {
-            let udmabuf_name = name.as_str().context("udmabuf_name is not string")?;
-            match UdmabufAccessor::new(udmabuf_name, false) {
-                Ok(udmabuf_acc) => {
-                    udmabuf.push(udmabuf_acc);
-                },
-                Err(e) => {
-                    bail!("UdmabufAccessor: {}", e)
-                }
-            };
+        for udmabuf_name in desc.udmabuf.iter() {
+            let io = backend.open_udmabuf(udmabuf_name, desc.udmabuf_cached)?;
+            udmabuf.push(DmaBuffer::from_io(io));
         }
 
         Ok(AxiVdmaMM2S {
             uio_acc: uio,
             udmabuf_acc: udmabuf,
-            frame_height: 1280,
-            frame_width: 720,
-            bytes_per_pix: 3,
+            frame_width: 0,
+            frame_height: 0,
+            bytes_per_pix: params.bytes_per_pix,
             pix_per_clk: 1,
-            desired_frame: 1,
-            frame_buffers: 3,
+            desired_frame: 0,
+            pending: None,
+            fresh: false,
+            repeated: 0,
+            frame_buffers: params.frame_stores,
+            frame_stores: params.frame_stores,
+            mm_data_bytes: params.mm_data_bytes,
+            genlock_mode: params.genlock_mode,
+            // Parked on the frame `write_frame` last filled, locked to S2MM.
+            config: VdmaConfig {
+                mode: VdmaMode::Park,
+                genlock: true,
+                ..VdmaConfig::default()
+            },
+            pool: None,
+            pool_shown: None,
+            pool_retiring: None,
+            addr_width: params.addr_width,
+            timeout: DEFAULT_TIMEOUT,
         })
     }
 
@@ -105,112 +421,900 @@ impl AxiVdmaMM2S {
         unsafe { self.uio_acc.read_mem32(MM2S_DMASR) & 1 == 0 }
     }
 
-    fn write_framebuf_addr(&self) -> Result<()> {
-        ensure!(self.frame_buffers < 16, "self.frame_buffers > 15");
-        for i in 0..self.frame_buffers {
-            let mem_phys_addr = self.udmabuf_acc[i].phys_addr() as u32;
-            unsafe {
-                self.uio_acc
-                    .write_mem32(MM2S_START_ADDRESS1 + 4 * i, mem_phys_addr);
-            }
+    pub fn check_error(&self) -> Result<()> {
+        check_vdmasr(&*self.uio_acc, MM2S_DMASR).map_err(|e| e.context("AxiVdmaMM2S"))
+    }
+
+    pub fn config(&self) -> &VdmaConfig {
+        &self.config
+    }
+
+    /// Applied at once if the channel is running, otherwise on `start`.
+    pub fn set_config(&mut self, config: VdmaConfig) -> Result<()> {
+        config.validate(self.frame_buffers)?;
+        self.config = config;
+        if self.is_running() {
+            self.write_control(VDMACR_RS);
+            self.write_format()?;
         }
+        Ok(())
+    }
+
+    pub fn set_mode(&mut self, mode: VdmaMode) -> Result<()> {
+        self.set_config(VdmaConfig {
+            mode,
+            ..self.config
+        })
+    }
 
+    /// Frame store read in park mode.
+    pub fn set_park_frame(&mut self, index: usize) -> Result<()> {
+        check_park_frame(index, self.frame_buffers)?;
+        self.desired_frame = index;
+        self.write_desired_frame();
         Ok(())
     }
 
-    pub fn write_format(&self) {
-        let mmap_width_bytes = self.pix_per_clk * 8;
-        let stride = ((self.frame_width * self.bytes_per_pix + mmap_width_bytes - 1)
-            / mmap_width_bytes)
-            * mmap_width_bytes;
+    fn write_control(&self, run: u32) {
         unsafe {
             self.uio_acc
-                .write_mem32(MM2S_HSIZE, self.frame_width * self.bytes_per_pix);
+                .write_mem32(MM2S_DMACR, self.config.control(VDMA_IRQ_FRAME_COUNT, run));
         }
-        let mut reg = unsafe { self.uio_acc.read_mem32(MM2S_FRMDLY_STRIDE) };
-        reg &= 0xF << 24;
-        reg |= stride;
-        unsafe {
-            self.uio_acc.write_mem32(MM2S_FRMDLY_STRIDE, reg);
+    }
+
+    pub fn genlock_mode(&self) -> GenlockMode {
+        self.genlock_mode
+    }
+
+    pub fn frame_buffers(&self) -> usize {
+        self.frame_buffers
+    }
+
+    /// Uses the first `count` frame stores from the next `start`; the channel
+    /// must be halted.
+    pub fn set_frame_buffers(&mut self, count: usize) -> Result<()> {
+        check_frame_buffers(count, self.frame_stores, self.is_running())?;
+        self.config.validate(count)?;
+        self.frame_buffers = count;
+        self.desired_frame %= count;
+        Ok(())
+    }
+
+    fn write_framebuf_addr(&self) -> Result<()> {
+        for i in 0..self.frame_buffers {
+            let addr = match &self.pool {
+                Some(pool) => pool.phys_addr(i)?,
+                None => self.udmabuf_acc[i].phys_addr(),
+            };
+            self.write_start_address(MM2S_START_ADDRESS1, i, addr)?;
         }
+
+        Ok(())
     }
 
-    pub fn reload(&self) {
+    /// Start address `index` of the bank at `first`. Above 32 address bits
+    /// every address takes an LSB and an MSB word.
+    fn write_start_address(&self, first: usize, index: usize, addr: usize) -> Result<()> {
+        let (lsb, msb) = split_phys_addr(addr, self.addr_width)?;
         unsafe {
-            self.uio_acc.write_mem32(MM2S_VSIZE, self.frame_height);
+            if self.addr_width > 32 {
+                self.uio_acc.write_mem32(first + 8 * index, lsb);
+                self.uio_acc.write_mem32(first + 8 * index + 4, msb);
+            } else {
+                self.uio_acc.write_mem32(first + 4 * index, lsb);
+            }
         }
+        Ok(())
     }
 
-    pub fn stop(&self) {
+    fn stride(&self) -> u32 {
+        calc_stride(self.frame_width, self.bytes_per_pix, self.mm_data_bytes)
+    }
+
+    pub fn write_format(&self) -> Result<()> {
+        let size = (self.stride() * self.frame_height) as usize;
+        // Pool buffers were checked when the pool was attached.
+        let buffers = if self.pool.is_some() {
+            0
+        } else {
+            self.frame_buffers
+        };
+        for buf in &self.udmabuf_acc[..buffers] {
+            if size > buf.size() {
+                return Err(XipError::BufferTooLarge {
+                    size,
+                    capacity: buf.size(),
+                });
+            }
+        }
         unsafe {
-            self.uio_acc.write_mem32(MM2S_DMACR, 0x00011080);
+            self.uio_acc
+                .write_mem32(MM2S_HSIZE, self.frame_width * self.bytes_per_pix);
+            self.uio_acc.write_mem32(
+                MM2S_FRMDLY_STRIDE,
+                frmdly_stride(&self.config, self.stride()),
+            );
         }
-        while self.is_running() {}
+        Ok(())
     }
 
-    pub fn reset(&self) {
-        self.stop();
+    pub fn reload(&self) {
         unsafe {
-            self.uio_acc.write_mem32(MM2S_DMACR, 0x00011084);
-            while self.uio_acc.read_mem32(MM2S_DMACR) & 4 == 4 {}
-            self.uio_acc.write_mem32(MM2S_DMACR, 0x00011084);
+            self.uio_acc.write_mem32(MM2S_VSIZE, self.frame_height);
         }
     }
 
+    fn read_status(&self) -> u32 {
+        unsafe { self.uio_acc.read_mem32(MM2S_DMASR) }
+    }
+
+    /// Timeout of `start`, `stop`, `reset` and the wait for a frame period.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn poll_halted(&self) -> bool {
+        !self.is_running()
+    }
+    pub fn poll_reset_done(&self) -> bool {
+        unsafe { self.uio_acc.read_mem32(MM2S_DMACR) & 4 == 0 }
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        self.write_control(0);
+        wait_until(
+            "AxiVdmaMM2S::stop",
+            self.timeout,
+            || Ok(self.poll_halted()),
+            || self.read_status(),
+        )
+    }
+
+    pub fn reset(&self) -> Result<()> {
+        self.stop()?;
+        self.write_control(VDMACR_RESET);
+        wait_until(
+            "AxiVdmaMM2S::reset",
+            self.timeout,
+            || Ok(self.poll_reset_done()),
+            || unsafe { self.uio_acc.read_mem32(MM2S_DMACR) },
+        )
+    }
+
     pub fn start(&mut self) -> Result<()> {
+        check_frame_size("AxiVdmaMM2S", self.frame_width, self.frame_height)?;
+        if let Some(pool) = self.pool.clone() {
+            ensure!(
+                self.config.mode == VdmaMode::Park,
+                InvalidState,
+                "AxiVdmaMM2S: a FramePool needs park mode"
+            );
+            self.release_pool_frames()?;
+            let Some(frame) = pool.acquire_read() else {
+                bail!(
+                    InvalidState,
+                    "AxiVdmaMM2S::start: nothing published to the FramePool yet"
+                );
+            };
+            self.pool_shown = Some(frame.index);
+            self.desired_frame = frame.index;
+        }
         self.write_framebuf_addr()?;
-        self.write_format();
-        self.reload();
         unsafe {
-            self.uio_acc.write_mem32(MM2S_DMACR, 0x00011089);
+            self.uio_acc
+                .write_mem32(MM2S_FRMSTORE, self.frame_buffers as u32);
         }
-        while !self.is_running() {}
+        self.write_format()?;
+        self.reload();
+        self.pending = None;
+        self.fresh = true;
+        self.repeated = 0;
+        self.write_desired_frame();
+        self.write_control(VDMACR_RS);
+        wait_until(
+            "AxiVdmaMM2S::start",
+            self.timeout,
+            || Ok(self.is_running()),
+            || self.read_status(),
+        )?;
         self.reload();
-        self.uio_acc.set_irq_enable(true).unwrap();
+        self.uio_acc.set_irq_enable(true)?;
         Ok(())
     }
-    pub fn write_frame<V>(&mut self, frame: *const V) -> Result<()> {
-        ensure!(self.frame_buffers < 16, "self.frame_buffers > 15");
-        while unsafe { self.uio_acc.read_mem32(MM2S_DMASR) & 0x1000 == 0 } {
-            self.uio_acc.wait_irq().unwrap();
-        }
-        unsafe {
-            self.uio_acc.write_mem32(MM2S_DMASR, 0x1000);
-        }
-        self.inc_desired_frame();
-        self.write_frame_internal(frame);
-        self.reload();
+
+    /// Copies a frame with tightly packed lines into a free frame store at
+    /// the next frame period and parks the VDMA on it.
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let line = (self.frame_width * self.bytes_per_pix) as usize;
+        let len = line * self.frame_height as usize;
+        ensure!(
+            frame.len() == len,
+            InvalidArgument,
+            "AxiVdmaMM2S: frame is {} bytes, {} expected",
+            frame.len(),
+            len
+        );
+        let stride = self.stride() as usize;
+        self.write_frame_with(|buf| {
+            let dst = buf.as_mut_slice::<u8>();
+            for (y, src) in frame.chunks(line).enumerate() {
+                dst[y * stride..][..line].copy_from_slice(src);
+            }
+        })?;
+        Ok(())
+    }
+    /// Waits for the next frame period and lets `fill` render into a free
+    /// frame store in place, then parks the VDMA on it.
+    pub fn write_frame_with<F>(&mut self, fill: F) -> Result<CommitReport>
+    where
+        F: FnOnce(&mut DmaBuffer),
+    {
+        self.wait_frame()?;
+        let index = loop {
+            match self.acquire()? {
+                Some(index) => break index,
+                None => self.wait_frame()?,
+            }
+        };
+        fill(&mut self.udmabuf_acc[index]);
+        self.commit(index)
+    }
+
+    /// A frame store that is neither being read nor waiting to be, or `None`
+    /// if there is none until the VDMA moves on. Does not block.
+    ///
+    /// Fill it through `frame_buffer_mut` and hand it over with `commit`.
+    pub fn acquire(&mut self) -> Result<Option<usize>> {
+        self.check_no_pool()?;
+        self.poll()?;
+        let active = self.read_active_frame() as usize;
+        let n = self.frame_buffers;
+        Ok((1..=n)
+            .map(|i| (self.desired_frame + i) % n)
+            .find(|&i| i != active && Some(i) != self.pending))
+    }
+
+    /// Parks the VDMA on frame store `index` from the next frame on. This
+    /// is a single PARK_PTR_REG write and does not wait for the frame.
+    pub fn commit(&mut self, index: usize) -> Result<CommitReport> {
+        self.check_no_pool()?;
+        check_park_frame(index, self.frame_buffers)?;
+        let len = (self.stride() * self.frame_height) as usize;
+        let buf = &self.udmabuf_acc[index];
+        buf.sync_for_device(0, len.min(buf.size()))?;
+        self.park_on(index)
+    }
+
+    fn park_on(&mut self, index: usize) -> Result<CommitReport> {
+        ensure!(
+            self.config.mode == VdmaMode::Park,
+            InvalidState,
+            "AxiVdmaMM2S::commit needs park mode"
+        );
+        self.poll()?;
+        let report = CommitReport {
+            dropped: self.pending.is_some(),
+            repeated: std::mem::take(&mut self.repeated),
+        };
+        self.pending = Some(index);
+        self.desired_frame = index;
         self.write_desired_frame();
-        println!("{}", self.desired_frame);
+        Ok(report)
+    }
+
+    fn check_no_pool(&self) -> Result<()> {
+        ensure!(
+            self.pool.is_none(),
+            InvalidState,
+            "AxiVdmaMM2S: the frame stores belong to a FramePool, use show_from_pool"
+        );
         Ok(())
     }
-    fn write_frame_internal<V>(&mut self, frame: *const V) {
-        let count = if core::mem::size_of::<V>() == 1 {
-            (self.frame_width * self.frame_height * self.bytes_per_pix) as usize
-        } else {
-            1
+
+    /// Layout of the frames with the current size, for `attach_pool`.
+    pub fn frame_spec(&self) -> FrameSpec {
+        FrameSpec::packed(self.frame_width, self.frame_height, self.bytes_per_pix)
+            .with_stride(self.stride())
+    }
+
+    /// Uses the buffers of `pool` as frame stores from the next `start` and
+    /// shows what its writers publish with `show_from_pool`. The channel must
+    /// be halted and the pool must hold frames of the current size and
+    /// stride.
+    pub fn attach_pool(&mut self, pool: &FramePool) -> Result<()> {
+        let attached = pool.attach(&self.frame_spec())?;
+        self.detach_pool()?;
+        self.set_frame_buffers(pool.len())?;
+        self.pool = Some(attached);
+        Ok(())
+    }
+
+    /// Gives the frame stores back to the udmabufs; the channel must be
+    /// halted.
+    pub fn detach_pool(&mut self) -> Result<()> {
+        ensure!(
+            !self.is_running(),
+            InvalidState,
+            "AxiVdmaMM2S: detach the FramePool while halted"
+        );
+        self.release_pool_frames()?;
+        self.pool = None;
+        Ok(())
+    }
+
+    fn release_pool_frames(&mut self) -> Result<()> {
+        if let Some(pool) = &self.pool {
+            for index in [self.pool_shown.take(), self.pool_retiring.take()]
+                .into_iter()
+                .flatten()
+            {
+                pool.release_read(index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parks on the latest frame published to the pool if it is not shown
+    /// yet and returns its sequence number. The frame shown before is given
+    /// back once the VDMA has switched away from it; until then this
+    /// returns `None` without switching again. Does not block.
+    pub fn show_from_pool(&mut self) -> Result<Option<u64>> {
+        let Some(pool) = self.pool.clone() else {
+            bail!(InvalidState, "AxiVdmaMM2S: no FramePool attached");
+        };
+        self.poll()?;
+        if let Some(old) = self.pool_retiring {
+            if self.pending.is_some() {
+                return Ok(None);
+            }
+            pool.release_read(old)?;
+            self.pool_retiring = None;
+        }
+        let Some(frame) = pool.acquire_read() else {
+            return Ok(None);
         };
+        if Some(frame.index) == self.pool_shown {
+            pool.release_read(frame.index)?;
+            return Ok(None);
+        }
+        if let Err(e) = self.park_on(frame.index) {
+            pool.release_read(frame.index)?;
+            return Err(e);
+        }
+        self.pool_retiring = self.pool_shown.replace(frame.index);
+        Ok(Some(frame.sequence))
+    }
+
+    /// Blocks until the next frame-count interrupt, up to the channel
+    /// timeout.
+    fn wait_frame(&mut self) -> Result<()> {
+        let deadline = IrqDeadline::new("AxiVdmaMM2S::wait_frame", self.timeout);
+        loop {
+            self.uio_acc.set_irq_enable(true)?;
+            if self.poll()? {
+                return Ok(());
+            }
+            deadline.wait_irq(self.uio_acc.as_mut(), MM2S_DMASR)?;
+        }
+    }
+
+    /// Takes in the frame-count status: notes when the committed frame has
+    /// reached the display and counts repeated frames. True on a frame-count
+    /// event.
+    fn poll(&mut self) -> Result<bool> {
+        self.check_error()?;
+        if self.pending == Some(self.read_active_frame() as usize) {
+            self.pending = None;
+            self.fresh = true;
+        }
+        if self.read_status() & VDMA_IRQ_FRAME_COUNT == 0 {
+            return Ok(false);
+        }
         unsafe {
-            self.udmabuf_acc[self.desired_frame].copy_from(frame, 0, count);
+            self.uio_acc.write_mem32(MM2S_DMASR, VDMA_IRQ_FRAME_COUNT);
+        }
+        if !std::mem::take(&mut self.fresh) {
+            self.repeated += 1;
         }
+        Ok(true)
+    }
+
+    /// Frame store `index`, e.g. to prepare frames before `start`.
+    pub fn frame_buffer(&self, index: usize) -> Option<&DmaBuffer> {
+        self.udmabuf_acc.get(index)
+    }
+    pub fn frame_buffer_mut(&mut self, index: usize) -> Option<&mut DmaBuffer> {
+        self.udmabuf_acc.get_mut(index)
     }
 
     pub fn read_desired_frame(&self) -> u32 {
         unsafe { self.uio_acc.read_mem32(PARK_PTR_REG) & 0x1F }
     }
     pub fn read_active_frame(&self) -> u32 {
-        unsafe { (self.uio_acc.read_mem32(PARK_PTR_REG) >> 16) & 0x1F }
+        unsafe { (self.uio_acc.read_mem32(PARK_PTR_REG) >> PARK_PTR_RD_STORE_SHIFT) & 0x1F }
     }
 
     fn write_desired_frame(&self) {
-        let mut reg = unsafe { self.uio_acc.read_mem32(PARK_PTR_REG) };
-        reg &= !0x1F;
-        reg |= self.desired_frame as u32;
+        write_park_ptr(&*self.uio_acc, PARK_PTR_RD_REF_SHIFT, self.desired_frame);
+    }
+}
+
+/// Bytes from one line to the next: a line rounded up to the memory-map
+/// data width.
+fn calc_stride(frame_width: u32, bytes_per_pix: u32, mm_data_bytes: u32) -> u32 {
+    (frame_width * bytes_per_pix).next_multiple_of(mm_data_bytes)
+}
+
+fn check_frame_size(driver: &str, frame_width: u32, frame_height: u32) -> Result<()> {
+    ensure!(
+        frame_width > 0 && frame_height > 0,
+        InvalidState,
+        "{}: set frame_width and frame_height before start",
+        driver
+    );
+    Ok(())
+}
+
+fn check_frame_buffers(count: usize, frame_stores: usize, running: bool) -> Result<()> {
+    ensure!(
+        (1..=frame_stores).contains(&count),
+        InvalidArgument,
+        "frame buffer count must be 1 to {} (C_NUM_FSTORES), found {}",
+        frame_stores,
+        count
+    );
+    ensure!(
+        !running,
+        InvalidState,
+        "FRMSTORE can only be changed while the channel is halted"
+    );
+    Ok(())
+}
+
+/// How a channel walks its frame stores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VdmaMode {
+    /// Every frame store in turn.
+    Circular,
+    /// Only the frame store set with `set_park_frame`.
+    Park,
+}
+
+/// Capture through the S2MM (stream to memory) channel, one udmabuf per
+/// frame store.
+///
+/// In circular mode `read_frame` returns the last completed frame. In park
+/// mode it moves the park pointer to the next frame store first, so the
+/// returned frame is not overwritten while it is read. The VDMA only takes
+/// the park pointer in at frame start, so when it has not visibly moved on
+/// `read_frame` waits for one more frame, which may have gone to the same
+/// store.
+///
+/// The frame stores can also be the buffers of a `FramePool`, see
+/// `attach_pool`.
+pub struct AxiVdmaS2MM {
+    uio_acc: Box<dyn RegIo>,
+    udmabuf_acc: Vec<DmaBuffer>,
+    /// The frame size has to be set before `start`.
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// `C_*_TDATA_WIDTH` / 8 by default: one pixel per stream beat.
+    pub bytes_per_pix: u32,
+    pub pix_per_clk: u32,
+    config: VdmaConfig,
+    park_frame: usize,
+    frame_buffers: usize,
+    frame_stores: usize,
+    mm_data_bytes: u32,
+    genlock_mode: GenlockMode,
+    pool: Option<FramePool>,
+    /// Pool buffer the channel is parked on.
+    pool_writing: Option<usize>,
+    addr_width: u32,
+    timeout: Duration,
+}
+
+impl AxiVdmaS2MM {
+    pub fn new(hw_info: &serde_json::Value) -> Result<Self> {
+        Self::with_backend(hw_info, &DeviceBackend)
+    }
+
+    pub fn with_backend(hw_info: &serde_json::Value, backend: &dyn Backend) -> Result<Self> {
+        Self::from_desc_with_backend(&IpDescriptor::from_json(hw_info)?, backend)
+    }
+
+    pub fn from_desc(desc: &IpDescriptor) -> Result<Self> {
+        Self::from_desc_with_backend(desc, &DeviceBackend)
+    }
+
+    pub fn from_desc_with_backend(desc: &IpDescriptor, backend: &dyn Backend) -> Result<Self> {
+        desc.ensure_ip("xilinx.com", &["ip"], "axi_vdma", "AxiVdmaS2MM::new()")?;
+        ensure!(
+            desc.param_bool_or("C_INCLUDE_S2MM", true)?,
+            Unsupported,
+            "AxiVdmaS2MM::new(): The S2MM channel is not included in this IP."
+        );
+        let params =
+            ChannelParams::read(desc, "S2MM").map_err(|e| e.context("AxiVdmaS2MM::new()"))?;
+        let uio = backend.open_uio(desc.uio()?)?;
+        let mut udmabuf = Vec::new();
+        for udmabuf_name in desc.udmabuf.iter() {
+            let io = backend.open_udmabuf(udmabuf_name, desc.udmabuf_cached)?;
+            udmabuf.push(DmaBuffer::from_io(io));
+        }
+
+        Ok(AxiVdmaS2MM {
+            uio_acc: uio,
+            udmabuf_acc: udmabuf,
+            frame_width: 0,
+            frame_height: 0,
+            bytes_per_pix: params.bytes_per_pix,
+            pix_per_clk: 1,
+            config: VdmaConfig::default(),
+            park_frame: 0,
+            frame_buffers: params.frame_stores,
+            frame_stores: params.frame_stores,
+            mm_data_bytes: params.mm_data_bytes,
+            genlock_mode: params.genlock_mode,
+            pool: None,
+            pool_writing: None,
+            addr_width: params.addr_width,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.read_status() & VDMASR_HALTED == 0
+    }
+
+    pub fn read_status(&self) -> u32 {
+        unsafe { self.uio_acc.read_mem32(S2MM_DMASR) }
+    }
+
+    /// The error flagged in VDMASR, if any. Frame size errors are cleared
+    /// once reported; the others halt the channel until `reset`.
+    pub fn check_error(&self) -> Result<()> {
+        check_vdmasr(&*self.uio_acc, S2MM_DMASR).map_err(|e| {
+            e.context(format!(
+                "AxiVdmaS2MM {}x{}",
+                self.frame_width, self.frame_height
+            ))
+        })
+    }
+
+    /// Timeout of `start`, `stop`, `reset` and the wait for a frame.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn genlock_mode(&self) -> GenlockMode {
+        self.genlock_mode
+    }
+
+    pub fn frame_buffers(&self) -> usize {
+        self.frame_buffers
+    }
+
+    /// Uses the first `count` frame stores from the next `start`; the channel
+    /// must be halted.
+    pub fn set_frame_buffers(&mut self, count: usize) -> Result<()> {
+        check_frame_buffers(count, self.frame_stores, self.is_running())?;
+        self.config.validate(count)?;
+        self.frame_buffers = count;
+        self.park_frame %= count;
+        Ok(())
+    }
+    pub fn frame_buffer(&self, index: usize) -> Option<&DmaBuffer> {
+        self.udmabuf_acc.get(index)
+    }
+
+    pub fn config(&self) -> &VdmaConfig {
+        &self.config
+    }
+
+    /// Applied at once if the channel is running, otherwise on `start`.
+    pub fn set_config(&mut self, config: VdmaConfig) -> Result<()> {
+        config.validate(self.frame_buffers)?;
+        self.config = config;
+        if self.is_running() {
+            self.write_control(VDMACR_RS);
+            self.write_format()?;
+        }
+        Ok(())
+    }
+
+    pub fn mode(&self) -> VdmaMode {
+        self.config.mode
+    }
+
+    pub fn set_mode(&mut self, mode: VdmaMode) -> Result<()> {
+        self.set_config(VdmaConfig {
+            mode,
+            ..self.config
+        })
+    }
+
+    /// The frame store written in park mode.
+    pub fn set_park_frame(&mut self, index: usize) -> Result<()> {
+        check_park_frame(index, self.frame_buffers)?;
+        self.park_frame = index;
+        write_park_ptr(&*self.uio_acc, PARK_PTR_WR_REF_SHIFT, index);
+        Ok(())
+    }
+
+    /// Frame store the channel is writing.
+    pub fn read_active_frame(&self) -> u32 {
+        unsafe { (self.uio_acc.read_mem32(PARK_PTR_REG) >> PARK_PTR_WR_STORE_SHIFT) & 0x1F }
+    }
+
+    fn write_control(&self, run: u32) {
+        let reg = self
+            .config
+            .control(VDMA_IRQ_FRAME_COUNT | VDMA_IRQ_ERR, run);
+        unsafe {
+            self.uio_acc.write_mem32(S2MM_DMACR, reg);
+        }
+    }
+
+    fn write_framebuf_addr(&self) -> Result<()> {
+        for i in 0..self.frame_buffers {
+            let addr = match &self.pool {
+                Some(pool) => pool.phys_addr(i)?,
+                None => self.udmabuf_acc[i].phys_addr(),
+            };
+            let (lsb, msb) = split_phys_addr(addr, self.addr_width)?;
+            unsafe {
+                if self.addr_width > 32 {
+                    self.uio_acc.write_mem32(S2MM_START_ADDRESS1 + 8 * i, lsb);
+                    self.uio_acc
+                        .write_mem32(S2MM_START_ADDRESS1 + 8 * i + 4, msb);
+                } else {
+                    self.uio_acc.write_mem32(S2MM_START_ADDRESS1 + 4 * i, lsb);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn stride(&self) -> u32 {
+        calc_stride(self.frame_width, self.bytes_per_pix, self.mm_data_bytes)
+    }
+
+    pub fn write_format(&self) -> Result<()> {
+        let size = (self.stride() * self.frame_height) as usize;
+        // Pool buffers were checked when the pool was attached.
+        let buffers = if self.pool.is_some() {
+            0
+        } else {
+            self.frame_buffers
+        };
+        for buf in &self.udmabuf_acc[..buffers] {
+            if size > buf.size() {
+                return Err(XipError::BufferTooLarge {
+                    size,
+                    capacity: buf.size(),
+                });
+            }
+        }
+        unsafe {
+            self.uio_acc
+                .write_mem32(S2MM_HSIZE, self.frame_width * self.bytes_per_pix);
+        }
+        unsafe {
+            self.uio_acc.write_mem32(
+                S2MM_FRMDLY_STRIDE,
+                frmdly_stride(&self.config, self.stride()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Writing VSIZE makes the channel pick up the other settings.
+    pub fn reload(&self) {
         unsafe {
-            self.uio_acc.write_mem32(PARK_PTR_REG, reg);
+            self.uio_acc.write_mem32(S2MM_VSIZE, self.frame_height);
         }
     }
-    fn inc_desired_frame(&mut self) {
-        self.desired_frame = (self.read_desired_frame() as usize + 1) % self.frame_buffers;
+
+    pub fn stop(&self) -> Result<()> {
+        self.write_control(0);
+        wait_until(
+            "AxiVdmaS2MM::stop",
+            self.timeout,
+            || Ok(!self.is_running()),
+            || self.read_status(),
+        )
+    }
+
+    pub fn reset(&self) -> Result<()> {
+        self.stop()?;
+        unsafe {
+            self.uio_acc.write_mem32(S2MM_DMACR, VDMACR_RESET);
+        }
+        wait_until(
+            "AxiVdmaS2MM::reset",
+            self.timeout,
+            || Ok(unsafe { self.uio_acc.read_mem32(S2MM_DMACR) } & VDMACR_RESET == 0),
+            || unsafe { self.uio_acc.read_mem32(S2MM_DMACR) },
+        )
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        check_frame_size("AxiVdmaS2MM", self.frame_width, self.frame_height)?;
+        if let Some(pool) = &self.pool {
+            ensure!(
+                self.config.mode == VdmaMode::Park,
+                InvalidState,
+                "AxiVdmaS2MM: a FramePool needs park mode"
+            );
+            let index = match self.pool_writing {
+                Some(index) => index,
+                None => pool.acquire_write()?,
+            };
+            self.pool_writing = Some(index);
+            self.park_frame = index;
+        }
+        self.write_framebuf_addr()?;
+        self.write_format()?;
+        unsafe {
+            self.uio_acc
+                .write_mem32(S2MM_FRMSTORE, self.frame_buffers() as u32);
+        }
+        self.set_park_frame(self.park_frame)?;
+        let size = (self.stride() * self.frame_height) as usize;
+        let buffers = if self.pool.is_some() {
+            0
+        } else {
+            self.frame_buffers
+        };
+        for buf in &self.udmabuf_acc[..buffers] {
+            buf.sync_for_device(0, size)?;
+        }
+        self.write_control(VDMACR_RS);
+        wait_until(
+            "AxiVdmaS2MM::start",
+            self.timeout,
+            || Ok(self.is_running()),
+            || self.read_status(),
+        )?;
+        self.reload();
+        self.uio_acc.set_irq_enable(true)?;
+        Ok(())
+    }
+
+    /// Waits for the frame-count interrupt and returns the frame store
+    /// holding the frame that just completed.
+    pub fn next_frame(&mut self) -> Result<usize> {
+        ensure!(
+            self.pool.is_none(),
+            InvalidState,
+            "AxiVdmaS2MM: the frame stores belong to a FramePool, use capture_to_pool"
+        );
+        self.wait_frame_count()?;
+        let frames = self.frame_buffers();
+        let done = match self.config.mode {
+            VdmaMode::Circular => (self.read_active_frame() as usize + frames - 1) % frames,
+            VdmaMode::Park => {
+                let done = self.park_frame;
+                self.park_after(done, (done + 1) % frames)?;
+                done
+            }
+        };
+        let size = (self.stride() * self.frame_height) as usize;
+        self.udmabuf_acc[done].sync_for_cpu(0, size)?;
+        Ok(done)
+    }
+
+    /// Parks on `next` after a frame was written to `done`. A frame that
+    /// started before the park pointer moved still goes to `done`; unless
+    /// the VDMA reports `next` already, wait until that one is written too.
+    fn park_after(&mut self, done: usize, next: usize) -> Result<()> {
+        self.set_park_frame(next)?;
+        if next != done && self.read_active_frame() as usize == done {
+            self.wait_frame_count()?;
+        }
+        Ok(())
+    }
+
+    /// Sleeps on the interrupt until the frame-count event, up to the
+    /// channel timeout.
+    fn wait_frame_count(&mut self) -> Result<()> {
+        let deadline = IrqDeadline::new("AxiVdmaS2MM::wait_frame_count", self.timeout);
+        loop {
+            self.uio_acc.set_irq_enable(true)?;
+            self.check_error()?;
+            if self.read_status() & VDMA_IRQ_FRAME_COUNT != 0 {
+                unsafe {
+                    self.uio_acc.write_mem32(S2MM_DMASR, VDMA_IRQ_FRAME_COUNT);
+                }
+                return Ok(());
+            }
+            deadline.wait_irq(self.uio_acc.as_mut(), S2MM_DMASR)?;
+        }
+    }
+
+    /// Layout of the frames with the current size, for `attach_pool`.
+    pub fn frame_spec(&self) -> FrameSpec {
+        FrameSpec::packed(self.frame_width, self.frame_height, self.bytes_per_pix)
+            .with_stride(self.stride())
+    }
+
+    /// Uses the buffers of `pool` as frame stores from the next `start` and
+    /// publishes every captured frame with `capture_to_pool`. The channel
+    /// must be halted, in park mode once started, and the pool must hold
+    /// frames of the current size and stride.
+    pub fn attach_pool(&mut self, pool: &FramePool) -> Result<()> {
+        let attached = pool.attach(&self.frame_spec())?;
+        self.detach_pool()?;
+        self.set_frame_buffers(pool.len())?;
+        self.pool = Some(attached);
+        Ok(())
+    }
+
+    /// Gives the frame stores back to the udmabufs; the channel must be
+    /// halted.
+    pub fn detach_pool(&mut self) -> Result<()> {
+        ensure!(
+            !self.is_running(),
+            InvalidState,
+            "AxiVdmaS2MM: detach the FramePool while halted"
+        );
+        if let (Some(pool), Some(index)) = (&self.pool, self.pool_writing.take()) {
+            pool.release_write(index)?;
+        }
+        self.pool = None;
+        Ok(())
+    }
+
+    /// Waits for the frame-count interrupt, parks the channel on a pool
+    /// buffer no reader holds and publishes the frame that just completed
+    /// once no frame can land on it any more. Returns its sequence number.
+    pub fn capture_to_pool(&mut self) -> Result<u64> {
+        let (Some(pool), Some(done)) = (self.pool.clone(), self.pool_writing) else {
+            bail!(
+                InvalidState,
+                "AxiVdmaS2MM: attach a FramePool and start first"
+            );
+        };
+        self.wait_frame_count()?;
+        let next = pool.acquire_write()?;
+        if let Err(e) = self.park_after(done, next) {
+            // Keep capturing into `done`, which is not published.
+            self.set_park_frame(done)?;
+            pool.release_write(next)?;
+            return Err(e);
+        }
+        self.pool_writing = Some(next);
+        pool.publish(done)
+    }
+
+    /// Waits for the next frame and copies it out without the line padding.
+    pub fn read_frame(&mut self) -> Result<Vec<u8>> {
+        let index = self.next_frame()?;
+        let line = (self.frame_width * self.bytes_per_pix) as usize;
+        let stride = self.stride() as usize;
+        let frame = self.udmabuf_acc[index].slice::<u8>(0, stride * self.frame_height as usize)?;
+        let mut buf = Vec::with_capacity(line * self.frame_height as usize);
+        for row in frame.chunks(stride) {
+            buf.extend_from_slice(&row[..line]);
+        }
+        Ok(buf)
+    }
+
+    pub fn read_frame_as_image(&mut self) -> Result<image::RgbImage> {
+        ensure!(
+            self.bytes_per_pix == 3,
+            Format,
+            "read_frame_as_image needs 3 bytes per pixel, found {}",
+            self.bytes_per_pix
+        );
+        image::ImageBuffer::from_raw(self.frame_width, self.frame_height, self.read_frame()?)
+            .ok_or_else(|| XipError::Format("Can't convert to image".to_string()))
     }
 }
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axidma::AxiDma;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::AxiDmaModel;
+use xipdriver_rs::umv_lane_detector::UmvLaneDetector;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead};
+use xipdriver_rs::vdma::AxiVdmaMM2S;
+
+const HIGH: usize = 0x8_0000_0000;
+
+fn dma(
+    backend: &SimBackend,
+    scatter_gather: bool,
+    addr_width: u32,
+) -> Result<(AxiDmaModel, AxiDma)> {
+    let model = AxiDmaModel::new(scatter_gather).with_addr_width(addr_width);
+    backend
+        .add_uio("axi_dma_0", 0x1000)
+        .set_model(model.clone());
+    // Only the MM2S buffer is above 4 GiB.
+    backend.add_udmabuf("udmabuf1", 0x1000);
+    backend.add_udmabuf_at("udmabuf0", HIGH, 0x1000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_dma",
+        "uio": "axi_dma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1"],
+        "params": {
+            "C_INCLUDE_SG": scatter_gather as u32,
+            "C_ADDR_WIDTH": addr_width,
+        },
+    });
+    Ok((model, AxiDma::with_backend(&hw_info, backend)?))
+}
+
+#[test]
+fn axidma_simple_above_4gib() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, false, 40)?;
+    dma.start();
+    dma.write(&[1u8, 2, 3, 4])?;
+    let regs = backend.uio("axi_dma_0").unwrap();
+    assert_eq!(regs.read32(0x18), 0);
+    assert_eq!(regs.read32(0x1C), 8);
+    assert_eq!(model.sent(), vec![vec![1, 2, 3, 4]]);
+
+    model.push_packet(vec![5, 6]);
+    assert_eq!(dma.read::<u8>(2)?, vec![5, 6]);
+    assert_eq!(regs.read32(0x4C), 0);
+    Ok(())
+}
+
+#[test]
+fn axidma_sg_above_4gib() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true, 64)?;
+    dma.setup_sg(4)?;
+    dma.start();
+    let regs = backend.uio("axi_dma_0").unwrap();
+    assert_eq!(regs.read_addr(0x08), HIGH);
+
+    let data: Vec<u8> = (0..100).collect();
+    dma.write_sg(&data)?;
+    assert_eq!(model.sent(), vec![data.clone()]);
+    assert_eq!(regs.read_addr(0x10), HIGH);
+    let ring = backend.udmabuf("udmabuf0").unwrap();
+    assert_eq!(ring.read_addr(0x00), HIGH + 0x40);
+    assert_eq!(ring.read_addr(0x08), HIGH + 0x100);
+
+    model.push_packet(data.clone());
+    assert_eq!(dma.read_sg::<u8>(100)?, data);
+    Ok(())
+}
+
+#[test]
+fn axidma_rejects_unreachable_buffer() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, false, 32)?;
+    dma.start();
+    let err = dma.write(&[1u8, 2, 3, 4]).unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+    assert!(model.sent().is_empty());
+    model.push_packet(vec![5, 6]);
+    assert_eq!(dma.read::<u8>(2)?, vec![5, 6]);
+    Ok(())
+}
+
+fn v_frmbuf_rd(addr_width: u32) -> serde_json::Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_frmbuf_rd",
+        "uio": "v_frmbuf_rd",
+        "udmabuf": ["udmabuf0"],
+        "params": {
+            "MAX_COLS": 16,
+            "MAX_ROWS": 4,
+            "HAS_RGB8": 1,
+            "HAS_YUYV8": 1,
+            "SAMPLES_PER_CLOCK": 1,
+            "AXIMM_ADDR_WIDTH": addr_width,
+        },
+    })
+}
+
+#[test]
+fn v_frmbuf_above_4gib() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("v_frmbuf_rd", 0x1000);
+    backend.add_udmabuf_at("udmabuf0", HIGH + 0x1000, 16 * 4 * 3);
+
+    let mut vfb_r = VideoFrameBufRead::with_backend(&v_frmbuf_rd(64), &backend)?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    vfb_r.configure()?;
+    assert_eq!(regs.read_addr(0x30), HIGH + 0x1000);
+
+    let mut vfb_r = VideoFrameBufRead::with_backend(&v_frmbuf_rd(32), &backend)?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    let err = vfb_r.configure().unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+
+    let err = VideoFrameBufRead::with_backend(&v_frmbuf_rd(16), &backend)
+        .err()
+        .unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn vdma_extended_start_addresses() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_vdma_0", 0x1000);
+    for i in 0..3 {
+        backend.add_udmabuf_at(&format!("udmabuf{}", i), HIGH + i * 0x10_0000, 0x10_0000);
+    }
+    let mut vdma = AxiVdmaMM2S::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_vdma",
+            "uio": "axi_vdma_0",
+            "udmabuf": ["udmabuf0", "udmabuf1", "udmabuf2"],
+            "params": { "C_ADDR_WIDTH": 64 },
+        }),
+        &backend,
+    )?;
+    vdma.frame_width = 64;
+    vdma.frame_height = 32;
+    vdma.start()?;
+    for i in 0..3 {
+        assert_eq!(regs.read_addr(0x5C + 8 * i), HIGH + i * 0x10_0000);
+    }
+    Ok(())
+}
+
+#[test]
+fn umv_lane_detector_needs_low_memory() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("umv_lane_detector_0", 0x1000);
+    backend.add_udmabuf_at("udmabuf0", HIGH, 0x1000);
+    let detector = UmvLaneDetector::with_backend(
+        &json!({
+            "vendor": "slab",
+            "library": "umv_project",
+            "name": "umv_lane_detector",
+            "uio": "umv_lane_detector_0",
+            "udmabuf": ["udmabuf0"],
+            "params": {
+                "IMAGE_WIDTH": 64,
+                "IMAGE_HEIGHT": 32,
+                "MAX_DETECT_LINES": 16,
+                "FILTER_TYPE_DEFAULT": 0,
+            },
+        }),
+        &backend,
+    )?;
+    let err = detector.write_framebuf_addr().unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+    Ok(())
+}
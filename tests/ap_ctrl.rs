@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::ap_ctrl::{ApCtrl, AP_INT_DONE};
+use xipdriver_rs::bird_eye_view::BirdEyeViewHW;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::VideoFrameBufWriteModel;
+use xipdriver_rs::timeout::TimeoutError;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufWrite};
+use xipdriver_rs::v_proc_ss::VideoProcSubsystemCsc;
+
+fn bird_eye_view(backend: &SimBackend) -> Result<BirdEyeViewHW> {
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "hls",
+        "name": "bird_eye_view",
+        "uio": "bird_eye_view_0",
+        "udmabuf": ["udmabuf0", "udmabuf0", "udmabuf0"],
+    });
+    backend.add_uio("bird_eye_view_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x10);
+    Ok(BirdEyeViewHW::with_backend(&hw_info, backend)?)
+}
+
+#[test]
+fn control_bits() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("v_proc_ss_0", 0x10000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_proc_ss",
+        "uio": "v_proc_ss_0",
+    });
+    let csc = VideoProcSubsystemCsc::with_backend(&hw_info, &backend)?;
+    regs.write32(0x00, 0x0E);
+    assert!(csc.is_done() && csc.is_idle() && csc.is_ready());
+    assert!(!csc.is_running() && !csc.get_auto_restart_enable());
+    regs.write32(0x00, 0x81);
+    assert!(csc.is_running() && csc.get_auto_restart_enable());
+
+    let bev = bird_eye_view(&backend)?;
+    bev.set_auto_restart_enable(true);
+    assert_eq!(backend.uio("bird_eye_view_0").unwrap().read32(0x00), 0x80);
+    bev.ap_start();
+    assert_eq!(backend.uio("bird_eye_view_0").unwrap().read32(0x00), 0x81);
+    Ok(())
+}
+
+#[test]
+fn start_and_wait() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = VideoFrameBufWriteModel::new();
+    backend
+        .add_uio("v_frmbuf_wr_0", 0x10000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_frmbuf_wr",
+        "uio": "v_frmbuf_wr_0",
+        "udmabuf": ["udmabuf0"],
+        "params": { "MAX_COLS": 16, "MAX_ROWS": 4, "HAS_RGB8": 1, "HAS_YUYV8": 1, "SAMPLES_PER_CLOCK": 1 },
+    });
+    let mut vfb_w = VideoFrameBufWrite::with_backend(&hw_info, &backend)?;
+    vfb_w.set_format(ColorFormat::Yuyv8)?;
+    vfb_w.write_format()?;
+    vfb_w.set_framebuf_addr()?;
+    vfb_w.start_and_wait(Duration::from_millis(100))?;
+    vfb_w.start_and_wait(Duration::from_millis(100))?;
+    assert_eq!(model.frame_count(), 2);
+    assert!(vfb_w.is_idle());
+
+    // Interrupt on ap_done, acknowledged afterwards.
+    vfb_w.enable_interrupt(AP_INT_DONE)?;
+    vfb_w.ap_start();
+    assert_eq!(vfb_w.interrupt_status(), AP_INT_DONE);
+    vfb_w.clear_interrupt(AP_INT_DONE);
+    assert_eq!(vfb_w.interrupt_status(), 0);
+    vfb_w.disable_interrupt();
+    Ok(())
+}
+
+#[test]
+fn start_and_wait_times_out() -> Result<()> {
+    // Without a model nothing ever reports ap_done.
+    let backend = SimBackend::new();
+    let bev = bird_eye_view(&backend)?;
+    let err = bev.start_and_wait(Duration::from_millis(10)).unwrap_err();
+    assert!(
+        matches!(err, XipError::Timeout(TimeoutError { status: 0x01, .. })),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn done_interrupt_times_out() -> Result<()> {
+    let backend = SimBackend::new();
+    let mut bev = bird_eye_view(&backend)?;
+    bev.ap_start();
+    let err = bev
+        .wait_done_interrupt(Duration::from_millis(10))
+        .unwrap_err();
+    match err {
+        XipError::Timeout(e) => {
+            assert_eq!(e.operation, "ap_ctrl: wait_done_interrupt");
+            assert_eq!(e.status, 0x01);
+        }
+        other => panic!("{:?}", other),
+    }
+    Ok(())
+}
@@ -0,0 +1,335 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axidma::{AxiDma, DmaSegment, DmaStatus};
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::AxiDmaModel;
+
+fn dma(backend: &SimBackend, scatter_gather: bool) -> Result<(AxiDmaModel, AxiDma)> {
+    let model = AxiDmaModel::new(scatter_gather);
+    backend
+        .add_uio("axi_dma_0", 0x1000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    backend.add_udmabuf("udmabuf1", 0x1000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_dma",
+        "uio": "axi_dma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1"],
+        "params": {
+            "C_INCLUDE_SG": scatter_gather as u32,
+            "C_SG_LENGTH_WIDTH": 8,
+        },
+    });
+    Ok((model, AxiDma::with_backend(&hw_info, backend)?))
+}
+
+#[test]
+fn simple_mode_round_trip() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, false)?;
+    dma.start();
+    dma.write(&[1u32, 2, 3])?;
+    assert_eq!(model.sent(), vec![vec![1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]]);
+
+    model.push_packet(vec![9, 8, 7, 6]);
+    assert_eq!(dma.read::<u8>(4)?, vec![9, 8, 7, 6]);
+
+    // LENGTH is 8 bits wide here, whatever the udmabuf holds.
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    let err = mm2s.write_in_place(0x100).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::BufferTooLarge {
+                size: 0x100,
+                capacity: 0xff
+            }
+        ),
+        "{:?}",
+        err
+    );
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    let err = s2mm.read_in_place(0x100).unwrap_err();
+    assert!(
+        matches!(err, XipError::BufferTooLarge { size: 0x100, .. }),
+        "{:?}",
+        err
+    );
+
+    let err = dma.setup_sg(4).unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn sg_splits_into_descriptors() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true)?;
+    dma.setup_sg(8)?;
+    dma.start();
+
+    // 255-byte descriptors carry 192 bytes each to keep segments aligned.
+    let data: Vec<u8> = (0..500).map(|i| i as u8).collect();
+    let status = dma.write_sg(&data)?;
+    assert_eq!(
+        status.iter().map(|s| s.transferred).collect::<Vec<_>>(),
+        vec![192, 192, 116]
+    );
+    assert!(status.iter().all(|s| s.complete && !s.is_error()));
+    assert_eq!(model.sent(), vec![data.clone()]);
+
+    // The ring wraps around.
+    for _ in 0..4 {
+        dma.write_sg(&data[..300])?;
+    }
+    assert_eq!(model.sent().len(), 5);
+    assert_eq!(model.sent()[4], data[..300]);
+
+    model.push_packet(data[..400].to_vec());
+    assert_eq!(dma.read_sg::<u8>(400)?, data[..400]);
+    Ok(())
+}
+
+#[test]
+fn sg_submit_and_status() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true)?;
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    assert!(s2mm.has_sg());
+    assert_eq!(s2mm.max_segment_len(), 255);
+
+    let err = s2mm.submit(&[]).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    s2mm.setup_sg(2)?;
+    s2mm.start();
+
+    let base = backend.udmabuf("udmabuf1").unwrap();
+    let data = base.phys_addr() + s2mm.sg_data_offset()?;
+    let segments = [
+        DmaSegment {
+            phys_addr: data,
+            len: 64,
+        },
+        DmaSegment {
+            phys_addr: data + 64,
+            len: 64,
+        },
+    ];
+    let transfer = s2mm.submit(&segments)?;
+    // No stream data yet.
+    assert!(!s2mm.poll_transfer(&transfer)?);
+    let err = s2mm.submit(&segments[..1]).unwrap_err();
+    assert!(err.to_string().contains("ring is full"), "{}", err);
+
+    model.push_packet(vec![0x55; 100]);
+    let status = s2mm.wait_transfer(&transfer)?;
+    assert_eq!(
+        (status[0].transferred, status[0].sof, status[0].eof),
+        (64, true, false)
+    );
+    assert_eq!(
+        (status[1].transferred, status[1].sof, status[1].eof),
+        (36, false, true)
+    );
+    assert_eq!(
+        base.read_bytes(s2mm.sg_data_offset()?, 100),
+        vec![0x55; 100]
+    );
+
+    let err = s2mm
+        .submit(&[DmaSegment {
+            phys_addr: data,
+            len: 256,
+        }])
+        .unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn s2mm_stream_recycles_buffers() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true)?;
+    dma.set_timeout(Duration::from_millis(10));
+    let mut stream = dma.stream(3, 128)?;
+    assert_eq!(stream.buffers(), 3);
+    assert!(stream.try_next()?.is_none());
+
+    for i in 0..2u8 {
+        model.push_packet(vec![i; 100]);
+    }
+    let first = stream.next_buffer()?;
+    let second = stream.next().unwrap()?;
+    assert_eq!((first.index(), second.index()), (0, 1));
+    assert_eq!(stream.read::<u8>(&first), vec![0; 100]);
+    assert_eq!(stream.read::<u8>(&second), vec![1; 100]);
+    assert!(second.status().sof && second.status().eof);
+
+    // Released out of order, re-armed in ring order.
+    stream.release(second)?;
+    stream.release(first)?;
+    assert_eq!(stream.overruns(), 0);
+
+    // A packet longer than one buffer spans several.
+    model.push_packet((0..200).map(|i| i as u8).collect());
+    let head = stream.next_buffer()?;
+    let tail = stream.next_buffer()?;
+    assert_eq!(
+        (head.index(), head.len(), head.status().eof),
+        (2, 128, false)
+    );
+    assert_eq!((tail.index(), tail.len(), tail.status().eof), (0, 72, true));
+    assert_eq!(stream.read::<u8>(&tail)[0], 128);
+
+    // Filling the last armed buffer leaves nothing for the DMA.
+    assert_eq!(stream.overruns(), 0);
+    model.push_packet(vec![6; 16]);
+    let third = stream.next_buffer()?;
+    assert_eq!(stream.overruns(), 1);
+    model.push_packet(vec![7; 16]);
+    let fourth = stream.next_buffer();
+    assert!(matches!(fourth, Err(XipError::Timeout(_))), "{:?}", fourth);
+    for buf in [head, tail, third] {
+        stream.release(buf)?;
+    }
+    let fourth = stream.next_buffer()?;
+    assert_eq!(
+        (fourth.index(), stream.read::<u8>(&fourth)),
+        (2, vec![7; 16])
+    );
+    assert_eq!(stream.overruns(), 1);
+    stream.stop()?;
+    Ok(())
+}
+
+#[test]
+fn interrupt_completion() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, false)?;
+    let regs = backend.uio("axi_dma_0").unwrap();
+    let err = dma.enable_interrupt(4, 0).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+
+    dma.enable_interrupt(1, 0)?;
+    dma.start();
+    assert_eq!(regs.read32(0x00), 0x0001_5001);
+    assert_eq!(regs.read32(0x30), 0x0001_5001);
+    model.push_packet(vec![3; 8]);
+    assert_eq!(dma.read::<u8>(8)?, vec![3; 8]);
+    // DMASR.IOC_Irq was acknowledged.
+    assert_eq!(regs.read32(0x34) & 0x7000, 0);
+
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    mm2s.write(&[1u8, 2])?;
+    assert_eq!(mm2s.interrupt_status(), 0x1000);
+    mm2s.wait()?;
+    assert_eq!(mm2s.interrupt_status(), 0);
+    mm2s.disable_interrupt()?;
+    assert_eq!(regs.read32(0x00), 0x0000_0001);
+    Ok(())
+}
+
+#[test]
+fn interrupt_coalescing() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true)?;
+    let regs = backend.uio("axi_dma_0").unwrap();
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    s2mm.setup_sg(8)?;
+    s2mm.enable_interrupt(4, 0)?;
+    s2mm.start();
+    assert_eq!(regs.read32(0x30), 0x0004_5001);
+
+    let data = backend.udmabuf("udmabuf1").unwrap().phys_addr() + s2mm.sg_data_offset()?;
+    let segments: Vec<DmaSegment> = (0..6)
+        .map(|i| DmaSegment {
+            phys_addr: data + 64 * i,
+            len: 64,
+        })
+        .collect();
+    let transfer = s2mm.submit(&segments)?;
+    for _ in 0..3 {
+        model.push_packet(vec![1; 64]);
+    }
+    assert!(!s2mm.poll_transfer(&transfer)?);
+    assert_eq!(s2mm.interrupt_status(), 0);
+    // Nothing to wake up on: the interrupt wait gives up with the status.
+    match s2mm.wait_transfer(&transfer) {
+        Err(XipError::Timeout(e)) => {
+            assert_eq!(e.operation, "AxiDmaChannel::wait_transfer");
+            assert_eq!(e.status & 0x7000, 0);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    model.push_packet(vec![1; 64]);
+    assert!(!s2mm.poll_transfer(&transfer)?);
+    assert_eq!(s2mm.interrupt_status(), 0x1000);
+    s2mm.clear_interrupt(0x1000);
+
+    // The delay timer reports the two completions below the threshold.
+    s2mm.enable_interrupt(4, 10)?;
+    assert_eq!(regs.read32(0x30), 0x0A04_7001);
+    model.push_packet(vec![1; 128]);
+    assert_eq!(s2mm.wait_transfer(&transfer)?.len(), 6);
+    assert_eq!(s2mm.interrupt_status(), 0);
+    assert_eq!(regs.read32(0x34) & 0x7000, 0);
+    Ok(())
+}
+
+#[test]
+fn status_decoding() {
+    let status = DmaStatus::from_reg(0x0305_7779);
+    assert!(status.halted && !status.idle && status.sg_included);
+    assert!(status.internal_error && status.slave_error && status.decode_error);
+    assert!(status.sg_internal_error && status.sg_slave_error && status.sg_decode_error);
+    assert!(status.ioc_irq && status.delay_irq && status.error_irq);
+    assert_eq!((status.irq_threshold, status.irq_delay), (0x05, 0x03));
+    assert!(matches!(
+        status.error(),
+        Some(XipError::DmaInternal {
+            status: 0x0305_7779
+        })
+    ));
+
+    let status = DmaStatus::from_reg(0x0000_0402);
+    assert!(status.idle && status.sg_decode_error && status.is_error());
+    assert!(!DmaStatus::from_reg(0x0001_1002).is_error());
+}
+
+#[test]
+fn reset_recovers_after_error() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut dma) = dma(&backend, true)?;
+    let regs = backend.uio("axi_dma_0").unwrap();
+    dma.setup_sg(4)?;
+    dma.start();
+    let data: Vec<u8> = (0..100).collect();
+    dma.write_sg(&data)?;
+
+    // A slave error halts the channel.
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    s2mm.set_auto_reset(true);
+    regs.write32(0x34, 0x0000_0029);
+    let err = s2mm.read_sg::<u8>(16).unwrap_err();
+    assert!(matches!(err, XipError::DmaSlave { .. }), "{:?}", err);
+    // Reset and restarted, with a fresh descriptor ring.
+    assert!(s2mm.is_running() && !s2mm.is_error());
+    model.push_packet(data.clone());
+    assert_eq!(s2mm.read_sg::<u8>(100)?, data);
+
+    // The soft reset also halted MM2S.
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    assert!(!mm2s.is_running());
+    mm2s.reset()?;
+    mm2s.start();
+    mm2s.write_sg(&data)?;
+    assert_eq!(model.sent(), vec![data.clone(), data]);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axigpio::AxiGpio;
+use xipdriver_rs::board::{Board, Registry};
+use xipdriver_rs::hwinfo::HwInfo;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::vdma::{AxiVdmaMM2S, AxiVdmaS2MM};
+
+fn v_frmbuf(name: &str, uio: &str, udmabuf: &str) -> serde_json::Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": name,
+        "uio": uio,
+        "udmabuf": [udmabuf],
+        "params": {
+            "MAX_COLS": 64,
+            "MAX_ROWS": 8,
+            "HAS_RGB8": 1,
+            "HAS_YUYV8": 1,
+            "SAMPLES_PER_CLOCK": 1,
+        },
+    })
+}
+
+fn gpio(uio: &str) -> serde_json::Value {
+    json!({ "vendor": "xilinx.com", "library": "ip", "name": "axi_gpio", "uio": uio })
+}
+
+fn board() -> Result<(SimBackend, Board)> {
+    let backend = SimBackend::new();
+    backend.add_uio("v_frmbuf_rd", 0x10000);
+    backend.add_uio("v_frmbuf_wr", 0x10000);
+    backend.add_uio("gpio_0", 0x1000);
+    backend.add_uio("gpio_1", 0x1000);
+    backend.add_udmabuf("udmabuf0", 64 * 8 * 3);
+    backend.add_udmabuf("udmabuf1", 64 * 8 * 3);
+    let hw = HwInfo::from_json(&json!({
+        "/lane_detection/v_frmbuf_rd": v_frmbuf("v_frmbuf_rd", "v_frmbuf_rd", "udmabuf0"),
+        "/lane_detection/v_frmbuf_wr": v_frmbuf("v_frmbuf_wr", "v_frmbuf_wr", "udmabuf1"),
+        "/gpio/axi_gpio_0": gpio("gpio_0"),
+        "/gpio/axi_gpio_1": gpio("gpio_1"),
+        "/gpio/axi_gpio_2": gpio("gpio_2"),
+        "/zynq_ultra_ps_e_0": { "vendor": "xilinx.com", "library": "ip", "name": "zynq_ultra_ps_e" },
+    }))?;
+    let board = Board::with_backend(&hw, &backend);
+    Ok((backend, board))
+}
+
+#[test]
+fn typed_handles_by_glob() -> Result<()> {
+    let (backend, mut board) = board()?;
+
+    let vfb_r = board.get_mut::<VideoFrameBufRead>("/lane_detection/*")?;
+    vfb_r.set_format(ColorFormat::Yuyv8)?;
+    vfb_r.write_format()?;
+    assert_eq!(backend.uio("v_frmbuf_rd").unwrap().read32(0x28), 12);
+    assert!(board.get::<VideoFrameBufWrite>("/lane_detection/*").is_ok());
+    assert!(board.get::<VideoFrameBufWrite>("/**/v_frmbuf_?r").is_ok());
+
+    // Two gpios open under /gpio, so the pattern has to be specific.
+    assert_eq!(board.paths::<AxiGpio>("/gpio/*").len(), 2);
+    let err = board.get::<AxiGpio>("/gpio/*").err().unwrap().to_string();
+    assert!(err.contains("matches several"), "{}", err);
+    let gpio = board.take::<AxiGpio>("/gpio/axi_gpio_1")?;
+    gpio.write_data(1, 0x5A)?;
+    assert_eq!(backend.uio("gpio_1").unwrap().read32(0x00), 0x5A);
+    assert!(board.get::<AxiGpio>("/gpio/*").is_ok());
+
+    // `*` does not cross levels.
+    assert!(board.get::<VideoFrameBufRead>("/*").is_err());
+    Ok(())
+}
+
+#[test]
+fn unknown_and_failed_ips_are_reported() -> Result<()> {
+    let (_backend, mut board) = board()?;
+
+    let unknown: Vec<&str> = board.unknown().iter().map(|ip| ip.path.as_str()).collect();
+    assert_eq!(unknown, vec!["/zynq_ultra_ps_e_0"]);
+    let failed: Vec<&str> = board
+        .failed()
+        .iter()
+        .map(|(ip, _)| ip.path.as_str())
+        .collect();
+    assert_eq!(failed, vec!["/gpio/axi_gpio_2"]);
+
+    let report = board.report().to_string();
+    assert!(
+        report.contains("/zynq_ultra_ps_e_0: unknown IP xilinx.com:ip:zynq_ultra_ps_e"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("/gpio/axi_gpio_2: failed to open"),
+        "{}",
+        report
+    );
+    assert!(board.ensure_all_open().is_err());
+
+    let err = board
+        .get::<AxiGpio>("/gpio/axi_gpio_2")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("uio device not found: gpio_2"), "{}", err);
+    let err = board.get::<AxiGpio>("/zynq*").err().unwrap().to_string();
+    assert!(err.contains("has no driver"), "{}", err);
+    let err = board
+        .get::<AxiGpio>("/lane_detection/v_frmbuf_rd")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        err.contains("is a xipdriver_rs::v_frmbuf::VideoFrameBufRead"),
+        "{}",
+        err
+    );
+
+    board.take::<AxiGpio>("/gpio/axi_gpio_0")?;
+    let err = board
+        .take::<AxiGpio>("/gpio/axi_gpio_0")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("already taken"), "{}", err);
+    Ok(())
+}
+
+struct PsStub;
+
+#[test]
+fn custom_registry() -> Result<()> {
+    let (backend, _) = board()?;
+    let hw = HwInfo::from_json(&json!({
+        "/zynq_ultra_ps_e_0": { "vendor": "xilinx.com", "library": "ip", "name": "zynq_ultra_ps_e" },
+        "/gpio/axi_gpio_0": gpio("gpio_0"),
+    }))?;
+    let mut registry = Registry::new();
+    registry.register("xilinx.com", "zynq_*", |_, _| Ok(PsStub));
+    let board = Board::with_registry(&hw, &registry, &backend);
+    board.ensure_all_open()?;
+    assert!(board.get::<PsStub>("/zynq_ultra_ps_e_0").is_ok());
+    Ok(())
+}
+
+#[test]
+fn vdma_opens_the_included_channel() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("vdma_0", 0x1000);
+    backend.add_uio("vdma_1", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    let vdma = |uio: &str, params: serde_json::Value| {
+        json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_vdma",
+            "uio": uio,
+            "udmabuf": ["udmabuf0"],
+            "params": params,
+        })
+    };
+    let hw = HwInfo::from_json(&json!({
+        "/display/axi_vdma_0": vdma("vdma_0", json!({ "C_NUM_FSTORES": 1 })),
+        "/capture/axi_vdma_0": vdma("vdma_1", json!({ "C_NUM_FSTORES": 1, "C_INCLUDE_MM2S": 0 })),
+    }))?;
+    let board = Board::with_backend(&hw, &backend);
+    board.ensure_all_open()?;
+    assert!(board.get::<AxiVdmaMM2S>("/display/*").is_ok());
+    assert!(board.get::<AxiVdmaS2MM>("/capture/*").is_ok());
+    assert!(board.get::<AxiVdmaMM2S>("/capture/*").is_err());
+    Ok(())
+}
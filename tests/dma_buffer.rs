@@ -0,0 +1,184 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axidma::AxiDma;
+use xipdriver_rs::dma_buffer::DmaBuffer;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::{SimBackend, SimSync};
+use xipdriver_rs::sim_models::{AxiDmaModel, VideoFrameBufWriteModel};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
+
+#[test]
+fn slices_view_the_mapping() -> Result<()> {
+    let backend = SimBackend::new();
+    let region = backend.add_udmabuf("udmabuf0", 0x100);
+    let mut buf = DmaBuffer::with_backend("udmabuf0", &backend)?;
+    assert_eq!(buf.size(), 0x100);
+    assert_eq!(buf.phys_addr(), region.phys_addr());
+
+    buf.as_mut_slice::<u32>()[1] = 0x4433_2211;
+    assert_eq!(region.read_bytes(4, 4), vec![0x11, 0x22, 0x33, 0x44]);
+    buf.slice_mut::<[u8; 3]>(0x10, 2)?[1] = [7, 8, 9];
+    assert_eq!(region.read_bytes(0x13, 3), vec![7, 8, 9]);
+    region.write_bytes(0xFC, &[1, 0, 0, 0]);
+    assert_eq!(buf.as_slice::<u32>().len(), 0x40);
+    assert_eq!(buf.slice::<u32>(0xFC, 1)?, &[1]);
+
+    let err = buf.slice::<u32>(0xFC, 2).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    let err = buf.slice::<u32>(2, 1).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    assert!(buf.sync_for_device(0, 0x100).is_ok());
+    assert!(buf.sync_for_cpu(0x80, 0x81).is_err());
+    Ok(())
+}
+
+#[test]
+fn axidma_in_place() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = AxiDmaModel::new(false);
+    backend
+        .add_uio("axi_dma_0", 0x1000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 0x100);
+    backend.add_udmabuf("udmabuf1", 0x100);
+    let mut dma = AxiDma::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_dma",
+            "uio": "axi_dma_0",
+            "udmabuf": ["udmabuf0", "udmabuf1"],
+        }),
+        &backend,
+    )?;
+    dma.start();
+
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    mm2s.buffer_mut().as_mut_slice::<u16>()[..2].copy_from_slice(&[0x0201, 0x0403]);
+    mm2s.write_in_place(4)?;
+    assert_eq!(model.sent(), vec![vec![1, 2, 3, 4]]);
+    let err = mm2s.write_in_place(0x101).unwrap_err();
+    assert!(matches!(err, XipError::BufferTooLarge { .. }), "{:?}", err);
+
+    model.push_packet(vec![5, 6, 7]);
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    assert_eq!(s2mm.read_in_place(0x10)?, 3);
+    assert_eq!(s2mm.buffer().slice::<u8>(0, 3)?, &[5, 6, 7]);
+    Ok(())
+}
+
+fn v_frmbuf(name: &str, uio: &str, udmabuf: &str) -> serde_json::Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": name,
+        "uio": uio,
+        "udmabuf": [udmabuf],
+        "params": {
+            "MAX_COLS": 4,
+            "MAX_ROWS": 2,
+            "HAS_RGB8": 1,
+            "HAS_YUYV8": 1,
+            "SAMPLES_PER_CLOCK": 1,
+        },
+    })
+}
+
+#[test]
+fn v_frmbuf_in_place() -> Result<()> {
+    let backend = SimBackend::new();
+    let rd_regs = backend.add_uio("v_frmbuf_rd", 0x1000);
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(VideoFrameBufWriteModel::new());
+    backend.add_udmabuf("udmabuf0", 16 * 2);
+    let wr_buf = backend.add_udmabuf("udmabuf1", 16 * 2);
+
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf("v_frmbuf_rd", "v_frmbuf_rd", "udmabuf0"),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    for (i, b) in vfb_r
+        .buffer_mut()
+        .as_mut_slice::<u8>()
+        .iter_mut()
+        .enumerate()
+    {
+        *b = i as u8;
+    }
+    vfb_r.write_frame_in_place()?;
+    assert_eq!(rd_regs.read32(0x30) as usize, vfb_r.buffer().phys_addr());
+    assert_eq!(rd_regs.read32(0x00) & 0x81, 0x81);
+    vfb_r.frame_height = 3;
+    assert!(vfb_r.write_frame_in_place().is_err());
+
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf("v_frmbuf_wr", "v_frmbuf_wr", "udmabuf1"),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::Yuyv8)?;
+    wr_buf.write_bytes(0, &[9; 16]);
+    assert_eq!(vfb_w.read_frame_in_place()?, &[9; 16]);
+    Ok(())
+}
+
+#[test]
+fn cached_buffers_are_synced() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = AxiDmaModel::new(true);
+    backend
+        .add_uio("axi_dma_0", 0x1000)
+        .set_model(model.clone());
+    let mm2s_buf = backend.add_udmabuf("udmabuf0", 0x1000);
+    let s2mm_buf = backend.add_udmabuf("udmabuf1", 0x1000);
+    let mut dma = AxiDma::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_dma",
+            "uio": "axi_dma_0",
+            "udmabuf": ["udmabuf0", "udmabuf1"],
+            "udmabuf_cached": true,
+            "params": { "C_INCLUDE_SG": 1 },
+        }),
+        &backend,
+    )?;
+    assert!(mm2s_buf.cached() && s2mm_buf.cached());
+    dma.setup_sg(2)?;
+    assert_eq!(mm2s_buf.take_syncs(), vec![SimSync::ForDevice(0, 0x80)]);
+    dma.start();
+
+    dma.write_sg(&[1u8; 16])?;
+    let syncs = mm2s_buf.take_syncs();
+    assert_eq!(
+        syncs[..2],
+        [SimSync::ForDevice(0, 0x40), SimSync::ForDevice(0x80, 16)]
+    );
+    assert!(syncs[2..]
+        .iter()
+        .all(|sync| *sync == SimSync::ForCpu(0, 0x40)));
+
+    model.push_packet(vec![2; 8]);
+    assert_eq!(dma.read_sg::<u8>(8)?, vec![2; 8]);
+    assert_eq!(
+        s2mm_buf.take_syncs().last(),
+        Some(&SimSync::ForCpu(0x80, 8))
+    );
+
+    // Uncached buffers are never synced.
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(VideoFrameBufWriteModel::new());
+    let wr_buf = backend.add_udmabuf("udmabuf2", 16 * 2);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf("v_frmbuf_wr", "v_frmbuf_wr", "udmabuf2"),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
+    vfb_w.read_frame()?;
+    assert!(!wr_buf.cached());
+    assert!(wr_buf.take_syncs().is_empty());
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use serde_json::json;
+
+use xipdriver_rs::axidma::AxiDma;
+use xipdriver_rs::axigpio::AxiGpio;
+use xipdriver_rs::bird_eye_view::BirdEyeViewHW;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::hwinfo::HwInfo;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead};
+
+fn dma(backend: &SimBackend, params: serde_json::Value) -> Result<AxiDma, XipError> {
+    backend.add_uio("axi_dma_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    backend.add_udmabuf("udmabuf1", 0x100);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_dma",
+        "uio": "axi_dma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1"],
+        "params": params,
+    });
+    AxiDma::with_backend(&hw_info, backend)
+}
+
+#[test]
+fn open_errors() {
+    let backend = SimBackend::new();
+    let gpio =
+        json!({ "vendor": "xilinx.com", "library": "ip", "name": "axi_gpio", "uio": "gpio_0" });
+    let err = AxiGpio::with_backend(&gpio, &backend).err().unwrap();
+    assert!(matches!(err, XipError::Mapping(_)), "{:?}", err);
+
+    let not_gpio =
+        json!({ "vendor": "xilinx.com", "library": "ip", "name": "axi_dma", "uio": "gpio_0" });
+    let err = AxiGpio::with_backend(&not_gpio, &backend).err().unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+
+    // bird_eye_view needs an input, a map and an output buffer.
+    backend.add_uio("bird_eye_view_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x10);
+    let bev = json!({
+        "vendor": "xilinx.com",
+        "library": "hls",
+        "name": "bird_eye_view",
+        "uio": "bird_eye_view_0",
+        "udmabuf": ["udmabuf0", "udmabuf0"],
+    });
+    let err = BirdEyeViewHW::with_backend(&bev, &backend).err().unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+
+    let err = HwInfo::read("/nonexistent/hwinfo.json").unwrap_err();
+    assert!(matches!(err, XipError::Io { .. }), "{:?}", err);
+}
+
+#[test]
+fn dma_errors() -> anyhow::Result<()> {
+    let backend = SimBackend::new();
+    let mut dma = dma(&backend, json!({ "C_INCLUDE_S2MM": 0 }))?;
+    // DMASR reads as running (halted = 0).
+    let regs = backend.uio("axi_dma_0").unwrap();
+
+    let err = dma.read::<u8>(4).unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    regs.write32(0x04, 0x10);
+    assert!(matches!(
+        mm2s.wait(),
+        Err(XipError::DmaInternal { status: 0x10 })
+    ));
+    regs.write32(0x04, 0x20);
+    assert!(matches!(
+        mm2s.wait(),
+        Err(XipError::DmaSlave { status: 0x20 })
+    ));
+    regs.write32(0x04, 0x40);
+    assert!(matches!(
+        mm2s.wait(),
+        Err(XipError::DmaDecode { status: 0x40 })
+    ));
+    regs.write32(0x04, 0x0209);
+    assert!(matches!(
+        mm2s.wait(),
+        Err(XipError::DmaSgSlave { status: 0x0209 })
+    ));
+    regs.write32(0x04, 0x01);
+    let err = mm2s.wait().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+
+    regs.write32(0x04, 0x02);
+    let err = mm2s.write(&[0u8; 0x101]).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::BufferTooLarge {
+                size: 0x101,
+                capacity: 0x100
+            }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn s2mm_errors_come_from_s2mm_dmasr() -> anyhow::Result<()> {
+    let backend = SimBackend::new();
+    let mut dma = dma(&backend, json!({}))?;
+    let regs = backend.uio("axi_dma_0").unwrap();
+    regs.write32(0x04, 0x10);
+    regs.write32(0x34, 0x40);
+    let s2mm = dma.s2mm.as_mut().unwrap();
+    assert!(matches!(
+        s2mm.wait(),
+        Err(XipError::DmaDecode { status: 0x40 })
+    ));
+    regs.write32(0x34, 0x02);
+    let err = dma.read::<u8>(0x101).unwrap_err();
+    assert!(matches!(err, XipError::BufferTooLarge { .. }), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn buffer_too_large() -> anyhow::Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("bird_eye_view_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x10);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "hls",
+        "name": "bird_eye_view",
+        "uio": "bird_eye_view_0",
+        "udmabuf": ["udmabuf0", "udmabuf0", "udmabuf0"],
+    });
+    let mut bev = BirdEyeViewHW::with_backend(&hw_info, &backend)?;
+    bev.write_img_in(&[0; 4])?;
+    // Sizes are in bytes, not u32 elements.
+    for len in [5, 0x20] {
+        let err = bev.write_img_in(&vec![0; len]).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                XipError::BufferTooLarge { size, capacity: 0x10 } if size == 4 * len
+            ),
+            "{:?}",
+            err
+        );
+        let err = bev.write_img_map(&vec![0; len]).unwrap_err();
+        assert!(matches!(err, XipError::BufferTooLarge { .. }), "{:?}", err);
+    }
+    let err = bev.read_img_out().unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::BufferTooLarge {
+                size: 0x0038_4000,
+                capacity: 0x10
+            }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn format_errors() -> anyhow::Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("v_frmbuf_rd_0", 0x10000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_frmbuf_rd",
+        "uio": "v_frmbuf_rd_0",
+        "udmabuf": ["udmabuf0"],
+        "params": { "MAX_COLS": 16, "MAX_ROWS": 4, "HAS_RGB8": 1, "HAS_YUYV8": 0, "SAMPLES_PER_CLOCK": 1 },
+    });
+    let mut vfb_r = VideoFrameBufRead::with_backend(&hw_info, &backend)?;
+    let err = vfb_r.set_format(ColorFormat::Yuyv8).unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    assert_eq!(err.to_string(), "YUYV8 is not enabled");
+
+    // Converts into anyhow and back.
+    let err: anyhow::Error = vfb_r.write_format().unwrap_err().into();
+    assert!(matches!(
+        err.downcast_ref::<XipError>(),
+        Some(XipError::Format(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage, Rgba, RgbaImage};
+
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::frame_image::{image_to_frame, FrameImage};
+use xipdriver_rs::v_frmbuf::ColorFormat;
+
+fn test_image() -> RgbImage {
+    RgbImage::from_fn(4, 2, |x, y| {
+        Rgb([100 + x as u8 * 8, 80 + y as u8 * 10, 160 - x as u8 * 6])
+    })
+}
+
+fn assert_close(a: &RgbImage, b: &RgbImage, tolerance: u8) {
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for (ca, cb) in pa.0.iter().zip(pb.0) {
+            assert!(ca.abs_diff(cb) <= tolerance, "{:?} vs {:?}", pa, pb);
+        }
+    }
+}
+
+#[test]
+fn rgb_layouts() -> Result<()> {
+    let cases = [
+        (ColorFormat::Rgb8, vec![1, 2, 3, 4, 5, 6], [1, 2, 3]),
+        (ColorFormat::Bgr8, vec![1, 2, 3, 4, 5, 6], [3, 2, 1]),
+        (ColorFormat::Rgbx8, vec![1, 2, 3, 0, 4, 5, 6, 0], [1, 2, 3]),
+        (ColorFormat::Bgrx8, vec![1, 2, 3, 0, 4, 5, 6, 0], [3, 2, 1]),
+    ];
+    for (fmt, bytes, first) in cases {
+        let FrameImage::Rgb(img) = FrameImage::from_frame(fmt, 2, 1, &bytes)? else {
+            panic!("{} is not converted to RGB", fmt);
+        };
+        assert_eq!(img.get_pixel(0, 0).0, first, "{}", fmt);
+        let back = image_to_frame(fmt, &DynamicImage::ImageRgb8(img))?;
+        assert_eq!(back, bytes, "{}", fmt);
+    }
+
+    let bgra = [1, 2, 3, 4, 5, 6, 7, 8];
+    let img = FrameImage::from_frame(ColorFormat::Bgra8, 2, 1, &bgra)?;
+    let rgba = RgbaImage::from_raw(2, 1, vec![3, 2, 1, 4, 7, 6, 5, 8]).unwrap();
+    assert_eq!(img, FrameImage::Rgba(rgba.clone()));
+    assert_eq!(img.clone().into_rgb8().get_pixel(1, 0).0, [7, 6, 5]);
+    let img = DynamicImage::from(img);
+    assert_eq!(image_to_frame(ColorFormat::Bgra8, &img)?, bgra);
+    assert_eq!(img.get_pixel(1, 0), Rgba([7, 6, 5, 8]));
+    Ok(())
+}
+
+#[test]
+fn luma() -> Result<()> {
+    let frame: Vec<u8> = (0..8).collect();
+    let img = FrameImage::from_frame(ColorFormat::Y8, 4, 2, &frame)?;
+    assert!(matches!(img, FrameImage::Luma(_)));
+    assert_eq!(img.dimensions(), (4, 2));
+    assert_eq!(image_to_frame(ColorFormat::Y8, &img.into())?, frame);
+    Ok(())
+}
+
+#[test]
+fn yuv_round_trip() -> Result<()> {
+    let img = test_image();
+    let dynamic = DynamicImage::ImageRgb8(img.clone());
+    for (fmt, size) in [
+        (ColorFormat::Yuyv8, 16),
+        (ColorFormat::Uyvy8, 16),
+        (ColorFormat::YUv8, 16),
+        (ColorFormat::YUv8420, 12),
+    ] {
+        let frame = image_to_frame(fmt, &dynamic)?;
+        assert_eq!(frame.len(), size, "{}", fmt);
+        let back = FrameImage::from_frame(fmt, 4, 2, &frame)?.into_rgb8();
+        // Neighbouring pixels share their chroma.
+        assert_close(&back, &img, 12);
+    }
+
+    let grey = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([128, 128, 128])));
+    for fmt in [ColorFormat::Yuyv8, ColorFormat::Uyvy8, ColorFormat::YUv8420] {
+        let frame = image_to_frame(fmt, &grey)?;
+        let back = FrameImage::from_frame(fmt, 4, 2, &frame)?.into_rgb8();
+        assert_close(&back, &grey.to_rgb8(), 1);
+    }
+    // Y, U, Y, V: white and black share the neutral chroma.
+    let yuyv = [235, 128, 16, 128];
+    let img = FrameImage::from_frame(ColorFormat::Yuyv8, 2, 1, &yuyv)?.into_rgb8();
+    assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255]);
+    assert_eq!(img.get_pixel(1, 0).0, [0, 0, 0]);
+    Ok(())
+}
+
+#[test]
+fn conversion_errors() {
+    let err = FrameImage::from_frame(ColorFormat::Rgb8, 4, 2, &[0; 23]).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    let err = FrameImage::from_frame(ColorFormat::Rgb565, 4, 2, &[0; 16]).unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+    let err = FrameImage::from_frame(ColorFormat::YUv8420, 3, 2, &[0; 9]).unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    let img = DynamicImage::ImageRgb8(test_image());
+    let err = image_to_frame(ColorFormat::Yuv8, &img).unwrap_err();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+}
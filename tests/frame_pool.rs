@@ -0,0 +1,408 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use xipdriver_rs::bird_eye_view::BirdEyeViewHW;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::frame_pool::{FramePool, FrameSpec, PoolFrame};
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::{
+    AxiVdmaMM2SModel, AxiVdmaS2MMModel, BirdEyeViewModel, UmvLaneDetectorModel,
+    VideoFrameBufReadModel, VideoFrameBufWriteModel,
+};
+use xipdriver_rs::umv_lane_detector::{LanePoint, UmvLaneDetector};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
+use xipdriver_rs::vdma::{AxiVdmaMM2S, AxiVdmaS2MM, VdmaMode};
+
+fn pool(backend: &SimBackend, spec: FrameSpec, buffers: usize, size: usize) -> Result<FramePool> {
+    let names: Vec<String> = (0..buffers).map(|i| format!("pool{}", i)).collect();
+    for name in &names {
+        backend.add_udmabuf(name, size);
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    Ok(FramePool::with_backend(spec, &names, backend)?)
+}
+
+#[test]
+fn ping_pong() -> Result<()> {
+    let backend = SimBackend::new();
+    let spec = FrameSpec::new(4, 2, ColorFormat::Rgb8).with_stride(16);
+    assert_eq!(spec.size(), 32);
+    let pool = pool(&backend, spec, 2, 32)?;
+    assert_eq!(pool.acquire_read(), None);
+
+    let first = pool.acquire_write()?;
+    assert_eq!(first, 0);
+    let frame: Vec<u8> = (0..24).collect();
+    pool.write_frame(first, &frame)?;
+    assert_eq!(pool.publish(first)?, 0);
+    assert_eq!(pool.read_frame(first)?, frame);
+    let pool0 = backend.udmabuf("pool0").unwrap();
+    assert_eq!(pool0.read_bytes(16, 12), &frame[12..]);
+
+    let read = pool.acquire_read().unwrap();
+    assert_eq!(
+        read,
+        PoolFrame {
+            index: 0,
+            sequence: 0
+        }
+    );
+    assert_eq!(pool.acquire_write()?, 1);
+    assert_eq!(pool.publish(1)?, 1);
+    // Buffer 0 is still being read, so the unread latest frame is replaced.
+    assert_eq!(pool.acquire_write()?, 1);
+    let err = pool.acquire_write().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    pool.release_read(0)?;
+    assert_eq!(pool.acquire_write()?, 0);
+    assert_eq!(pool.acquire_read(), None);
+    pool.release_write(0)?;
+    assert_eq!(pool.publish(1)?, 2);
+    assert_eq!(pool.acquire_read().map(|f| f.index), Some(1));
+
+    let err = pool.release_read(0).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    let err = pool.publish(0).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+
+    for err in [
+        pool.phys_addr(2).unwrap_err(),
+        pool.plane_addrs(2).unwrap_err(),
+    ] {
+        assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    }
+    Ok(())
+}
+
+#[test]
+fn pool_needs_room() -> Result<()> {
+    let backend = SimBackend::new();
+    let spec = FrameSpec::new(4, 2, ColorFormat::Rgb8).with_stride(16);
+    let err = pool(&backend, spec, 1, 32).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(XipError::InvalidArgument(_))),
+        "{:?}",
+        err
+    );
+    let backend = SimBackend::new();
+    let err = pool(&backend, spec, 2, 24).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref(),
+            Some(XipError::BufferTooLarge {
+                size: 32,
+                capacity: 24
+            })
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn spec_compatibility() -> Result<()> {
+    let backend = SimBackend::new();
+    let pool = pool(&backend, FrameSpec::new(8, 2, ColorFormat::Rgb8), 2, 0x100)?;
+    assert_eq!(pool.handle_count(), 1);
+    // An IP that only knows the pixel size matches any 3-byte format.
+    let vdma_side = pool.attach(&FrameSpec::packed(8, 2, 3))?;
+    assert_eq!(pool.handle_count(), 2);
+    drop(vdma_side);
+    assert_eq!(pool.handle_count(), 1);
+
+    for spec in [
+        FrameSpec::new(8, 2, ColorFormat::Bgr8),
+        FrameSpec::new(8, 3, ColorFormat::Rgb8),
+        FrameSpec::packed(8, 2, 4),
+        FrameSpec::new(8, 2, ColorFormat::Rgb8).with_stride(32),
+    ] {
+        let err = pool.attach(&spec).unwrap_err();
+        assert!(matches!(err, XipError::Format(_)), "{}: {:?}", spec, err);
+    }
+    Ok(())
+}
+
+fn v_frmbuf(name: &str, params: Value) -> Value {
+    let mut all = json!({ "MAX_COLS": 16, "MAX_ROWS": 4, "SAMPLES_PER_CLOCK": 1 });
+    all.as_object_mut()
+        .unwrap()
+        .extend(params.as_object().unwrap().clone());
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": name,
+        "uio": name,
+        "udmabuf": [format!("{}_buf", name)],
+        "params": all,
+    })
+}
+
+#[test]
+fn v_frmbuf_handoff() -> Result<()> {
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(wr_model.clone());
+    backend
+        .add_uio("v_frmbuf_rd", 0x1000)
+        .set_model(rd_model.clone());
+    backend.add_udmabuf("v_frmbuf_wr_buf", 0x1000);
+    backend.add_udmabuf("v_frmbuf_rd_buf", 0x1000);
+    let params = json!({ "HAS_RGB8": 1, "HAS_Y_UV8_420": 1 });
+    let mut vfb_w =
+        VideoFrameBufWrite::with_backend(&v_frmbuf("v_frmbuf_wr", params.clone()), &backend)?;
+    let mut vfb_r = VideoFrameBufRead::with_backend(&v_frmbuf("v_frmbuf_rd", params), &backend)?;
+    vfb_w.set_format(ColorFormat::YUv8420)?;
+    vfb_r.set_format(ColorFormat::YUv8420)?;
+    let spec = vfb_w.frame_spec()?;
+    let pool = pool(&backend, spec, 3, 0x100)?;
+    vfb_w.attach_pool(&pool)?;
+    vfb_r.attach_pool(&pool)?;
+    assert_eq!(pool.handle_count(), 3);
+    assert_eq!(vfb_r.show_from_pool()?, None);
+
+    let frame: Vec<u8> = (0..16 * 6).collect();
+    wr_model.set_frame(frame.clone());
+    assert_eq!(vfb_w.capture_to_pool()?, 0);
+    let regs = backend.uio("v_frmbuf_wr").unwrap();
+    assert_eq!(regs.read_addr(0x3C), pool.phys_addr(0)? + 16 * 4);
+    assert_eq!(vfb_r.show_from_pool()?, Some(0));
+    assert_eq!(rd_model.last_frame(), Some(frame));
+    // The reader gave the buffer back, so nothing is held.
+    assert_eq!(pool.acquire_write()?, 1);
+
+    vfb_r.frame_width = 8;
+    let err = vfb_r.show_from_pool().unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    let err = vfb_r.attach_pool(&pool).unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    vfb_r.detach_pool();
+    assert_eq!(pool.handle_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn bird_eye_view_pools() -> Result<()> {
+    let backend = SimBackend::new();
+    backend
+        .add_uio("bird_eye_view_0", 0x1000)
+        .set_model(BirdEyeViewModel::new(1280, 720));
+    let pixels = 1280 * 720;
+    let map = backend.add_udmabuf("map", pixels * 4);
+    for i in 0..pixels {
+        // Mirror every line.
+        let (y, x) = (i / 1280, i % 1280);
+        map.write32(4 * i, (y * 1280 + 1279 - x) as u32);
+    }
+    let mut bev = BirdEyeViewHW::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "hls",
+            "name": "bird_eye_view",
+            "uio": "bird_eye_view_0",
+            "udmabuf": ["map", "map", "map"],
+            "params": {},
+        }),
+        &backend,
+    )?;
+    bev.set_img_map_addr()?;
+    let spec = FrameSpec::new(1280, 720, ColorFormat::Rgbx8);
+    let pool_in = pool(&backend, spec, 2, pixels * 4)?;
+    let names = ["out0", "out1"];
+    for name in names {
+        backend.add_udmabuf(name, pixels * 4);
+    }
+    let pool_out = FramePool::with_backend(spec, &names, &backend)?;
+    let err = bev.run_pools().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    bev.attach_input_pool(&pool_in)?;
+    bev.attach_output_pool(&pool_out)?;
+    assert_eq!(bev.run_pools()?, None);
+
+    let index = pool_in.acquire_write()?;
+    let frame: Vec<u8> = (0..pixels * 4).map(|i| (i / 4) as u8).collect();
+    pool_in.write_frame(index, &frame)?;
+    pool_in.publish(index)?;
+    assert_eq!(bev.run_pools()?, Some(0));
+    let out = pool_out.acquire_read().unwrap();
+    let img = pool_out.read_frame(out.index)?;
+    assert_eq!(img[..4], [(1279 % 256) as u8; 4]);
+    assert_eq!(img[4 * 1280..][..4], frame[4 * 2559..][..4]);
+    Ok(())
+}
+
+#[test]
+fn lane_detector_pool() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = UmvLaneDetectorModel::new();
+    backend
+        .add_uio("umv_lane_detector_0", 0x1000)
+        .set_model(model.clone());
+    backend.add_udmabuf("points", 0x100);
+    let mut ld = UmvLaneDetector::with_backend(
+        &json!({
+            "vendor": "slab",
+            "library": "umv_project",
+            "name": "umv_lane_detector",
+            "uio": "umv_lane_detector_0",
+            "udmabuf": ["points"],
+            "params": {
+                "IMAGE_WIDTH": 640,
+                "IMAGE_HEIGHT": 480,
+                "MAX_DETECT_LINES": 16,
+                "FILTER_TYPE_DEFAULT": 0,
+            },
+        }),
+        &backend,
+    )?;
+    let pool = pool(&backend, FrameSpec::packed(16, 1, 4), 2, 0x100)?;
+    let err = ld.read_pool_data().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    ld.attach_pool(&pool)?;
+    model.set_points(vec![LanePoint {
+        direction: 1,
+        x: 20,
+        y: 30,
+    }]);
+    ld.start_into_pool()?;
+    let regs = backend.uio("umv_lane_detector_0").unwrap();
+    assert_eq!(regs.read32(0x28) as usize, pool.phys_addr(0)?);
+    assert_eq!(regs.read32(0x2C), 16);
+    let (sequence, points) = ld.read_pool_data()?;
+    assert_eq!(sequence, 0);
+    assert_eq!((points[0].direction, points[0].x, points[0].y), (1, 20, 30));
+    let frame = pool.acquire_read().unwrap();
+    assert_eq!(frame.index, 0);
+    assert_eq!(
+        pool.read_frame(0)?[..4],
+        ((1 << 28) | (20 << 14) | 30u32).to_le_bytes()
+    );
+
+    ld.start_into_pool()?;
+    assert_eq!(regs.read32(0x28) as usize, pool.phys_addr(1)?);
+    ld.detach_pool()?;
+    assert_eq!(pool.acquire_write()?, 1);
+    Ok(())
+}
+
+fn axi_vdma(uio: &str) -> Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_vdma",
+        "uio": uio,
+        "udmabuf": (0..3).map(|i| format!("{}_buf{}", uio, i)).collect::<Vec<_>>(),
+        "params": { "C_NUM_FSTORES": 3 },
+    })
+}
+
+#[test]
+fn vdma_handoff() -> Result<()> {
+    let backend = SimBackend::new();
+    let s2mm_model = AxiVdmaS2MMModel::new();
+    let mm2s_model = AxiVdmaMM2SModel::new();
+    backend
+        .add_uio("axi_vdma_0", 0x1000)
+        .set_model(s2mm_model.clone());
+    backend
+        .add_uio("axi_vdma_1", 0x1000)
+        .set_model(mm2s_model.clone());
+    for i in 0..3 {
+        backend.add_udmabuf(&format!("axi_vdma_0_buf{}", i), 0x100);
+        backend.add_udmabuf(&format!("axi_vdma_1_buf{}", i), 0x100);
+    }
+    let mut s2mm = AxiVdmaS2MM::with_backend(&axi_vdma("axi_vdma_0"), &backend)?;
+    let mut mm2s = AxiVdmaMM2S::with_backend(&axi_vdma("axi_vdma_1"), &backend)?;
+    s2mm.frame_width = 8;
+    s2mm.frame_height = 2;
+    mm2s.frame_width = 8;
+    mm2s.frame_height = 2;
+    let pool = pool(&backend, FrameSpec::new(8, 2, ColorFormat::Rgb8), 3, 0x100)?;
+    s2mm.attach_pool(&pool)?;
+    mm2s.attach_pool(&pool)?;
+    assert_eq!(s2mm.frame_buffers(), 3);
+
+    // Pool buffers are only handed out in park mode.
+    let err = s2mm.start().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    s2mm.set_mode(VdmaMode::Park)?;
+    s2mm.start()?;
+    let s2mm_regs = backend.uio("axi_vdma_0").unwrap();
+    assert_eq!(s2mm_regs.read32(0xAC) as usize, pool.phys_addr(0)?);
+    let err = mm2s.start().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+
+    // The S2MM channel reports the finished buffer until the next frame
+    // starts, so every capture also waits for the frame after it.
+    let push_twice = |n| {
+        for _ in 0..2 {
+            s2mm_model.push_frame(vec![n; 48]);
+        }
+    };
+    push_twice(1);
+    assert_eq!(s2mm.capture_to_pool()?, 0);
+    mm2s.start()?;
+    mm2s_model.advance(1);
+    mm2s.read_active_frame();
+    assert_eq!(mm2s_model.last_frame(), Some(vec![1; 48]));
+    assert_eq!(mm2s.show_from_pool()?, None);
+
+    push_twice(2);
+    assert_eq!(s2mm.capture_to_pool()?, 1);
+    assert_eq!(mm2s.show_from_pool()?, Some(1));
+    // Buffer 0 stays held until the VDMA has switched to buffer 1, so the
+    // frame is dropped.
+    s2mm_model.push_frame(vec![3; 48]);
+    let err = s2mm.capture_to_pool().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    mm2s_model.advance(1);
+    assert_eq!(mm2s.show_from_pool()?, None);
+    assert_eq!(mm2s_model.last_frame(), Some(vec![2; 48]));
+    push_twice(4);
+    assert_eq!(s2mm.capture_to_pool()?, 2);
+    assert_eq!(mm2s.show_from_pool()?, Some(2));
+    mm2s_model.advance(1);
+    mm2s.read_active_frame();
+    assert_eq!(mm2s_model.last_frame(), Some(vec![4; 48]));
+
+    let err = mm2s.acquire().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    let err = mm2s.detach_pool().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn vdma_capture_waits_out_a_started_frame() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = AxiVdmaS2MMModel::new();
+    backend
+        .add_uio("axi_vdma_0", 0x1000)
+        .set_model(model.clone());
+    for i in 0..3 {
+        backend.add_udmabuf(&format!("axi_vdma_0_buf{}", i), 0x100);
+    }
+    let mut s2mm = AxiVdmaS2MM::with_backend(&axi_vdma("axi_vdma_0"), &backend)?;
+    s2mm.frame_width = 8;
+    s2mm.frame_height = 2;
+    let pool = pool(&backend, FrameSpec::new(8, 2, ColorFormat::Rgb8), 3, 0x100)?;
+    s2mm.attach_pool(&pool)?;
+    s2mm.set_mode(VdmaMode::Park)?;
+    s2mm.start()?;
+
+    // Frame 2 starts in buffer 0 before the park pointer moves to buffer 1,
+    // so buffer 0 is only published once frame 2 is in it.
+    model.push_frame(vec![1; 48]);
+    model.push_frame_back_to_back(vec![2; 48]);
+    assert_eq!(s2mm.capture_to_pool()?, 0);
+    assert_eq!(model.frame_count(), 2);
+    let frame = pool.acquire_read().unwrap();
+    assert_eq!(frame.index, 0);
+    assert_eq!(pool.read_frame(0)?, vec![2; 48]);
+    assert_eq!(pool.read_frame(1)?, vec![0; 48]);
+    Ok(())
+}
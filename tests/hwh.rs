@@ -0,0 +1,179 @@
+use std::io::Write;
+
+use anyhow::Result;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use xipdriver_rs::hwh::{self, DeviceMap};
+use xipdriver_rs::hwinfo::{self, HwInfo};
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufWrite};
+
+const HWH: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<EDKSYSTEM EDWVERSION="1.2" TIMESTAMP="Tue Jun 13 12:00:00 2023" VIVADOVERSION="2022.1">
+  <MODULES>
+    <MODULE COREREVISION="10" FULLNAME="/zynq_ultra_ps_e_0" HWVERSION="3.4" INSTANCE="zynq_ultra_ps_e_0" IPTYPE="PERIPHERAL" MODTYPE="zynq_ultra_ps_e" VLNV="xilinx.com:ip:zynq_ultra_ps_e:3.4">
+      <PARAMETERS>
+        <PARAMETER NAME="C_MAXIGP0_DATA_WIDTH" VALUE="128"/>
+      </PARAMETERS>
+      <MEMORYMAP>
+        <MEMRANGE ADDRESSBLOCK="Reg" BASENAME="C_BASEADDR" BASEVALUE="0xA0000000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA000FFFF" INSTANCE="lane_detection_v_frmbuf_wr_0" IS_DATA="TRUE" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="s_axi_CTRL"/>
+        <MEMRANGE ADDRESSBLOCK="S_AXI_LITE" BASENAME="C_BASEADDR" BASEVALUE="0xA0010000" HIGHNAME="C_HIGHADDR" HIGHVALUE="0xA001FFFF" INSTANCE="axi_dma_0" IS_DATA="TRUE" MEMTYPE="REGISTER" SLAVEBUSINTERFACE="S_AXI_LITE"/>
+      </MEMORYMAP>
+    </MODULE>
+    <MODULE COREREVISION="4" FULLNAME="/lane_detection/v_frmbuf_wr_0" HWVERSION="2.4" INSTANCE="lane_detection_v_frmbuf_wr_0" IPTYPE="PERIPHERAL" MODTYPE="v_frmbuf_wr" VLNV="xilinx.com:ip:v_frmbuf_wr:2.4">
+      <PARAMETERS>
+        <PARAMETER NAME="MAX_COLS" VALUE="64"/>
+        <PARAMETER NAME="MAX_ROWS" VALUE="8"/>
+        <PARAMETER NAME="HAS_RGB8" VALUE="1"/>
+        <PARAMETER NAME="HAS_YUYV8" VALUE="0"/>
+        <PARAMETER NAME="SAMPLES_PER_CLOCK" VALUE="1"/>
+      </PARAMETERS>
+    </MODULE>
+    <MODULE COREREVISION="28" FULLNAME="/axi_dma_0" HWVERSION="7.1" INSTANCE="axi_dma_0" IPTYPE="PERIPHERAL" MODTYPE="axi_dma" VLNV="xilinx.com:ip:axi_dma:7.1">
+      <PARAMETERS>
+        <PARAMETER NAME="C_INCLUDE_MM2S" VALUE="1"/>
+        <PARAMETER NAME="C_INCLUDE_S2MM" VALUE="0"/>
+        <PARAMETER NAME="C_BASEADDR" VALUE="0xA0010000"/>
+        <PARAMETER NAME="C_HIGHADDR" VALUE="0xA001FFFF"/>
+      </PARAMETERS>
+    </MODULE>
+    <MODULE FULLNAME="/axi_gpio_0" HWVERSION="2.0" INSTANCE="axi_gpio_0" MODTYPE="axi_gpio" VLNV="xilinx.com:ip:axi_gpio:2.0">
+      <PARAMETERS>
+        <PARAMETER NAME="C_BASEADDR" VALUE="0x80000000"/>
+        <PARAMETER NAME="C_HIGHADDR" VALUE="0x80000FFF"/>
+      </PARAMETERS>
+    </MODULE>
+  </MODULES>
+</EDKSYSTEM>
+"#;
+
+fn devices() -> DeviceMap {
+    let mut devices = DeviceMap::new();
+    devices.insert(
+        "/lane_detection/v_frmbuf_wr_0",
+        Some("v_frmbuf_wr"),
+        &["udmabuf0"],
+    );
+    devices
+}
+
+/// `hwh` deflated, everything else stored, the way Vivado writes them.
+/// `zip64` forces zip64 headers on every entry.
+fn write_xsa(path: &std::path::Path, entries: &[(&str, &[u8])], zip64: bool) -> Result<()> {
+    let mut writer = ZipWriter::new(std::fs::File::create(path)?);
+    for (name, contents) in entries {
+        let method = if name.ends_with(".hwh") {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        let options = SimpleFileOptions::default()
+            .compression_method(method)
+            .large_file(zip64);
+        writer.start_file(*name, options)?;
+        writer.write_all(contents)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[test]
+fn parse_hwh() -> Result<()> {
+    let hw = hwh::parse(HWH, &devices())?;
+    assert_eq!(hw.ips.len(), 4);
+
+    let vfb = &hw["/lane_detection/v_frmbuf_wr_0"];
+    assert_eq!(vfb.vlnv.to_string(), "xilinx.com:ip:v_frmbuf_wr:2.4");
+    assert_eq!(vfb.base_addr, Some(0xA000_0000));
+    assert_eq!(vfb.range, Some(0x10000));
+    assert_eq!(vfb.uio()?, "v_frmbuf_wr");
+    assert_eq!(vfb.udmabuf(0)?, "udmabuf0");
+    assert_eq!(vfb.param_u32("MAX_COLS")?, 64);
+    assert!(vfb.param_bool("HAS_RGB8")?);
+
+    let dma = &hw["/axi_dma_0"];
+    assert_eq!(dma.base_addr, Some(0xA001_0000));
+    assert!(dma.uio.is_none());
+    // No memory range in the processor's map: falls back to C_BASEADDR / C_HIGHADDR.
+    assert_eq!(hw["/axi_gpio_0"].base_addr, Some(0x8000_0000));
+    assert_eq!(hw["/axi_gpio_0"].range, Some(0x1000));
+    Ok(())
+}
+
+#[test]
+fn hwh_feeds_json_consumers() -> Result<()> {
+    let hw_json = hwh::parse(HWH, &devices())?.to_json();
+    let path = hwinfo::match_hw(&hw_json, "lane_detection", "v_frmbuf_wr")?;
+    assert_eq!(path, "/lane_detection/v_frmbuf_wr_0");
+    assert_eq!(HwInfo::from_json(&hw_json)?, hwh::parse(HWH, &devices())?);
+
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("v_frmbuf_wr", 0x10000);
+    backend.add_udmabuf("udmabuf0", 64 * 8 * 3);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(&hw_json[&path], &backend)?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
+    vfb_w.write_format()?;
+    assert_eq!(regs.read32(0x10), 64);
+    Ok(())
+}
+
+#[test]
+fn device_map_errors() -> Result<()> {
+    let devices = DeviceMap::from_json(&serde_json::json!({
+        "/lane_detection/v_frmbuf_rd_0": { "uio": "v_frmbuf_rd" },
+    }))?;
+    let err = format!("{:#}", hwh::parse(HWH, &devices).unwrap_err());
+    assert!(
+        err.contains("/lane_detection/v_frmbuf_rd_0 is not in the hardware description"),
+        "{}",
+        err
+    );
+
+    assert!(DeviceMap::from_json(&serde_json::json!({ "/axi_dma_0": { "udmabuf": 0 } })).is_err());
+    assert!(hwh::parse("<hwinfo/>", &DeviceMap::new()).is_err());
+    Ok(())
+}
+
+#[test]
+fn read_xsa() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("xipdriver-hwh-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let xsa = dir.join("design_1.xsa");
+    write_xsa(
+        &xsa,
+        &[("xsa.json", b"{}"), ("design_1.hwh", HWH.as_bytes())],
+        false,
+    )?;
+    let hw = hwh::read(xsa.to_str().unwrap(), &devices())?;
+    assert_eq!(hw, hwh::parse(HWH, &devices())?);
+
+    let two = dir.join("two.xsa");
+    write_xsa(
+        &two,
+        &[("a.hwh", HWH.as_bytes()), ("b.hwh", HWH.as_bytes())],
+        false,
+    )?;
+    assert!(hwh::read(two.to_str().unwrap(), &devices()).is_err());
+    let hw = hwh::read_xsa_entry(two.to_str().unwrap(), "b.hwh", &devices())?;
+    assert_eq!(hw.ips.len(), 4);
+
+    let err = hwh::read_xsa_entry(two.to_str().unwrap(), "c.hwh", &devices()).unwrap_err();
+    assert!(err.to_string().contains("c.hwh not found"), "{}", err);
+
+    let zip64 = dir.join("zip64.xsa");
+    write_xsa(&zip64, &[("design_1.hwh", HWH.as_bytes())], true)?;
+    assert_eq!(hwh::read(zip64.to_str().unwrap(), &devices())?, hw);
+
+    let garbage = dir.join("garbage.xsa");
+    std::fs::write(&garbage, b"not a zip")?;
+    assert!(hwh::read(garbage.to_str().unwrap(), &devices()).is_err());
+
+    let hwh_file = dir.join("design_1.hwh");
+    std::fs::write(&hwh_file, HWH)?;
+    assert_eq!(hwh::read(hwh_file.to_str().unwrap(), &devices())?, hw);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::hwinfo::{HwInfo, IpDescriptor, ParamValue, Vlnv};
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::umv_motor_controller::UmvMotorController;
+use xipdriver_rs::v_frmbuf::VideoFrameBufWrite;
+
+fn board_json() -> serde_json::Value {
+    json!({
+        "/lane_detection/v_frmbuf_wr": {
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "v_frmbuf_wr",
+            "version": "2.4",
+            "uio": "v_frmbuf_wr_0",
+            "udmabuf": ["udmabuf0"],
+            "params": {
+                "MAX_COLS": "1280",
+                "MAX_ROWS": 720,
+                "HAS_RGB8": "true",
+                "HAS_YUYV8": 0,
+                "SAMPLES_PER_CLOCK": "0x1",
+            },
+        },
+        "/umv_motor_controller_0": {
+            "vendor": "slab",
+            "library": "umv_project",
+            "name": "umv_motor_controller",
+            "uio": "umv_motor_controller_0",
+            "params": { "ACCEL_MAX": 1000, "FB_EDGE_PERIOD": "0.01" },
+        },
+    })
+}
+
+#[test]
+fn parse_typed_hwinfo() -> Result<()> {
+    let hw = HwInfo::from_json(&board_json())?;
+    let vfb = &hw["/lane_detection/v_frmbuf_wr"];
+    assert_eq!(vfb.path, "/lane_detection/v_frmbuf_wr");
+    assert_eq!(vfb.vlnv.to_string(), "xilinx.com:ip:v_frmbuf_wr:2.4");
+    assert_eq!(vfb.uio()?, "v_frmbuf_wr_0");
+    assert_eq!(vfb.udmabuf(0)?, "udmabuf0");
+    assert_eq!(vfb.param_u32("MAX_COLS")?, 1280);
+    assert_eq!(vfb.param_u32("SAMPLES_PER_CLOCK")?, 1);
+    assert!(vfb.param_bool("HAS_RGB8")?);
+    assert!(!vfb.param_bool("HAS_YUYV8")?);
+    assert_eq!(vfb.param("MAX_ROWS")?, &ParamValue::Int(720));
+    assert_eq!(hw.find("lane_detection", "v_frmbuf_wr")?.path, vfb.path);
+    assert!(hw.find("lane_detection", "v_frmbuf_rd").is_err());
+    assert_eq!(
+        Vlnv::parse("slab:umv_project:umv_motor_controller")?.name,
+        "umv_motor_controller"
+    );
+    Ok(())
+}
+
+#[test]
+fn descriptive_errors() -> Result<()> {
+    let hw = HwInfo::from_json(&board_json())?;
+    let motor = hw.get("/umv_motor_controller_0")?;
+    let err = motor.udmabuf(0).unwrap_err().to_string();
+    assert!(err.contains("/umv_motor_controller_0"), "{}", err);
+    let err = motor.param_u32("MAX_COLS").unwrap_err().to_string();
+    assert!(err.contains("MAX_COLS is missing"), "{}", err);
+    let err = motor.param_u32("FB_EDGE_PERIOD").unwrap_err().to_string();
+    assert!(err.contains("not an integer"), "{}", err);
+
+    let err = VideoFrameBufWrite::from_desc(motor)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        err.contains("slab:umv_project:umv_motor_controller"),
+        "{}",
+        err
+    );
+
+    let err = IpDescriptor::from_json(&json!({ "vendor": "xilinx.com", "name": "axi_gpio" }))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("\"library\" is missing"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn drivers_accept_descriptors() -> Result<()> {
+    let hw = HwInfo::from_json(&board_json())?;
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("umv_motor_controller_0", 0x1000);
+    let motor =
+        UmvMotorController::from_desc_with_backend(&hw["/umv_motor_controller_0"], &backend)?;
+    assert_eq!(motor.get_max_accel(), 1000);
+    motor.write_accel(10, -10)?;
+    assert_eq!(regs.read32(0x08), 10);
+    assert_eq!(regs.read32(0x04) as i32, -10);
+    assert!(motor.write_accel(1001, 0).is_err());
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axidma::AxiDma;
+use xipdriver_rs::axigpio::AxiGpio;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead};
+
+#[test]
+fn axidma_loopback() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_dma_0", 0x10000);
+    let tx = backend.add_udmabuf("udmabuf0", 0x1000);
+    let rx = backend.add_udmabuf("udmabuf1", 0x1000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_dma",
+        "uio": "axi_dma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1"],
+        "params": { "C_INCLUDE_MM2S": 1, "C_INCLUDE_S2MM": 1 },
+    });
+    let mut dma = AxiDma::with_backend(&hw_info, &backend)?;
+    dma.start();
+    assert_eq!(regs.read32(0x00), 1);
+    assert_eq!(regs.read32(0x30), 1);
+
+    dma.write(&[1u32, 2, 3, 4])?;
+    assert_eq!(regs.read32(0x18) as usize, tx.phys_addr());
+    assert_eq!(regs.read32(0x28), 16);
+    assert_eq!(tx.read32(0x0c), 4);
+
+    // Pretend the PL looped the stream back and went idle.
+    rx.write_bytes(0, &tx.read_bytes(0, 16));
+    regs.write32(0x34, 0x02);
+    let data: Vec<u32> = dma.read(4)?;
+    assert_eq!(data, vec![1, 2, 3, 4]);
+    assert_eq!(regs.read32(0x48) as usize, rx.phys_addr());
+    Ok(())
+}
+
+#[test]
+fn axigpio_channels() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_gpio_0", 0x1000);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_gpio",
+        "uio": "axi_gpio_0",
+    });
+    let gpio = AxiGpio::with_backend(&hw_info, &backend)?;
+    gpio.write_data(2, 0xA5)?;
+    gpio.write_tri(1, 0xFF)?;
+    assert_eq!(regs.read32(0x08), 0xA5);
+    assert_eq!(regs.read32(0x04), 0xFF);
+    assert!(gpio.read_data(3).is_err());
+    Ok(())
+}
+
+#[test]
+fn v_frmbuf_rd_programs_registers() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("v_frmbuf_rd_0", 0x10000);
+    let buf = backend.add_udmabuf("udmabuf0", 64 * 8 * 3);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_frmbuf_rd",
+        "uio": "v_frmbuf_rd_0",
+        "udmabuf": ["udmabuf0"],
+        "params": {
+            "MAX_COLS": 64,
+            "MAX_ROWS": 8,
+            "HAS_RGB8": 1,
+            "HAS_YUYV8": 0,
+            "SAMPLES_PER_CLOCK": 1,
+        },
+    });
+    let mut vfb_r = VideoFrameBufRead::with_backend(&hw_info, &backend)?;
+    assert!(vfb_r.set_format(ColorFormat::Yuyv8).is_err());
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    let frame = vec![0x5Au8; 64 * 8 * 3];
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(regs.read32(0x00), 0x81);
+    assert_eq!(regs.read32(0x10), 64);
+    assert_eq!(regs.read32(0x18), 8);
+    assert_eq!(regs.read32(0x20), 64 * 3);
+    assert_eq!(regs.read32(0x28), 20);
+    assert_eq!(regs.read32(0x30) as usize, buf.phys_addr());
+    assert_eq!(buf.read_bytes(0, 4), vec![0x5A; 4]);
+    Ok(())
+}
+
+#[test]
+fn missing_device_is_an_error() {
+    let backend = SimBackend::new();
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_gpio",
+        "uio": "axi_gpio_0",
+    });
+    assert!(AxiGpio::with_backend(&hw_info, &backend).is_err());
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use xipdriver_rs::ap_ctrl::ApCtrl;
+use xipdriver_rs::bird_eye_view::BirdEyeViewHW;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::{
+    BirdEyeViewModel, UmvLaneDetectorModel, VideoFrameBufReadModel, VideoFrameBufWriteModel,
+};
+use xipdriver_rs::umv_lane_detector::{LanePoint, UmvLaneDetector};
+use xipdriver_rs::v_frmbuf::{ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
+
+fn v_frmbuf_info(name: &str, uio: &str, udmabuf: &str) -> Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": name,
+        "uio": uio,
+        "udmabuf": [udmabuf],
+        "params": {
+            "MAX_COLS": 16,
+            "MAX_ROWS": 4,
+            "HAS_RGB8": 1,
+            "HAS_YUYV8": 1,
+            "SAMPLES_PER_CLOCK": 1,
+        },
+    })
+}
+
+#[test]
+fn v_frmbuf_wr_captures_synthetic_frames() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = VideoFrameBufWriteModel::new();
+    backend
+        .add_uio("v_frmbuf_wr_0", 0x10000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", "v_frmbuf_wr_0", "udmabuf0"),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
+    assert!(vfb_w.is_idle());
+
+    vfb_w.start()?;
+    assert_eq!(model.frame_count(), 1);
+    let frame = vfb_w.read_frame()?;
+    assert_eq!(frame.len(), 16 * 4 * 3);
+    assert_eq!(frame[0], 0);
+    assert_eq!(frame[16 * 3 + 5], 6);
+    // read_frame() restarts the core, which captures the next frame.
+    assert_eq!(model.frame_count(), 2);
+    assert_eq!(vfb_w.read_frame()?[0], 1);
+    Ok(())
+}
+
+#[test]
+fn v_frmbuf_rd_streams_written_frame() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = VideoFrameBufReadModel::new();
+    backend
+        .add_uio("v_frmbuf_rd_0", 0x10000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", "v_frmbuf_rd_0", "udmabuf0"),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::Yuyv8)?;
+    let frame: Vec<u8> = (0..16 * 4 * 2).map(|i| i as u8).collect();
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(model.last_frame(), Some(frame));
+    vfb_r.stop()?;
+    Ok(())
+}
+
+#[test]
+fn v_frmbuf_done_interrupt() -> Result<()> {
+    let backend = SimBackend::new();
+    backend
+        .add_uio("v_frmbuf_rd_0", 0x10000)
+        .set_model(VideoFrameBufReadModel::new());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", "v_frmbuf_rd_0", "udmabuf0"),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    vfb_r.start_once()?;
+    assert!(vfb_r.is_done());
+    // ap_done is clear-on-read.
+    assert!(!vfb_r.is_done());
+    vfb_r.wait_done_interrupt(Duration::from_secs(1))?;
+    Ok(())
+}
+
+#[test]
+fn umv_lane_detector_reports_points() -> Result<()> {
+    let backend = SimBackend::new();
+    let model = UmvLaneDetectorModel::new();
+    backend
+        .add_uio("umv_lane_detector_0", 0x1000)
+        .set_model(model.clone());
+    backend.add_udmabuf("udmabuf0", 4 * 2);
+    let hw_info = json!({
+        "vendor": "slab",
+        "library": "umv_project",
+        "name": "umv_lane_detector",
+        "uio": "umv_lane_detector_0",
+        "udmabuf": ["udmabuf0"],
+        "params": {
+            "IMAGE_WIDTH": 640,
+            "IMAGE_HEIGHT": 480,
+            "MAX_DETECT_LINES": 64,
+            "FILTER_TYPE_DEFAULT": 0,
+        },
+    });
+    let ld = UmvLaneDetector::with_backend(&hw_info, &backend)?;
+    model.set_points(vec![
+        LanePoint {
+            direction: 1,
+            x: 100,
+            y: 200,
+        },
+        LanePoint {
+            direction: 2,
+            x: 300,
+            y: 400,
+        },
+        LanePoint {
+            direction: 1,
+            x: 5,
+            y: 6,
+        },
+    ]);
+    ld.start()?;
+    assert!(ld.is_waiting());
+    assert!(ld.is_running());
+    let points = ld.read_data()?;
+    assert!(ld.is_done());
+    // The result buffer only holds two points.
+    assert_eq!(points.len(), 2);
+    assert_eq!(
+        (points[1].direction, points[1].x, points[1].y),
+        (2, 300, 400)
+    );
+    Ok(())
+}
+
+#[test]
+fn bird_eye_view_remaps_image() -> Result<()> {
+    let (w, h) = (1280, 720);
+    let backend = SimBackend::new();
+    backend
+        .add_uio("bird_eye_view_0", 0x1000)
+        .set_model(BirdEyeViewModel::new(w, h));
+    for name in ["udmabuf0", "udmabuf1", "udmabuf2"] {
+        backend.add_udmabuf(name, w * h * 4);
+    }
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "hls",
+        "name": "bird_eye_view",
+        "uio": "bird_eye_view_0",
+        "udmabuf": ["udmabuf0", "udmabuf1", "udmabuf2"],
+    });
+    let mut bev = BirdEyeViewHW::with_backend(&hw_info, &backend)?;
+    let img_in: Vec<u32> = (0..(w * h) as u32).collect();
+    // Mirror every row.
+    let img_map: Vec<u32> = (0..w * h)
+        .map(|i| ((i / w) * w + (w - 1 - i % w)) as u32)
+        .collect();
+    bev.write_img_in(&img_in)?;
+    bev.write_img_map(&img_map)?;
+    bev.set_img_in_addr()?;
+    bev.set_img_map_addr()?;
+    bev.set_img_out_addr()?;
+    bev.start_once()?;
+    assert!(bev.is_idle());
+    let img_out = bev.read_img_out()?;
+    assert_eq!(img_out[0], (w - 1) as u32);
+    assert_eq!(img_out[w + 1], (2 * w - 2) as u32);
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::hwinfo::HwInfo;
+use xipdriver_rs::sysfs::{Sysfs, UdmabufDevice};
+
+struct FakeSysfs {
+    root: PathBuf,
+}
+
+impl FakeSysfs {
+    fn new(test: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("xipdriver-sysfs-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        FakeSysfs { root }
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn add_uio(&self, number: usize, name: &str, addr: u64, size: u64) {
+        let dir = format!("class/uio/uio{}", number);
+        self.write(&format!("{}/name", dir), &format!("{}\n", name));
+        self.write(
+            &format!("{}/maps/map0/addr", dir),
+            &format!("0x{:016x}\n", addr),
+        );
+        self.write(
+            &format!("{}/maps/map0/size", dir),
+            &format!("0x{:016x}\n", size),
+        );
+    }
+
+    fn add_udmabuf(&self, name: &str, phys_addr: u64, size: u64) {
+        let dir = format!("class/u-dma-buf/{}", name);
+        self.write(
+            &format!("{}/phys_addr", dir),
+            &format!("0x{:016x}\n", phys_addr),
+        );
+        self.write(&format!("{}/size", dir), &format!("{}\n", size));
+    }
+
+    fn sysfs(&self) -> Sysfs {
+        Sysfs::with_root(&self.root)
+    }
+
+    fn path(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn ip(base_addr: Option<&str>, uio: Option<&str>, udmabuf: &[&str]) -> serde_json::Value {
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_gpio",
+        "base_addr": base_addr,
+        "uio": uio,
+        "udmabuf": udmabuf,
+    })
+}
+
+#[test]
+fn list_devices() -> Result<()> {
+    let fake = FakeSysfs::new("list");
+    fake.add_uio(10, "v_frmbuf_wr", 0xA001_0000, 0x10000);
+    fake.add_uio(2, "axi_gpio", 0xA000_0000, 0x1000);
+    // Interrupt-only node without a memory map.
+    fake.write("class/uio/uio3/name", "gpio-irq\n");
+    fake.add_udmabuf("udmabuf0", 0x7000_0000, 4194304);
+    let sysfs = fake.sysfs();
+
+    let uios = sysfs.uio_devices()?;
+    assert_eq!(uios.len(), 2);
+    assert_eq!(uios[0].node, "uio2");
+    assert_eq!(uios[0].name, "axi_gpio");
+    assert_eq!(uios[1].addr, 0xA001_0000);
+    assert_eq!(
+        sysfs.udmabuf_devices()?,
+        vec![UdmabufDevice {
+            name: "udmabuf0".into(),
+            phys_addr: 0x7000_0000,
+            size: 4194304,
+        }]
+    );
+    assert!(Sysfs::with_root(fake.path().join("empty"))
+        .uio_devices()?
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn resolve_by_base_address() -> Result<()> {
+    let fake = FakeSysfs::new("resolve");
+    fake.add_uio(0, "gpio", 0xA000_0000, 0x1000);
+    fake.add_uio(1, "gpio", 0xA000_1000, 0x1000);
+    fake.add_uio(2, "v_frmbuf_wr", 0xA001_0000, 0x10000);
+    fake.add_udmabuf("udmabuf0", 0x7000_0000, 0x100000);
+
+    let mut hw = HwInfo::from_json(&json!({
+        "/gpio_a": ip(Some("0xA0000000"), None, &[]),
+        "/gpio_b": ip(Some("0xA0001000"), Some("gpio"), &[]),
+        "/v_frmbuf_wr": ip(Some("0xA0010000"), None, &["udmabuf0"]),
+        "/ps": ip(Some("0xFF000000"), None, &[]),
+        "/no_addr": ip(None, None, &[]),
+    }))?;
+    hw.resolve_devices(&fake.sysfs())?;
+    // Names shared by several nodes resolve to the node itself.
+    assert_eq!(hw["/gpio_a"].uio.as_deref(), Some("uio0"));
+    assert_eq!(hw["/gpio_b"].uio.as_deref(), Some("uio1"));
+    assert_eq!(hw["/v_frmbuf_wr"].uio.as_deref(), Some("v_frmbuf_wr"));
+    assert_eq!(hw["/ps"].uio, None);
+    assert_eq!(hw["/no_addr"].uio, None);
+    Ok(())
+}
+
+#[test]
+fn report_mismatches() -> Result<()> {
+    let fake = FakeSysfs::new("mismatch");
+    fake.add_uio(0, "axi_gpio", 0xA000_0000, 0x1000);
+    fake.add_uio(1, "dup", 0xA000_1000, 0x1000);
+    fake.add_uio(2, "dup", 0xA000_2000, 0x1000);
+    fake.add_udmabuf("udmabuf0", 0x7000_0000, 0x100000);
+
+    let mut hw = HwInfo::from_json(&json!({
+        "/wrong_addr": ip(Some("0xA0008000"), Some("axi_gpio"), &[]),
+        "/missing_uio": ip(None, Some("axi_dma"), &[]),
+        "/ambiguous": ip(None, Some("dup"), &[]),
+        "/missing_buf": ip(None, None, &["udmabuf3"]),
+    }))?;
+    let err = hw.resolve_devices(&fake.sysfs()).unwrap_err().to_string();
+    assert!(err.contains("/wrong_addr: uio device \"axi_gpio\" (uio0 at 0xA0000000) does not map the IP's base address 0xA0008000"), "{}", err);
+    assert!(
+        err.contains("/missing_uio: uio device \"axi_dma\" not found"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("/ambiguous: uio device name \"dup\" is ambiguous"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("/missing_buf: udmabuf \"udmabuf3\" not found"),
+        "{}",
+        err
+    );
+    assert!(err.contains("(available: udmabuf0)"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn udmabuf_sync_controls() -> Result<()> {
+    let fake = FakeSysfs::new("sync");
+    fake.add_udmabuf("udmabuf0", 0x7000_0000, 0x100000);
+    let sync = fake.sysfs().udmabuf_sync("udmabuf0");
+    let dir = fake.path().join("class/u-dma-buf/udmabuf0");
+    let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap();
+
+    sync.sync_for_device(0x1000, 0x200)?;
+    assert_eq!(read("sync_offset"), "4096");
+    assert_eq!(read("sync_size"), "512");
+    assert_eq!(read("sync_direction"), "1");
+    assert_eq!(read("sync_for_device"), "1");
+
+    sync.sync_for_cpu(0, 64)?;
+    assert_eq!(read("sync_size"), "64");
+    assert_eq!(read("sync_direction"), "2");
+    assert_eq!(read("sync_for_cpu"), "1");
+
+    let err = fake
+        .sysfs()
+        .udmabuf_sync("udmabuf9")
+        .sync_for_cpu(0, 64)
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::Io {
+                operation: "write",
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    assert!(err.to_string().starts_with("cannot write "), "{}", err);
+    Ok(())
+}
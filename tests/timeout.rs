@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::axidma::AxiDma;
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::timeout::TimeoutError;
+use xipdriver_rs::umv_lane_detector::UmvLaneDetector;
+use xipdriver_rs::v_frmbuf::VideoFrameBufRead;
+use xipdriver_rs::vdma::AxiVdmaMM2S;
+
+const TIMEOUT: Duration = Duration::from_millis(10);
+
+fn timeout_of(err: XipError) -> TimeoutError {
+    match err {
+        XipError::Timeout(timeout) => timeout,
+        e => panic!("not a timeout: {}", e),
+    }
+}
+
+#[test]
+fn axidma_stop_and_wait() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_dma_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    backend.add_udmabuf("udmabuf1", 0x100);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_dma",
+        "uio": "axi_dma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1"],
+    });
+    let mut dma = AxiDma::with_backend(&hw_info, &backend)?;
+    dma.set_timeout(TIMEOUT);
+    let mm2s = dma.mm2s.as_mut().unwrap();
+
+    // Running and busy: neither halts nor finishes.
+    assert!(!mm2s.poll_halted());
+    assert!(!mm2s.poll()?);
+    let err = timeout_of(mm2s.wait().unwrap_err());
+    assert_eq!((err.operation, err.status), ("AxiDmaChannel::wait", 0x0000));
+    regs.write32(0x34, 0x0008);
+    let err = timeout_of(dma.stop().unwrap_err());
+    assert_eq!((err.operation, err.status), ("AxiDmaChannel::stop", 0x0000));
+
+    let mm2s = dma.mm2s.as_mut().unwrap();
+    regs.write32(0x04, 0x0002);
+    assert!(mm2s.poll()?);
+    mm2s.wait()?;
+    regs.write32(0x04, 0x0001);
+    regs.write32(0x34, 0x0001);
+    dma.stop()?;
+    Ok(())
+}
+
+#[test]
+fn vdma_start_stop_reset() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_vdma_0", 0x1000);
+    for i in 0..3 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x100);
+    }
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_vdma",
+        "uio": "axi_vdma_0",
+        "udmabuf": ["udmabuf0", "udmabuf1", "udmabuf2"],
+    });
+    let mut vdma = AxiVdmaMM2S::with_backend(&hw_info, &backend)?;
+    vdma.set_timeout(TIMEOUT);
+    vdma.frame_width = 8;
+    vdma.frame_height = 2;
+
+    regs.write32(0x04, 0x0001);
+    let err = timeout_of(vdma.start().unwrap_err());
+    assert_eq!((err.operation, err.status), ("AxiVdmaMM2S::start", 0x0001));
+    regs.write32(0x04, 0x0000);
+    vdma.start()?;
+
+    let err = timeout_of(vdma.stop().unwrap_err());
+    assert_eq!(err.status, 0x0000);
+    regs.write32(0x04, 0x0001);
+    vdma.stop()?;
+
+    // Nothing clears the reset bit.
+    let err = timeout_of(vdma.reset().unwrap_err());
+    assert_eq!(
+        (err.operation, err.status),
+        ("AxiVdmaMM2S::reset", 0x0001108C)
+    );
+    assert!(!vdma.poll_reset_done());
+    Ok(())
+}
+
+#[test]
+fn v_frmbuf_rd_stop() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("v_frmbuf_rd_0", 0x10000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    let hw_info = json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "v_frmbuf_rd",
+        "uio": "v_frmbuf_rd_0",
+        "udmabuf": ["udmabuf0"],
+        "params": { "MAX_COLS": 16, "MAX_ROWS": 4, "HAS_RGB8": 1, "HAS_YUYV8": 1, "SAMPLES_PER_CLOCK": 1 },
+    });
+    let mut vfb_r = VideoFrameBufRead::with_backend(&hw_info, &backend)?;
+    vfb_r.set_timeout(TIMEOUT);
+    assert_eq!(vfb_r.timeout(), TIMEOUT);
+    let err = timeout_of(vfb_r.stop().unwrap_err());
+    assert_eq!((err.operation, err.status), ("ap_ctrl: wait_idle", 0x00));
+    Ok(())
+}
+
+#[test]
+fn lane_detector_stop() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("umv_lane_detector_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    let hw_info = json!({
+        "vendor": "slab",
+        "library": "umv_project",
+        "name": "umv_lane_detector",
+        "uio": "umv_lane_detector_0",
+        "udmabuf": ["udmabuf0"],
+        "params": {
+            "IMAGE_WIDTH": 640,
+            "IMAGE_HEIGHT": 480,
+            "MAX_DETECT_LINES": 64,
+            "FILTER_TYPE_DEFAULT": 0,
+        },
+    });
+    let mut ld = UmvLaneDetector::with_backend(&hw_info, &backend)?;
+    ld.set_timeout(TIMEOUT);
+
+    regs.write32(0x00, 1);
+    assert!(!ld.poll_stopped());
+    let err = timeout_of(ld.read_data().unwrap_err());
+    assert_eq!((err.operation, err.status), ("UmvLaneDetector::stop", 1));
+    regs.write32(0x00, 3);
+    ld.stop()?;
+    Ok(())
+}
@@ -0,0 +1,496 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use image::{DynamicImage, Rgb, RgbImage};
+use serde_json::{json, Value};
+
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::frame_image::FrameImage;
+use xipdriver_rs::sim::SimBackend;
+use xipdriver_rs::sim_models::{VideoFrameBufReadModel, VideoFrameBufWriteModel};
+use xipdriver_rs::v_frmbuf::{CapturedFrame, ColorFormat, VideoFrameBufRead, VideoFrameBufWrite};
+
+fn v_frmbuf_info(name: &str, params: Value) -> Value {
+    let mut params_all = json!({
+        "MAX_COLS": 16,
+        "MAX_ROWS": 4,
+        "SAMPLES_PER_CLOCK": 1,
+    });
+    params_all
+        .as_object_mut()
+        .unwrap()
+        .extend(params.as_object().unwrap().clone());
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": name,
+        "uio": name,
+        "udmabuf": ["udmabuf0"],
+        "params": params_all,
+    })
+}
+
+#[test]
+fn color_format_names() -> Result<()> {
+    for fmt in ColorFormat::ALL {
+        assert_eq!(ColorFormat::parse(fmt.name())?, fmt);
+        assert_eq!(ColorFormat::from_id(fmt.id()), Some(fmt));
+    }
+    assert_eq!(ColorFormat::Yuyv8.id(), 12);
+    assert_eq!(ColorFormat::YUv8420.to_string(), "Y_UV8_420");
+    assert_eq!(ColorFormat::parse("YUYV")?, ColorFormat::Yuyv8);
+    assert!(ColorFormat::parse("NV12").is_err());
+    assert_eq!(ColorFormat::from_id(9), None);
+    Ok(())
+}
+
+#[test]
+fn line_bytes() {
+    let cases = [
+        (ColorFormat::Rgbx8, 5, 20),
+        (ColorFormat::Rgb8, 5, 15),
+        (ColorFormat::Yuyv8, 5, 12),
+        (ColorFormat::Rgb565, 5, 10),
+        (ColorFormat::Y8, 5, 5),
+        (ColorFormat::YUv8420, 5, 5),
+        (ColorFormat::Y10, 5, 8),
+        (ColorFormat::YUv12, 5, 9),
+        (ColorFormat::Y12, 5, 10),
+        (ColorFormat::Rgbx12, 5, 25),
+        (ColorFormat::Rgb16, 5, 30),
+        (ColorFormat::Y16, 5, 10),
+        (ColorFormat::YUV8, 5, 5),
+    ];
+    for (fmt, width, bytes) in cases {
+        assert_eq!(fmt.line_bytes(width), bytes, "{}", fmt);
+    }
+}
+
+#[test]
+fn formats_from_params() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("v_frmbuf_wr", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info(
+            "v_frmbuf_wr",
+            json!({ "HAS_Y8": 1, "HAS_Y_UV8_420": 1, "HAS_RGBX10": 1, "HAS_RGB8": 0 }),
+        ),
+        &backend,
+    )?;
+    assert_eq!(
+        vfb_w.supported_formats(),
+        [ColorFormat::Rgbx10, ColorFormat::YUv8420, ColorFormat::Y8]
+    );
+    assert_eq!(vfb_w.get_format()?, "Not Set");
+    let err = vfb_w.set_format(ColorFormat::Rgb8).unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+
+    vfb_w.frame_width = 10;
+    vfb_w.set_format(ColorFormat::YUv8420)?;
+    vfb_w.write_format()?;
+    assert_eq!(regs.read32(0x20), 16);
+    assert_eq!(regs.read32(0x28), 19);
+    vfb_w.set_format(ColorFormat::Rgbx10)?;
+    vfb_w.write_format()?;
+    assert_eq!(regs.read32(0x20), 40);
+    assert_eq!(vfb_w.format(), Some(ColorFormat::Rgbx10));
+    Ok(())
+}
+
+#[test]
+fn y8_round_trip() -> Result<()> {
+    let backend = SimBackend::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    backend
+        .add_uio("v_frmbuf_rd", 0x1000)
+        .set_model(rd_model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", json!({ "HAS_Y8": 1 })),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::Y8)?;
+    let frame: Vec<u8> = (0..16 * 4).collect();
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(rd_model.last_frame(), Some(frame));
+
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(wr_model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y8": 1 })),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::Y8)?;
+    wr_model.set_frame(vec![5; 16 * 4]);
+    vfb_w.start()?;
+    assert_eq!(vfb_w.read_frame()?, vec![5; 16 * 4]);
+    Ok(())
+}
+
+#[test]
+fn planes() {
+    assert_eq!(ColorFormat::Rgb8.planes(), 1);
+    assert_eq!(ColorFormat::YUv8420.planes(), 2);
+    assert_eq!(ColorFormat::YUV8.planes(), 3);
+    assert_eq!(ColorFormat::YUv8420.plane_height(0, 5), 5);
+    assert_eq!(ColorFormat::YUv8420.plane_height(1, 5), 3);
+    assert_eq!(ColorFormat::YUv8.plane_height(1, 5), 5);
+}
+
+#[test]
+fn semi_planar_in_two_buffers() -> Result<()> {
+    let backend = SimBackend::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    let regs = backend.add_uio("v_frmbuf_rd", 0x1000);
+    regs.set_model(rd_model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let chroma = backend.add_udmabuf("udmabuf1", 0x1000);
+    let mut info = v_frmbuf_info("v_frmbuf_rd", json!({ "HAS_Y_UV8_420": 1 }));
+    info["udmabuf"] = json!(["udmabuf0", "udmabuf1"]);
+    let mut vfb_r = VideoFrameBufRead::with_backend(&info, &backend)?;
+    vfb_r.set_format(ColorFormat::YUv8420)?;
+    assert_eq!(vfb_r.plane_size(0)?, 16 * 4);
+    assert_eq!(vfb_r.plane_size(1)?, 16 * 2);
+    assert_eq!(vfb_r.frame_size()?, 16 * 6);
+    let err = vfb_r.plane_size(2).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+
+    vfb_r.write_plane(1, &[7; 16 * 2])?;
+    assert_eq!(chroma.read_bytes(0, 4), [7; 4]);
+    let frame: Vec<u8> = (0..16 * 6).collect();
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(regs.read_addr(0x3C), chroma.phys_addr());
+    assert_eq!(rd_model.last_frame(), Some(frame));
+    Ok(())
+}
+
+#[test]
+fn semi_planar_in_one_buffer() -> Result<()> {
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    let regs = backend.add_uio("v_frmbuf_wr", 0x1000);
+    regs.set_model(wr_model.clone());
+    let luma = backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y_UV8": 1 })),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::YUv8)?;
+    assert_eq!(
+        vfb_w.plane_addrs()?,
+        [luma.phys_addr(), luma.phys_addr() + 16 * 4]
+    );
+    let frame: Vec<u8> = (0..16 * 8).map(|i| (i * 3) as u8).collect();
+    wr_model.set_frame(frame.clone());
+    vfb_w.start()?;
+    assert_eq!(regs.read_addr(0x3C), luma.phys_addr() + 16 * 4);
+    let planes = vfb_w.read_planes_in_place()?;
+    assert_eq!(planes, [&frame[..16 * 4], &frame[16 * 4..]]);
+    assert_eq!(vfb_w.read_frame()?, frame);
+    Ok(())
+}
+
+#[test]
+fn planar_buffer_too_small() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("v_frmbuf_rd", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x80);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", json!({ "HAS_Y_U_V8": 1 })),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::YUV8)?;
+    let err = vfb_r.frame_size().unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::BufferTooLarge {
+                size: 0xC0,
+                capacity: 0x80
+            }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn planar_v_plane_register() -> Result<()> {
+    // 0x48 is the field ID; the V plane goes to 0x74 (rd) and 0x5C (wr).
+    let frame: Vec<u8> = (0..16 * 12).map(|i| i as u8).collect();
+    let backend = SimBackend::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    let rd_regs = backend.add_uio("v_frmbuf_rd", 0x1000);
+    rd_regs.set_model(rd_model.clone());
+    let rd_buf = backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", json!({ "HAS_Y_U_V8": 1 })),
+        &backend,
+    )?;
+    vfb_r.set_format(ColorFormat::YUV8)?;
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(rd_regs.read_addr(0x74), rd_buf.phys_addr() + 16 * 8);
+    assert_eq!(rd_regs.read32(0x48), 0);
+    assert_eq!(rd_model.last_frame(), Some(frame.clone()));
+
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    let wr_regs = backend.add_uio("v_frmbuf_wr", 0x1000);
+    wr_regs.set_model(wr_model.clone());
+    let wr_buf = backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y_U_V8": 1 })),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::YUV8)?;
+    wr_model.set_frame(frame.clone());
+    vfb_w.start()?;
+    assert_eq!(wr_regs.read_addr(0x5C), wr_buf.phys_addr() + 16 * 8);
+    assert_eq!(wr_regs.read32(0x48), 0);
+    assert_eq!(vfb_w.read_frame()?, frame);
+    Ok(())
+}
+
+#[test]
+fn unaligned_width_round_trip() -> Result<()> {
+    // RGB8 lines of 5 pixels are 15 bytes, the IP strides them by 16.
+    let frame: Vec<u8> = (0..15 * 4).collect();
+    let backend = SimBackend::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    let regs = backend.add_uio("v_frmbuf_rd", 0x1000);
+    regs.set_model(rd_model.clone());
+    let buf = backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_r = VideoFrameBufRead::with_backend(
+        &v_frmbuf_info("v_frmbuf_rd", json!({ "HAS_RGB8": 1 })),
+        &backend,
+    )?;
+    vfb_r.frame_width = 5;
+    vfb_r.set_format(ColorFormat::Rgb8)?;
+    assert_eq!(vfb_r.stride()?, 16);
+    vfb_r.write_frame(&frame)?;
+    assert_eq!(regs.read32(0x20), 16);
+    assert_eq!(buf.read_bytes(16, 15), &frame[15..30]);
+    assert_eq!(rd_model.last_frame(), Some(frame.clone()));
+
+    // Lines 20 bytes apart in the user buffer.
+    let mut padded = vec![0xFF; 20 * 4];
+    for (y, line) in frame.chunks(15).enumerate() {
+        padded[y * 20..][..15].copy_from_slice(line);
+    }
+    vfb_r.write_frame_from(&padded, 20)?;
+    assert_eq!(rd_model.last_frame(), Some(frame.clone()));
+    let err = vfb_r.write_frame_from(&padded, 12).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    let err = vfb_r
+        .write_frame_from(&padded[..20 * 3 + 14], 20)
+        .unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    vfb_r.frame_height = 0;
+    let err = vfb_r.write_frame_from(&padded, 20).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(wr_model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_RGB8": 1 })),
+        &backend,
+    )?;
+    vfb_w.frame_width = 5;
+    vfb_w.set_format(ColorFormat::Rgb8)?;
+    wr_model.set_frame(frame.clone());
+    vfb_w.start()?;
+    assert_eq!(vfb_w.read_frame()?, frame);
+    assert_eq!(vfb_w.read_frame_in_place()?.len(), 16 * 4);
+    vfb_w.start()?;
+    let mut padded = vec![0xFF; 20 * 3 + 15];
+    vfb_w.read_frame_into(&mut padded, 20)?;
+    for (y, line) in frame.chunks(15).enumerate() {
+        assert_eq!(&padded[y * 20..][..15], line);
+    }
+    assert_eq!(padded[15..20], [0xFF; 5]);
+    Ok(())
+}
+
+#[test]
+fn capture_ring() -> Result<()> {
+    let backend = SimBackend::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    let regs = backend.add_uio("v_frmbuf_wr", 0x1000);
+    regs.set_model(wr_model.clone());
+    let bufs: Vec<_> = (0..3)
+        .map(|i| backend.add_udmabuf(&format!("udmabuf{}", i), 0x1000))
+        .collect();
+    let mut info = v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y8": 1 }));
+    info["udmabuf"] = json!(["udmabuf0", "udmabuf1", "udmabuf2"]);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(&info, &backend)?;
+    vfb_w.set_format(ColorFormat::Y8)?;
+    let err = vfb_w.start_streaming(4).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    vfb_w.start_streaming(3)?;
+    assert!(vfb_w.is_streaming());
+    let err = vfb_w.start().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    // Refused before the core is touched.
+    let ctrl = regs.read32(0x00);
+    let err = vfb_w.read_frame().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    let err = vfb_w.read_frame_into(&mut [0; 64], 16).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    assert_eq!(regs.read32(0x00), ctrl);
+
+    // The model writes frame n as a gradient starting at n.
+    let frame = vfb_w.dequeue()?;
+    assert_eq!(
+        frame,
+        CapturedFrame {
+            index: 0,
+            sequence: 0
+        }
+    );
+    assert_eq!(vfb_w.ring_planes(0)?[0][..3], [0, 1, 2]);
+    assert_eq!(regs.read_addr(0x30), bufs[1].phys_addr());
+    let frame = vfb_w.dequeue()?;
+    assert_eq!(
+        frame,
+        CapturedFrame {
+            index: 1,
+            sequence: 1
+        }
+    );
+    assert_eq!(vfb_w.ring_planes(1)?[0][..3], [1, 2, 3]);
+    vfb_w.queue(0)?;
+    assert_eq!(
+        vfb_w.dequeue()?,
+        CapturedFrame {
+            index: 2,
+            sequence: 2
+        }
+    );
+    assert_eq!(regs.read_addr(0x30), bufs[0].phys_addr());
+
+    // With every other buffer dequeued the core stalls after this frame.
+    assert_eq!(
+        vfb_w.dequeue()?,
+        CapturedFrame {
+            index: 0,
+            sequence: 3
+        }
+    );
+    assert_eq!(wr_model.frame_count(), 4);
+    let err = vfb_w.dequeue().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    vfb_w.queue(1)?;
+    assert_eq!(
+        vfb_w.dequeue()?,
+        CapturedFrame {
+            index: 1,
+            sequence: 4
+        }
+    );
+    assert_eq!(vfb_w.ring_planes(1)?[0][..3], [4, 5, 6]);
+    let err = vfb_w.queue(3).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+
+    vfb_w.stop_streaming()?;
+    assert!(!vfb_w.is_streaming());
+    assert!(!regs.irq_enabled());
+    let err = vfb_w.dequeue().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn image_conversions() -> Result<()> {
+    let backend = SimBackend::new();
+    let rd_model = VideoFrameBufReadModel::new();
+    let wr_model = VideoFrameBufWriteModel::new();
+    backend
+        .add_uio("v_frmbuf_rd", 0x1000)
+        .set_model(rd_model.clone());
+    backend
+        .add_uio("v_frmbuf_wr", 0x1000)
+        .set_model(wr_model.clone());
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let params = json!({ "HAS_YUYV8": 1, "HAS_BGR8": 1 });
+    let mut vfb_r =
+        VideoFrameBufRead::with_backend(&v_frmbuf_info("v_frmbuf_rd", params.clone()), &backend)?;
+    let mut vfb_w =
+        VideoFrameBufWrite::with_backend(&v_frmbuf_info("v_frmbuf_wr", params), &backend)?;
+    vfb_r.set_format(ColorFormat::Yuyv8)?;
+    vfb_w.set_format(ColorFormat::Yuyv8)?;
+
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 4, Rgb([255, 255, 255])));
+    vfb_r.write_image(&img)?;
+    let frame = rd_model.last_frame().unwrap();
+    assert_eq!(frame[..4], [235, 128, 235, 128]);
+
+    wr_model.set_frame(frame);
+    vfb_w.start()?;
+    let FrameImage::Rgb(read) = vfb_w.read_frame_as_image()? else {
+        panic!("YUYV is not converted to RGB");
+    };
+    assert_eq!(read, img.to_rgb8());
+
+    vfb_w.set_format(ColorFormat::Bgr8)?;
+    wr_model.set_frame([10, 20, 30].repeat(16 * 4));
+    vfb_w.start()?;
+    let read = vfb_w.read_frame_as_image()?.into_rgb8();
+    assert_eq!(read.get_pixel(15, 3).0, [30, 20, 10]);
+
+    let small = DynamicImage::ImageRgb8(RgbImage::new(8, 4));
+    let err = vfb_r.write_image(&small).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn dequeue_times_out() -> Result<()> {
+    let backend = SimBackend::new();
+    // No model: the core never completes a frame.
+    backend.add_uio("v_frmbuf_wr", 0x1000);
+    for i in 0..2 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x1000);
+    }
+    let mut info = v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y8": 1 }));
+    info["udmabuf"] = json!(["udmabuf0", "udmabuf1"]);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(&info, &backend)?;
+    vfb_w.set_format(ColorFormat::Y8)?;
+    vfb_w.start_streaming(2)?;
+    match vfb_w.dequeue() {
+        Err(XipError::Timeout(e)) => assert_eq!(e.operation, "VideoFrameBufWrite::dequeue"),
+        other => panic!("{:?}", other),
+    }
+    vfb_w.stop_streaming()?;
+    Ok(())
+}
+
+#[test]
+fn read_in_place_waits_for_idle() -> Result<()> {
+    let backend = SimBackend::new();
+    // No model: ap_idle never comes up.
+    let regs = backend.add_uio("v_frmbuf_wr", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x1000);
+    let mut vfb_w = VideoFrameBufWrite::with_backend(
+        &v_frmbuf_info("v_frmbuf_wr", json!({ "HAS_Y8": 1 })),
+        &backend,
+    )?;
+    vfb_w.set_format(ColorFormat::Y8)?;
+    vfb_w.set_timeout(Duration::from_millis(10));
+    match vfb_w.read_frame_in_place() {
+        Err(XipError::Timeout(e)) => assert_eq!(e.operation, "ap_ctrl: wait_idle"),
+        other => panic!("{:?}", other),
+    }
+    regs.set_model(VideoFrameBufWriteModel::new());
+    assert_eq!(vfb_w.read_frame_in_place()?.len(), 16 * 4);
+    Ok(())
+}
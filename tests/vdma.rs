@@ -0,0 +1,530 @@
+use anyhow::Result;
+use serde_json::json;
+
+use xipdriver_rs::error::XipError;
+use xipdriver_rs::sim::{SimBackend, SimSync};
+use xipdriver_rs::sim_models::{AxiVdmaMM2SModel, AxiVdmaS2MMModel};
+use xipdriver_rs::vdma::{
+    AxiVdmaMM2S, AxiVdmaS2MM, CommitReport, FsyncSource, GenlockMode, GenlockSource, VdmaConfig,
+    VdmaMode,
+};
+
+fn s2mm(backend: &SimBackend, stores: usize) -> Result<(AxiVdmaS2MMModel, AxiVdmaS2MM)> {
+    let model = AxiVdmaS2MMModel::new();
+    backend
+        .add_uio("axi_vdma_0", 0x1000)
+        .set_model(model.clone());
+    let udmabuf: Vec<String> = (0..stores).map(|i| format!("udmabuf{}", i)).collect();
+    for name in &udmabuf {
+        backend.add_udmabuf(name, 0x100);
+    }
+    let mut vdma = AxiVdmaS2MM::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_vdma",
+            "uio": "axi_vdma_0",
+            "udmabuf": udmabuf,
+            "params": { "C_NUM_FSTORES": stores },
+        }),
+        backend,
+    )?;
+    // 3 bytes of padding at the end of every line.
+    vdma.frame_width = 7;
+    vdma.frame_height = 2;
+    vdma.bytes_per_pix = 3;
+    Ok((model, vdma))
+}
+
+fn frame(n: u8) -> Vec<u8> {
+    (0..42).map(|i| i + n).collect()
+}
+
+#[test]
+fn circular_capture() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = s2mm(&backend, 3)?;
+    vdma.start()?;
+    let regs = backend.uio("axi_vdma_0").unwrap();
+    assert_eq!(regs.read32(0x48), 3);
+    assert_eq!(regs.read32(0xA4), 21);
+    assert_eq!(regs.read32(0xA8), 24);
+    assert_eq!(regs.read32(0xA0), 2);
+
+    for n in 0..4 {
+        model.push_frame(frame(n));
+        assert_eq!(vdma.next_frame()?, n as usize % 3);
+    }
+    model.push_frame(frame(9));
+    assert_eq!(vdma.read_frame()?, frame(9));
+    let row = backend.udmabuf("udmabuf1").unwrap().read_bytes(24, 21);
+    assert_eq!(row, frame(9)[21..]);
+
+    // No frame arrives: the wait gives up with VDMASR.
+    match vdma.next_frame() {
+        Err(XipError::Timeout(e)) => {
+            assert_eq!(e.operation, "AxiVdmaS2MM::wait_frame_count");
+            assert_eq!(e.status & 0x1000, 0);
+        }
+        other => panic!("{:?}", other),
+    }
+    vdma.stop()?;
+    assert!(!vdma.is_running());
+    Ok(())
+}
+
+#[test]
+fn park_capture() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = s2mm(&backend, 2)?;
+    vdma.set_mode(VdmaMode::Park)?;
+    vdma.set_park_frame(1)?;
+    vdma.start()?;
+    let regs = backend.uio("axi_vdma_0").unwrap();
+    assert_eq!(regs.read32(0x30) & 0x3, 0x1);
+
+    // The VDMA still reports store 1 once parked on 0, so next_frame waits
+    // for one more frame, which lands in store 0.
+    model.push_frame(frame(1));
+    model.push_frame(frame(2));
+    assert_eq!(vdma.next_frame()?, 1);
+    assert_eq!(regs.read32(0x28) >> 8 & 0x1F, 0);
+    assert_eq!(vdma.read_active_frame(), 0);
+    let store0 = backend.udmabuf("udmabuf0").unwrap();
+    assert_eq!(store0.read_bytes(24, 21), frame(2)[21..]);
+    model.push_frame(frame(3));
+    model.push_frame(frame(4));
+    assert_eq!(vdma.read_frame()?, frame(3));
+    assert_eq!(model.frame_count(), 4);
+
+    let err = vdma.set_park_frame(2).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn park_capture_waits_out_a_started_frame() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = s2mm(&backend, 2)?;
+    vdma.set_mode(VdmaMode::Park)?;
+    vdma.start()?;
+    // Frame 2 starts in store 0 before next_frame moves the park pointer.
+    model.push_frame(frame(1));
+    model.push_frame_back_to_back(frame(2));
+    assert_eq!(vdma.next_frame()?, 0);
+    assert_eq!(model.frame_count(), 2);
+    let store0 = backend.udmabuf("udmabuf0").unwrap();
+    assert_eq!(store0.read_bytes(24, 21), frame(2)[21..]);
+    // Nothing was written to the store the VDMA is parked on now.
+    let store1 = backend.udmabuf("udmabuf1").unwrap();
+    assert_eq!(store1.read_bytes(0, 21), vec![0; 21]);
+    Ok(())
+}
+
+#[test]
+fn read_frame_as_image() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = s2mm(&backend, 1)?;
+    vdma.start()?;
+    model.push_frame(frame(0));
+    let img = vdma.read_frame_as_image()?;
+    assert_eq!(img.dimensions(), (7, 2));
+    assert_eq!(img.get_pixel(1, 1).0, [24, 25, 26]);
+
+    vdma.bytes_per_pix = 4;
+    let err = vdma.read_frame_as_image().unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn s2mm_errors() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = s2mm(&backend, 2)?;
+    vdma.start()?;
+
+    // A short frame is a frame size error, which is cleared once reported.
+    model.push_frame(vec![0; 30]);
+    let err = vdma.next_frame().unwrap_err();
+    assert!(matches!(err, XipError::Format(_)), "{:?}", err);
+    assert_eq!(vdma.read_status() & 0x4080, 0);
+
+    model.inject_error(1 << 5);
+    let err = vdma.next_frame().unwrap_err();
+    assert!(matches!(err, XipError::DmaSlave { .. }), "{:?}", err);
+    vdma.reset()?;
+    assert!(!vdma.is_running());
+    Ok(())
+}
+
+#[test]
+fn s2mm_must_be_included() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("axi_vdma_0", 0x1000);
+    backend.add_udmabuf("udmabuf0", 0x100);
+    let err = AxiVdmaS2MM::with_backend(
+        &json!({
+            "vendor": "xilinx.com",
+            "library": "ip",
+            "name": "axi_vdma",
+            "uio": "axi_vdma_0",
+            "udmabuf": ["udmabuf0"],
+            "params": { "C_INCLUDE_S2MM": 0 },
+        }),
+        &backend,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(err, XipError::Unsupported(_)), "{:?}", err);
+    Ok(())
+}
+
+fn axi_vdma(udmabufs: usize, params: serde_json::Value) -> serde_json::Value {
+    let udmabuf: Vec<String> = (0..udmabufs).map(|i| format!("udmabuf{}", i)).collect();
+    json!({
+        "vendor": "xilinx.com",
+        "library": "ip",
+        "name": "axi_vdma",
+        "uio": "axi_vdma_0",
+        "udmabuf": udmabuf,
+        "params": params,
+    })
+}
+
+#[test]
+fn config_from_params() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_vdma_0", 0x1000);
+    for i in 0..4 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x10_0000);
+    }
+    let hw_info = axi_vdma(
+        4,
+        json!({
+            "C_NUM_FSTORES": 4,
+            "C_M_AXI_MM2S_DATA_WIDTH": 128,
+            "C_M_AXIS_MM2S_TDATA_WIDTH": 48,
+            "C_MM2S_GENLOCK_MODE": 3,
+        }),
+    );
+    let mut vdma = AxiVdmaMM2S::with_backend(&hw_info, &backend)?;
+    assert_eq!((vdma.bytes_per_pix, vdma.pix_per_clk), (6, 1));
+    assert_eq!(vdma.genlock_mode(), GenlockMode::DynamicSlave);
+    assert_eq!(vdma.frame_buffers(), 4);
+
+    // There is no default frame size.
+    let err = vdma.start().unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    vdma.frame_width = 5;
+    vdma.frame_height = 2;
+    vdma.start()?;
+    assert_eq!(regs.read32(0x18), 4);
+    // 30 bytes rounded up to the 128-bit memory map.
+    assert_eq!(regs.read32(0x54), 30);
+    assert_eq!(regs.read32(0x58), 32);
+    Ok(())
+}
+
+#[test]
+fn runtime_frame_stores() -> Result<()> {
+    let backend = SimBackend::new();
+    let (_, mut vdma) = s2mm(&backend, 3)?;
+    vdma.set_frame_buffers(2)?;
+    vdma.start()?;
+    let regs = backend.uio("axi_vdma_0").unwrap();
+    assert_eq!(regs.read32(0x48), 2);
+
+    let err = vdma.set_frame_buffers(1).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    vdma.stop()?;
+    let err = vdma.set_frame_buffers(4).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    vdma.set_frame_buffers(1)?;
+    Ok(())
+}
+
+#[test]
+fn frame_stores_need_udmabufs() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("axi_vdma_0", 0x1000);
+    for i in 0..2 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x100);
+    }
+    // C_NUM_FSTORES defaults to 3.
+    for params in [json!({}), json!({ "C_NUM_FSTORES": 17 })] {
+        let err = AxiVdmaMM2S::with_backend(&axi_vdma(2, params), &backend)
+            .err()
+            .unwrap();
+        assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+    }
+    let params = json!({ "C_NUM_FSTORES": 2, "C_S2MM_GENLOCK_MODE": 4 });
+    let err = AxiVdmaS2MM::with_backend(&axi_vdma(2, params), &backend)
+        .err()
+        .unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn wide_addresses_stay_in_the_mm2s_bank() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_vdma_0", 0x1000);
+    regs.set_model(AxiVdmaMM2SModel::new());
+    for i in 0..16 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x100);
+    }
+    // 16 LSB/MSB pairs from MM2S_START_ADDRESS1 would run into S2MM_VSIZE.
+    let params = json!({ "C_NUM_FSTORES": 16, "C_ADDR_WIDTH": 64 });
+    let err = AxiVdmaMM2S::with_backend(&axi_vdma(16, params.clone()), &backend)
+        .err()
+        .unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+    let err = AxiVdmaS2MM::with_backend(&axi_vdma(16, params), &backend)
+        .err()
+        .unwrap();
+    assert!(matches!(err, XipError::HwDescription(_)), "{:?}", err);
+
+    let params = json!({ "C_NUM_FSTORES": 8, "C_ADDR_WIDTH": 64 });
+    let mut vdma = AxiVdmaMM2S::with_backend(&axi_vdma(8, params), &backend)?;
+    vdma.frame_width = 8;
+    vdma.frame_height = 2;
+    vdma.start()?;
+    let last = backend.udmabuf("udmabuf7").unwrap().phys_addr() as u32;
+    assert_eq!((regs.read32(0x94), regs.read32(0x98)), (last, 0));
+    for offset in (0xA0..0xF0).step_by(4) {
+        assert_eq!(regs.read32(offset), 0, "S2MM register 0x{:02X}", offset);
+    }
+    Ok(())
+}
+
+#[test]
+fn s2mm_control_register() -> Result<()> {
+    let backend = SimBackend::new();
+    let (_, mut vdma) = s2mm(&backend, 3)?;
+    vdma.set_config(VdmaConfig {
+        genlock_source: GenlockSource::External,
+        fsync: FsyncSource::Tuser,
+        irq_frame_count: 4,
+        irq_delay_count: 2,
+        ..VdmaConfig::default()
+    })?;
+    vdma.start()?;
+    let regs = backend.uio("axi_vdma_0").unwrap();
+    assert_eq!(regs.read32(0x30), 0x0204_7043);
+
+    // Switching to park mode while running only clears Circular_Park.
+    vdma.set_mode(VdmaMode::Park)?;
+    assert_eq!(regs.read32(0x30), 0x0204_7041);
+    assert_eq!(vdma.config().mode, VdmaMode::Park);
+    Ok(())
+}
+
+#[test]
+fn mm2s_genlock_slave() -> Result<()> {
+    let backend = SimBackend::new();
+    let regs = backend.add_uio("axi_vdma_0", 0x1000);
+    for i in 0..3 {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x10_0000);
+    }
+    let params = json!({ "C_NUM_FSTORES": 3, "C_MM2S_GENLOCK_MODE": 3 });
+    let mut vdma = AxiVdmaMM2S::with_backend(&axi_vdma(3, params), &backend)?;
+    vdma.frame_width = 8;
+    vdma.frame_height = 2;
+    // Parked with genlock by default.
+    assert_eq!(vdma.config().mode, VdmaMode::Park);
+    vdma.set_park_frame(2)?;
+    assert_eq!(regs.read32(0x28) & 0x1F, 2);
+
+    vdma.set_config(VdmaConfig {
+        genlock: true,
+        genlock_master: 1,
+        frame_delay: 1,
+        ..VdmaConfig::default()
+    })?;
+    vdma.frame_width = 8;
+    vdma.start()?;
+    assert_eq!(regs.read32(0x00), 0x0001_118B);
+    assert_eq!(regs.read32(0x58), 1 << 24 | 24);
+
+    // No model behind MM2S, so halt it by hand.
+    regs.write32(0x04, 1);
+    vdma.stop()?;
+    assert_eq!(regs.read32(0x00), 0x0001_118A);
+    Ok(())
+}
+
+#[test]
+fn config_validation() -> Result<()> {
+    let backend = SimBackend::new();
+    let (_, mut vdma) = s2mm(&backend, 2)?;
+    let invalid = [
+        VdmaConfig {
+            irq_frame_count: 0,
+            ..VdmaConfig::default()
+        },
+        VdmaConfig {
+            genlock_master: 16,
+            ..VdmaConfig::default()
+        },
+        VdmaConfig {
+            frame_delay: 2,
+            ..VdmaConfig::default()
+        },
+    ];
+    for config in invalid {
+        let err = vdma.set_config(config).unwrap_err();
+        assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    }
+    let err = vdma.set_park_frame(2).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+
+    vdma.set_config(VdmaConfig {
+        frame_delay: 1,
+        ..VdmaConfig::default()
+    })?;
+    let err = vdma.set_frame_buffers(1).unwrap_err();
+    assert!(matches!(err, XipError::InvalidArgument(_)), "{:?}", err);
+    Ok(())
+}
+
+fn mm2s(backend: &SimBackend, stores: usize) -> Result<(AxiVdmaMM2SModel, AxiVdmaMM2S)> {
+    let model = AxiVdmaMM2SModel::new();
+    backend
+        .add_uio("axi_vdma_0", 0x1000)
+        .set_model(model.clone());
+    for i in 0..stores {
+        backend.add_udmabuf(&format!("udmabuf{}", i), 0x100);
+    }
+    let params = json!({ "C_NUM_FSTORES": stores });
+    let mut vdma = AxiVdmaMM2S::with_backend(&axi_vdma(stores, params), backend)?;
+    // Lines are a multiple of the 64-bit memory map, so frames are packed.
+    vdma.frame_width = 8;
+    vdma.frame_height = 2;
+    Ok((model, vdma))
+}
+
+fn fill(vdma: &mut AxiVdmaMM2S, index: usize, value: u8) {
+    let buf = vdma.frame_buffer_mut(index).unwrap();
+    buf.as_mut_slice::<u8>().fill(value);
+}
+
+#[test]
+fn mm2s_acquire_commit() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = mm2s(&backend, 3)?;
+    vdma.start()?;
+
+    let index = vdma.acquire()?.unwrap();
+    assert_eq!(index, 1);
+    fill(&mut vdma, index, 1);
+    assert_eq!(vdma.commit(index)?, CommitReport::default());
+    model.advance(1);
+    assert_eq!(vdma.acquire()?, Some(2));
+    assert_eq!(model.last_frame(), Some(vec![1; 48]));
+
+    // Committing twice in one frame period drops the first frame.
+    fill(&mut vdma, 2, 2);
+    vdma.commit(2)?;
+    assert_eq!(vdma.acquire()?, Some(0));
+    fill(&mut vdma, 0, 3);
+    let report = vdma.commit(0)?;
+    assert!(report.dropped);
+
+    // Nothing new for the second period.
+    for _ in 0..2 {
+        model.advance(1);
+        vdma.acquire()?;
+    }
+    let report = vdma.commit(1)?;
+    assert_eq!((report.dropped, report.repeated), (false, 1));
+    assert_eq!(model.shown(), vec![1, 0, 0]);
+    assert_eq!(model.last_frame(), Some(vec![3; 48]));
+    Ok(())
+}
+
+#[test]
+fn mm2s_double_buffering() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = mm2s(&backend, 2)?;
+    vdma.start()?;
+    vdma.commit(1)?;
+    // Store 0 is on display and store 1 is waiting for it.
+    assert_eq!(vdma.acquire()?, None);
+    model.advance(1);
+    assert_eq!(vdma.acquire()?, Some(0));
+
+    vdma.set_mode(VdmaMode::Circular)?;
+    let err = vdma.commit(0).unwrap_err();
+    assert!(matches!(err, XipError::InvalidState(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn mm2s_frames_must_fit() -> Result<()> {
+    let backend = SimBackend::new();
+    let (_, mut vdma) = mm2s(&backend, 2)?;
+    vdma.frame_height = 16;
+    let err = vdma.start().unwrap_err();
+    assert!(
+        matches!(
+            err,
+            XipError::BufferTooLarge {
+                size: 0x180,
+                capacity: 0x100
+            }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn mm2s_commit_syncs_padded_lines() -> Result<()> {
+    let backend = SimBackend::new();
+    backend.add_uio("axi_vdma_0", 0x1000);
+    let bufs: Vec<_> = (0..2)
+        .map(|i| backend.add_udmabuf(&format!("udmabuf{}", i), 0x100))
+        .collect();
+    let mut info = axi_vdma(2, json!({ "C_NUM_FSTORES": 2 }));
+    info["udmabuf_cached"] = json!(true);
+    let mut vdma = AxiVdmaMM2S::with_backend(&info, &backend)?;
+    // 21-byte lines are strided by 24.
+    vdma.frame_width = 7;
+    vdma.frame_height = 2;
+    vdma.start()?;
+    vdma.commit(1)?;
+    assert_eq!(bufs[1].take_syncs(), vec![SimSync::ForDevice(0, 48)]);
+    Ok(())
+}
+
+#[test]
+fn mm2s_write_frame_waits_for_a_period() -> Result<()> {
+    let backend = SimBackend::new();
+    let (model, mut vdma) = mm2s(&backend, 3)?;
+    vdma.start()?;
+    // No frame period passes: the wait gives up with VDMASR.
+    match vdma.write_frame_with(|_| {}) {
+        Err(XipError::Timeout(e)) => assert_eq!(e.operation, "AxiVdmaMM2S::wait_frame"),
+        other => panic!("{:?}", other),
+    }
+
+    model.advance(1);
+    vdma.write_frame(&[7; 48])?;
+    model.advance(1);
+    assert_eq!(vdma.read_active_frame(), 1);
+    assert_eq!(model.last_frame(), Some(vec![7; 48]));
+    assert_eq!(model.shown(), vec![0, 1]);
+
+    // 21-byte lines are strided by 24.
+    let backend = SimBackend::new();
+    let (model, mut vdma) = mm2s(&backend, 3)?;
+    vdma.frame_width = 7;
+    vdma.start()?;
+    model.advance(1);
+    vdma.write_frame(&frame(0))?;
+    model.advance(1);
+    assert_eq!(vdma.read_active_frame(), 1);
+    assert_eq!(model.last_frame(), Some(frame(0)));
+    Ok(())
+}